@@ -1,3 +1,7 @@
+use std::time::Duration;
+
+use martin_tile_utils::TileCoord;
+
 #[cfg(feature = "unstable-cog")]
 use super::cog::CogError;
 #[cfg(feature = "mbtiles")]
@@ -31,6 +35,25 @@ pub enum MartinCoreError {
     #[error(transparent)]
     CogError(#[from] CogError),
 
+    /// The requested tile or resource does not exist.
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// No tile source with the given ID is configured.
+    ///
+    /// Unlike [`MartinCoreError::NotFound`], this means the whole source is unknown,
+    /// not just a single tile within an existing source.
+    #[error("Source {0} does not exist")]
+    SourceNotFound(String),
+
+    /// The tile coordinate is outside of what the source can serve.
+    #[error("Invalid tile coordinate {0:#}")]
+    InvalidCoordinate(TileCoord),
+
+    /// An operation did not complete in time.
+    #[error("Timed out after {0:?} while {1}")]
+    Timeout(Duration, String),
+
     /// Errors occurring from other sources, not implemented by `martin-core`.
     #[error(transparent)]
     OtherError(#[from] Box<dyn std::error::Error + Send + Sync>),
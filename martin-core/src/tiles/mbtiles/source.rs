@@ -4,7 +4,7 @@ use std::fmt::{Debug, Formatter};
 use std::io;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use backon::{FibonacciBuilder, Retryable as _};
@@ -12,11 +12,13 @@ use martin_tile_utils::{TileCoord, TileData, TileInfo};
 use mbtiles::sqlx::error::DatabaseError;
 use mbtiles::{MbtError, MbtilesPool};
 use tilejson::TileJSON;
-use tracing::{trace, warn};
+use tracing::field::Empty;
+use tracing::{Instrument as _, debug_span, trace, warn};
 
 use crate::CacheZoomRange;
 use crate::tiles::mbtiles::MbtilesError;
-use crate::tiles::{BoxedSource, MartinCoreResult, Source, UrlQuery};
+use crate::tiles::tile::layer_name;
+use crate::tiles::{BoxedSource, EmptyTileBehavior, MartinCoreResult, Source, UrlQuery};
 
 /// Tile source that reads from `MBTiles` files.
 #[derive(Clone)]
@@ -26,6 +28,7 @@ pub struct MbtSource {
     tilejson: TileJSON,
     tile_info: TileInfo,
     cache_zoom: CacheZoomRange,
+    empty_tile: EmptyTileBehavior,
 }
 
 #[expect(clippy::missing_fields_in_debug)]
@@ -97,8 +100,34 @@ impl MbtSource {
             tilejson: meta.tilejson,
             tile_info,
             cache_zoom,
+            empty_tile: EmptyTileBehavior::None,
         })
     }
+
+    /// Sets what to return for tiles missing from the file. Nothing by default.
+    #[must_use]
+    pub fn with_empty_tile(mut self, empty_tile: EmptyTileBehavior) -> Self {
+        self.empty_tile = empty_tile;
+        self
+    }
+
+    /// Reads the tile from the file, returning an empty one if it does not exist.
+    async fn fetch_tile(&self, xyz: TileCoord) -> MartinCoreResult<TileData> {
+        if let Some(tile) = self
+            .mbtiles
+            .get_tile(xyz.z, xyz.x, xyz.y)
+            .await
+            .map_err(|_| MbtilesError::AcquireConnError(self.id.clone()))?
+        {
+            Ok(tile)
+        } else {
+            trace!(
+                "Couldn't find tile data in {}/{}/{} of {}",
+                xyz.z, xyz.x, xyz.y, &self.id
+            );
+            Ok(Vec::new())
+        }
+    }
 }
 
 #[async_trait]
@@ -132,24 +161,33 @@ impl Source for MbtSource {
         self.cache_zoom
     }
 
+    /// Fetches the tile within a `get_tile` tracing span, which records the tile size in bytes
+    /// and the elapsed time in milliseconds once the tile is fetched.
     async fn get_tile(
         &self,
         xyz: TileCoord,
         _url_query: Option<&UrlQuery>,
     ) -> MartinCoreResult<TileData> {
-        if let Some(tile) = self
-            .mbtiles
-            .get_tile(xyz.z, xyz.x, xyz.y)
-            .await
-            .map_err(|_| MbtilesError::AcquireConnError(self.id.clone()))?
+        let span = debug_span!(
+            "get_tile",
+            source = %self.id,
+            z = xyz.z,
+            x = xyz.x,
+            y = xyz.y,
+            bytes = Empty,
+            elapsed_ms = Empty,
+        );
+        let start = Instant::now();
+        let mut tile = self.fetch_tile(xyz).instrument(span.clone()).await?;
+        if tile.is_empty()
+            && let Some(empty) = self
+                .empty_tile
+                .empty_tile(layer_name(&self.tilejson, &self.id), self.tile_info)
         {
-            Ok(tile)
-        } else {
-            trace!(
-                "Couldn't find tile data in {}/{}/{} of {}",
-                xyz.z, xyz.x, xyz.y, &self.id
-            );
-            Ok(Vec::new())
+            tile = empty;
         }
+        span.record("bytes", tile.len());
+        span.record("elapsed_ms", start.elapsed().as_secs_f64() * 1000.0);
+        Ok(tile)
     }
 }
@@ -35,7 +35,7 @@ mod error;
 pub use error::{MartinCoreError, MartinCoreResult};
 
 mod tile;
-pub use tile::Tile;
+pub use tile::{EmptyTileBehavior, Tile};
 
 mod cache;
 pub use cache::{NO_TILE_CACHE, OptTileCache, TileCache};
@@ -0,0 +1,311 @@
+//! Programmatic discovery of `PostgreSQL` tables and functions that can be published as tile sources.
+
+use serde::Serialize;
+use tracing::debug;
+
+use crate::tiles::postgres::PostgresError::PostgresError;
+use crate::tiles::postgres::{PostgresPool, PostgresResult};
+
+/// A table (or view) with a geometry or geography column that can be published as a tile source.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TableSourceInfo {
+    /// Table schema
+    pub schema: String,
+    /// Table name
+    pub table: String,
+    /// Geometry column name
+    pub geometry_column: String,
+    /// Geometry SRID, `0` if unknown
+    pub srid: i32,
+    /// Geometry type, e.g. `POINT` or `GEOMETRY`
+    pub geometry_type: String,
+    /// Minimum zoom level hint from the `minzoom` key of a `TileJSON` table comment
+    pub minzoom: Option<u8>,
+    /// Maximum zoom level hint from the `maxzoom` key of a `TileJSON` table comment
+    pub maxzoom: Option<u8>,
+}
+
+/// A function with `z`, `x`, `y` parameters that may be published as a tile source.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FunctionSourceInfo {
+    /// Function schema
+    pub schema: String,
+    /// Function name
+    pub function: String,
+    /// Names of the input parameters
+    pub input_names: Vec<String>,
+    /// Types of the input parameters, e.g. `integer` or `json`
+    pub input_types: Vec<String>,
+    /// Return type, either a single type like `bytea` or a record like `record(bytea, text)`
+    pub output_type: String,
+    /// The function accepts a 4th `json` or `jsonb` parameter with the URL query parameters
+    pub accepts_url_query: bool,
+    /// Why the function cannot be used as a tile source, `None` if it can
+    pub signature_error: Option<String>,
+}
+
+impl FunctionSourceInfo {
+    /// Signature of the function, e.g. `public.fn(integer, integer, integer) -> bytea`
+    #[must_use]
+    pub fn signature(&self) -> String {
+        format!(
+            "{}.{}({}) -> {}",
+            self.schema,
+            self.function,
+            self.input_types.join(", "),
+            self.output_type
+        )
+    }
+}
+
+const DISCOVER_TABLES_SQL: &str = r"
+WITH geo_columns AS (
+    SELECT f_table_schema AS schema, f_table_name AS name, f_geometry_column AS geom, srid, type
+    FROM geometry_columns
+    UNION
+    SELECT f_table_schema, f_table_name, f_geography_column, srid, type
+    FROM geography_columns
+)
+SELECT
+    gc.schema,
+    gc.name,
+    gc.geom,
+    gc.srid,
+    gc.type,
+    has_table_privilege(cls.oid, 'SELECT') AS can_select,
+    obj_description(cls.oid, 'pg_class') AS description
+FROM geo_columns AS gc
+INNER JOIN pg_catalog.pg_namespace AS ns ON gc.schema = ns.nspname
+INNER JOIN pg_catalog.pg_class AS cls ON ns.oid = cls.relnamespace AND gc.name = cls.relname
+WHERE cardinality($1::text[]) = 0 OR gc.schema = ANY($1::text[])
+ORDER BY gc.schema, gc.name, gc.geom";
+
+const DISCOVER_FUNCTIONS_SQL: &str = r"
+SELECT
+    ns.nspname AS schema,
+    p.proname AS name,
+    coalesce(p.proargnames[1:p.pronargs], '{}'::text[]) AS input_names,
+    (
+        SELECT coalesce(array_agg(format_type(a.t, NULL) ORDER BY a.i), '{}'::text[])
+        FROM unnest(p.proargtypes) WITH ORDINALITY AS a (t, i)
+    ) AS input_types,
+    format_type(p.prorettype, NULL) AS output_type,
+    (
+        SELECT array_agg(format_type(a.t, NULL) ORDER BY a.i)
+        FROM unnest(p.proallargtypes) WITH ORDINALITY AS a (t, i)
+        WHERE p.proargmodes[a.i] IN ('o', 't')
+    ) AS output_record_types
+FROM pg_catalog.pg_proc AS p
+INNER JOIN pg_catalog.pg_namespace AS ns ON p.pronamespace = ns.oid
+WHERE
+    p.prokind = 'f'
+    AND p.pronargs >= 3
+    AND lower(p.proargnames[1]) IN ('z', 'zoom')
+    AND lower(p.proargnames[2]) = 'x'
+    AND lower(p.proargnames[3]) = 'y'
+    AND has_function_privilege(p.oid, 'EXECUTE')
+    AND ns.nspname NOT IN ('pg_catalog', 'information_schema')
+    AND (cardinality($1::text[]) = 0 OR ns.nspname = ANY($1::text[]))
+ORDER BY ns.nspname, p.proname";
+
+impl PostgresPool {
+    /// Lists tables, views and materialized views with geometry or geography columns in the given schemas.
+    ///
+    /// Searches all schemas if `schemas` is empty.
+    /// Tables the connecting role is not allowed to `SELECT` from are skipped.
+    /// Zoom hints are taken from a `TileJSON` comment on the table, if present.
+    pub async fn discover_table_sources(
+        &self,
+        schemas: &[&str],
+    ) -> PostgresResult<Vec<TableSourceInfo>> {
+        let rows = self
+            .get()
+            .await?
+            .query(DISCOVER_TABLES_SQL, &[&schemas])
+            .await
+            .map_err(|e| PostgresError(e, "discovering table sources"))?;
+
+        let mut res = Vec::with_capacity(rows.len());
+        for row in rows {
+            let info = TableSourceInfo {
+                schema: row.get("schema"),
+                table: row.get("name"),
+                geometry_column: row.get("geom"),
+                srid: row.get("srid"),
+                geometry_type: row.get("type"),
+                minzoom: None,
+                maxzoom: None,
+            };
+            if !row.get::<_, bool>("can_select") {
+                debug!(
+                    "Skipping table {}.{} because the current role cannot SELECT from it",
+                    info.schema, info.table
+                );
+                continue;
+            }
+            let description: Option<&str> = row.get("description");
+            let (minzoom, maxzoom) = description.map_or((None, None), zoom_hints);
+            res.push(TableSourceInfo {
+                minzoom,
+                maxzoom,
+                ..info
+            });
+        }
+        Ok(res)
+    }
+
+    /// Lists functions with `z` (or `zoom`), `x`, `y` parameters in the given schemas,
+    /// checking if their signature matches a tile function:
+    /// `(z integer, x integer, y integer [, query json|jsonb]) -> bytea`,
+    /// optionally returning a `(bytea)` or `(bytea, text)` record.
+    ///
+    /// Searches all schemas if `schemas` is empty.
+    /// Functions the connecting role is not allowed to `EXECUTE` are skipped.
+    /// Functions that look like tile functions but have a wrong signature are returned
+    /// with a [`signature_error`](FunctionSourceInfo::signature_error) explaining the problem.
+    pub async fn discover_function_sources(
+        &self,
+        schemas: &[&str],
+    ) -> PostgresResult<Vec<FunctionSourceInfo>> {
+        let rows = self
+            .get()
+            .await?
+            .query(DISCOVER_FUNCTIONS_SQL, &[&schemas])
+            .await
+            .map_err(|e| PostgresError(e, "discovering function sources"))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let input_types: Vec<String> = row.get("input_types");
+                let output_type: String = row.get("output_type");
+                let output_record_types: Option<Vec<String>> = row.get("output_record_types");
+                let signature_error = check_function_signature(
+                    &input_types,
+                    &output_type,
+                    output_record_types.as_deref(),
+                )
+                .err();
+                let output_type = match output_record_types {
+                    Some(types) => format!("{output_type}({})", types.join(", ")),
+                    None => output_type,
+                };
+                FunctionSourceInfo {
+                    schema: row.get("schema"),
+                    function: row.get("name"),
+                    input_names: row.get("input_names"),
+                    accepts_url_query: input_types.len() == 4 && signature_error.is_none(),
+                    input_types,
+                    output_type,
+                    signature_error,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Checks that the types of a function with `z`, `x`, `y` parameters match what martin can call.
+fn check_function_signature(
+    input_types: &[String],
+    output_type: &str,
+    output_record_types: Option<&[String]>,
+) -> Result<(), String> {
+    if !(3..=4).contains(&input_types.len()) {
+        return Err(format!(
+            "expected 3 or 4 parameters, but the function has {}",
+            input_types.len()
+        ));
+    }
+    if let Some((idx, typ)) = input_types[..3]
+        .iter()
+        .enumerate()
+        .find(|(_, typ)| *typ != "integer")
+    {
+        return Err(format!(
+            "parameter {} must be an integer, but it is {typ}",
+            idx + 1
+        ));
+    }
+    if let Some(typ) = input_types.get(3)
+        && typ != "json"
+        && typ != "jsonb"
+    {
+        return Err(format!(
+            "the 4th parameter must be json or jsonb to receive the URL query, but it is {typ}"
+        ));
+    }
+    match (output_type, output_record_types) {
+        ("bytea", None) => Ok(()),
+        ("bytea" | "record", Some([data])) if data == "bytea" => Ok(()),
+        ("record", Some([data, hash])) if data == "bytea" && hash == "text" => Ok(()),
+        (_, Some(types)) => Err(format!(
+            "expected to return bytea or a (bytea) or (bytea, text) record, but it returns ({})",
+            types.join(", ")
+        )),
+        (typ, None) => Err(format!(
+            "expected to return bytea or a (bytea) or (bytea, text) record, but it returns {typ}"
+        )),
+    }
+}
+
+/// Extracts `minzoom` and `maxzoom` from a `TileJSON` table comment, ignoring any other comments.
+fn zoom_hints(description: &str) -> (Option<u8>, Option<u8>) {
+    let Ok(serde_json::Value::Object(tilejson)) = serde_json::from_str(description) else {
+        return (None, None);
+    };
+    let zoom = |key: &str| {
+        tilejson
+            .get(key)
+            .and_then(serde_json::Value::as_u64)
+            .and_then(|v| u8::try_from(v).ok())
+    };
+    (zoom("minzoom"), zoom("maxzoom"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(v: &[&str]) -> Vec<String> {
+        v.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn function_signature() {
+        let zxy = types(&["integer", "integer", "integer"]);
+        assert_eq!(check_function_signature(&zxy, "bytea", None), Ok(()));
+        let zxyq = types(&["integer", "integer", "integer", "jsonb"]);
+        assert_eq!(check_function_signature(&zxyq, "bytea", None), Ok(()));
+        let rec = types(&["bytea", "text"]);
+        assert_eq!(check_function_signature(&zxy, "record", Some(&rec)), Ok(()));
+
+        let err = check_function_signature(&types(&["integer", "text", "integer"]), "bytea", None);
+        assert_eq!(
+            err.unwrap_err(),
+            "parameter 2 must be an integer, but it is text"
+        );
+        let err = check_function_signature(
+            &types(&["integer", "integer", "integer", "text"]),
+            "bytea",
+            None,
+        );
+        assert!(
+            err.unwrap_err()
+                .starts_with("the 4th parameter must be json")
+        );
+        let err = check_function_signature(&zxy, "text", None);
+        assert!(err.unwrap_err().ends_with("but it returns text"));
+        let err = check_function_signature(&zxy, "record", Some(&types(&["text", "bytea"])));
+        assert!(err.unwrap_err().ends_with("but it returns (text, bytea)"));
+    }
+
+    #[test]
+    fn table_comment_zoom_hints() {
+        assert_eq!(
+            zoom_hints(r#"{"minzoom": 2, "maxzoom": 14}"#),
+            (Some(2), Some(14))
+        );
+        assert_eq!(zoom_hints(r#"{"maxzoom": 300}"#), (None, None));
+        assert_eq!(zoom_hints("a plain comment"), (None, None));
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use deadpool_postgres::tokio_postgres::Error as TokioPostgresError;
 use deadpool_postgres::tokio_postgres::config::SslMode;
@@ -10,6 +11,7 @@ use martin_tile_utils::TileCoord;
 use semver::Version;
 
 use crate::tiles::UrlQuery;
+use crate::tiles::postgres::UrlQueryParamType;
 use crate::tiles::postgres::utils::query_to_json;
 
 /// Result type for `PostgreSQL` operations.
@@ -31,10 +33,14 @@ pub enum PostgresError {
     #[error("Cannot parse certificate file {1}: {0}")]
     CannotParseCert(#[source] io::Error, PathBuf),
 
-    /// Invalid PEM RSA private key file.
-    #[error("Unable to parse PEM RSA key file {0}")]
+    /// No PKCS#1, PKCS#8 or SEC1 private key found in a PEM file.
+    #[error("Unable to find a PEM private key in file {0}")]
     InvalidPrivateKey(PathBuf),
 
+    /// Invalid TLS client configuration.
+    #[error("Invalid Postgres TLS configuration: {0}")]
+    TlsConfig(String),
+
     /// Cannot use client certificate pair.
     #[error("Unable to use client certificate pair {1} / {2}: {0}")]
     CannotUseClientKey(#[source] rustls::Error, PathBuf, PathBuf),
@@ -59,6 +65,10 @@ pub enum PostgresError {
     #[error("Unable to get a Postgres connection from the pool {1}: {0}")]
     PostgresPoolConnError(#[source] PoolError, String),
 
+    /// Timed out waiting for a connection from the `PostgreSQL` pool.
+    #[error("Timed out after {0:?} waiting for a connection from the Postgres pool {1}")]
+    PoolTimeout(Duration, String),
+
     /// Invalid `PostgreSQL` connection string.
     #[error("Unable to parse connection string {1}: {0}")]
     BadConnectionString(#[source] TokioPostgresError, String),
@@ -83,6 +93,24 @@ pub enum PostgresError {
     #[error("Invalid extent setting in source {0} for table {1}: extent=0")]
     InvalidTableExtent(String, String),
 
+    /// Invalid table simplification tolerance.
+    #[error(
+        "Invalid simplify setting in source {0} for table {1}: tolerance={2} must be a non-negative number"
+    )]
+    InvalidTableSimplify(String, String, f64),
+
+    /// Invalid table filter configuration.
+    #[error(
+        "Invalid filter in source {1}, expecting a boolean SQL expression like `population > 1000`, but got `{2}`: {0}"
+    )]
+    InvalidTableFilter(#[source] TokioPostgresError, String, String),
+
+    /// A configured function does not return a tile.
+    #[error(
+        "Function {1} of source {0} must return bytea or a table of (bytea) or (bytea, text), but it returns {2}"
+    )]
+    InvalidFunctionSignature(String, String, String),
+
     /// Query preparation error.
     #[error("Error preparing a query for the tile '{1}' ({2}): {3} {0}")]
     PrepareQueryError(#[source] TokioPostgresError, String, String, String),
@@ -99,4 +127,16 @@ pub enum PostgresError {
         TileCoord,
         Option<UrlQuery>,
     ),
+
+    /// Tile compression error.
+    #[error("Unable to compress tile {2:#} from {1}: {0}")]
+    CompressTileError(#[source] io::Error, String, TileCoord),
+
+    /// The tile query of a source did not produce a usable tile during output validation.
+    #[error("Source {0} failed output validation on tile {1:#}: {2}")]
+    InvalidSourceOutput(String, TileCoord, &'static str),
+
+    /// URL query parameter value does not match the configured type.
+    #[error("Invalid value of the URL query parameter {1} for source {0}, expecting a {2}")]
+    InvalidUrlQuery(String, String, UrlQueryParamType),
 }
@@ -1,12 +1,18 @@
+mod discovery;
+pub use discovery::{FunctionSourceInfo, TableSourceInfo};
+
 mod errors;
 pub use errors::{PostgresError, PostgresResult};
 
 mod tls;
 
 mod pool;
-pub use pool::PostgresPool;
+pub use pool::{PostgresPool, PostgresPoolOptions, PostgresPoolStats};
 
 mod source;
-pub use source::{PostgresSource, PostgresSqlInfo};
+pub use source::{
+    COMPRESSION_MIN_SIZE, PostgresCompression, PostgresQueryKind, PostgresSource, PostgresSqlInfo,
+    UrlQueryParamType,
+};
 
 pub(crate) mod utils;
@@ -1,15 +1,21 @@
 //! `PostgreSQL` connection pool implementation.
 
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
-use deadpool_postgres::{Manager, ManagerConfig, Object, Pool, RecyclingMethod};
+use deadpool_postgres::{
+    Hook, HookError, Manager, ManagerConfig, Object, Pool, PoolError, RecyclingMethod, Runtime,
+    TimeoutType,
+};
 use postgres::config::SslMode;
 use semver::Version;
+use serde::Serialize;
 use tracing::{info, warn};
 
 use crate::tiles::postgres::PostgresError::{
-    BadPostgisVersion, BadPostgresVersion, PostgisTooOld, PostgresError, PostgresPoolBuildError,
-    PostgresPoolConnError, PostgresqlTooOld,
+    BadPostgisVersion, BadPostgresVersion, PoolTimeout, PostgisTooOld, PostgresError,
+    PostgresPoolBuildError, PostgresPoolConnError, PostgresqlTooOld,
 };
 use crate::tiles::postgres::PostgresResult;
 use crate::tiles::postgres::tls::{SslModeOverride, make_connector, parse_conn_str};
@@ -27,11 +33,51 @@ const MISSING_GEOM_FIXED_POSTGIS_VERSION: Version = Version::new(3, 5, 0);
 /// Minimum version of postgres required for [`RECOMMENDED_POSTGIS_VERSION`] according to the [Support Matrix](https://trac.osgeo.org/postgis/wiki/UsersWikiPostgreSQLPostGIS)
 const RECOMMENDED_POSTGRES_VERSION: Version = Version::new(12, 0, 0);
 
+/// Point-in-time snapshot of a [`PostgresPool`]'s connection usage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PostgresPoolStats {
+    /// Maximum number of connections the pool may open.
+    pub max_size: usize,
+    /// Number of connections currently open.
+    pub size: usize,
+    /// Number of open connections that are not in use.
+    pub idle: usize,
+    /// Number of connections currently checked out.
+    pub in_use: usize,
+    /// Number of requests waiting for a connection to become available.
+    pub waiting_for_connection: usize,
+}
+
+/// Optional settings of a [`PostgresPool`], see [`PostgresPool::new_with_options`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PostgresPoolOptions {
+    acquire_timeout: Option<Duration>,
+    on_connect_sql: Vec<String>,
+}
+
+impl PostgresPoolOptions {
+    /// Sets the maximum time to wait for a connection if all of them are in use. Waits indefinitely by default.
+    #[must_use]
+    pub fn with_acquire_timeout(mut self, acquire_timeout: Option<Duration>) -> Self {
+        self.acquire_timeout = acquire_timeout;
+        self
+    }
+
+    /// Sets the SQL statements to run on every new connection, e.g. `SET statement_timeout = '10s'`. None by default.
+    #[must_use]
+    pub fn with_on_connect_sql(mut self, on_connect_sql: Vec<String>) -> Self {
+        self.on_connect_sql = on_connect_sql;
+        self
+    }
+}
+
 /// `PostgreSQL` connection pool with `PostGIS` support.
 #[derive(Clone, Debug)]
 pub struct PostgresPool {
     id: String,
     pool: Pool,
+    /// Maximum time to wait for a connection to become available, if any.
+    acquire_timeout: Option<Duration>,
     /// Indicates if `ST_TileEnvelope` supports the margin parameter.
     ///
     /// `true` if running postgis >= 3.1
@@ -54,16 +100,51 @@ impl PostgresPool {
         ssl_key: Option<&PathBuf>,
         ssl_root_cert: Option<&PathBuf>,
         pool_size: usize,
+    ) -> PostgresResult<Self> {
+        Self::new_with_options(
+            connection_string,
+            ssl_cert,
+            ssl_key,
+            ssl_root_cert,
+            pool_size,
+            PostgresPoolOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as [`PostgresPool::new`], with the additional settings of [`PostgresPoolOptions`].
+    pub async fn new_with_options(
+        connection_string: &str,
+        ssl_cert: Option<&PathBuf>,
+        ssl_key: Option<&PathBuf>,
+        ssl_root_cert: Option<&PathBuf>,
+        pool_size: usize,
+        options: PostgresPoolOptions,
     ) -> PostgresResult<Self> {
         let (id, mgr) = Self::parse_config(connection_string, ssl_cert, ssl_key, ssl_root_cert)?;
+        let PostgresPoolOptions {
+            acquire_timeout,
+            on_connect_sql,
+        } = options;
 
-        let pool = Pool::builder(mgr)
+        let mut builder = Pool::builder(mgr)
             .max_size(pool_size)
+            .wait_timeout(acquire_timeout)
+            .runtime(Runtime::Tokio1);
+        if !on_connect_sql.is_empty() {
+            info!(
+                "Running {} SQL statement(s) on every new connection for source {id}",
+                on_connect_sql.len()
+            );
+            builder = builder.post_create(on_connect_hook(&on_connect_sql));
+        }
+        let pool = builder
             .build()
             .map_err(|e| PostgresPoolBuildError(e, id.clone()))?;
         let mut res = Self {
             id: id.clone(),
             pool,
+            acquire_timeout,
             supports_tile_margin: false,
         };
         let conn = res.get().await?;
@@ -150,12 +231,31 @@ impl PostgresPool {
     ///
     /// # Errors
     ///
-    /// See [`PostgresPoolConnError`] for details.
+    /// Returns [`PoolTimeout`] if no connection became available within the configured acquire timeout.
+    /// See [`PostgresPoolConnError`] for other details.
     pub async fn get(&self) -> PostgresResult<Object> {
         self.pool
             .get()
             .await
-            .map_err(|e| PostgresPoolConnError(e, self.id.clone()))
+            .map_err(|e| match (e, self.acquire_timeout) {
+                (PoolError::Timeout(TimeoutType::Wait), Some(timeout)) => {
+                    PoolTimeout(timeout, self.id.clone())
+                }
+                (e, _) => PostgresPoolConnError(e, self.id.clone()),
+            })
+    }
+
+    /// Current connection usage of this [`PostgresPool`], e.g. to alert on pool exhaustion.
+    #[must_use]
+    pub fn stats(&self) -> PostgresPoolStats {
+        let status = self.pool.status();
+        PostgresPoolStats {
+            max_size: status.max_size,
+            size: status.size,
+            idle: status.available,
+            in_use: status.size.saturating_sub(status.available),
+            waiting_for_connection: status.waiting,
+        }
     }
 
     /// ID under which this [`PostgresPool`] is identified externally
@@ -174,6 +274,25 @@ impl PostgresPool {
     }
 }
 
+/// Creates a hook that runs the given SQL statements, in order, on each newly created connection.
+///
+/// A failing statement fails the creation of the connection.
+fn on_connect_hook(sql: &[String]) -> Hook {
+    let sql: Arc<[String]> = sql.into();
+    Hook::async_fn(move |client, _| {
+        let sql = Arc::clone(&sql);
+        Box::pin(async move {
+            for statement in sql.iter() {
+                client
+                    .batch_execute(statement)
+                    .await
+                    .map_err(HookError::Backend)?;
+            }
+            Ok(())
+        })
+    })
+}
+
 /// Get [PostgreSQL version](https://www.postgresql.org/support/versioning/).
 /// `PostgreSQL` only has a Major.Minor versioning, so we use 0 the patch version
 async fn get_postgres_version(conn: &Object) -> PostgresResult<Version> {
@@ -274,4 +393,99 @@ mod tests {
         assert_eq!(postgis_version.minor, 0);
         assert!(postgis_version.patch >= 3); // we don't want to break this testcase just because postgis updates that image
     }
+
+    #[tokio::test]
+    async fn on_connect_sql() {
+        let node = Postgres::default()
+            .with_name("postgis/postgis")
+            .with_tag("11-3.0")
+            .start()
+            .await
+            .expect("container launched");
+
+        let pg_config = Config::new()
+            .host(node.get_host().await.unwrap().to_string())
+            .port(node.get_host_port_ipv4(5432).await.unwrap())
+            .dbname("postgres")
+            .user("postgres")
+            .password("postgres")
+            .to_owned();
+        let mgr_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+        let mgr = Manager::from_config(pg_config.clone(), NoTls, mgr_config.clone());
+        let sql = [
+            "SET statement_timeout = '1234ms'".to_string(),
+            "SET application_name = 'martin-test'".to_string(),
+        ];
+        let pool = Pool::builder(mgr)
+            .max_size(2)
+            .post_create(on_connect_hook(&sql))
+            .build()
+            .unwrap();
+
+        let conn = pool.get().await.unwrap();
+        let row = conn
+            .query_one(
+                "SELECT current_setting('statement_timeout'), current_setting('application_name')",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, String>(0), "1234ms");
+        assert_eq!(row.get::<_, String>(1), "martin-test");
+
+        let mgr = Manager::from_config(pg_config, NoTls, mgr_config);
+        let pool = Pool::builder(mgr)
+            .post_create(on_connect_hook(&["SET no_such_setting = 1".to_string()]))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            pool.get().await,
+            Err(PoolError::PostCreateHook(HookError::Backend(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn pool_stats() {
+        let node = Postgres::default()
+            .with_name("postgis/postgis")
+            .with_tag("11-3.0")
+            .start()
+            .await
+            .expect("container launched");
+
+        let pg_config = Config::new()
+            .host(node.get_host().await.unwrap().to_string())
+            .port(node.get_host_port_ipv4(5432).await.unwrap())
+            .dbname("postgres")
+            .user("postgres")
+            .password("postgres")
+            .to_owned();
+        let mgr_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+        let mgr = Manager::from_config(pg_config, NoTls, mgr_config);
+        let pool = PostgresPool {
+            id: "test".to_string(),
+            pool: Pool::builder(mgr).max_size(2).build().unwrap(),
+            acquire_timeout: None,
+            supports_tile_margin: true,
+        };
+        assert_eq!(
+            pool.stats(),
+            PostgresPoolStats {
+                max_size: 2,
+                ..PostgresPoolStats::default()
+            }
+        );
+
+        let conn = pool.get().await.unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.size, stats.idle, stats.in_use), (1, 0, 1));
+
+        drop(conn);
+        let stats = pool.stats();
+        assert_eq!((stats.size, stats.idle, stats.in_use), (1, 1, 0));
+    }
 }
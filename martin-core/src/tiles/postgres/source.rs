@@ -1,18 +1,32 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::time::Instant;
+
 use async_trait::async_trait;
+use deadpool_postgres::GenericClient;
 use deadpool_postgres::tokio_postgres::types::{ToSql, Type};
-use martin_tile_utils::Encoding::Uncompressed;
+use martin_tile_utils::Encoding::{Gzip, Uncompressed};
 use martin_tile_utils::Format::Mvt;
-use martin_tile_utils::{TileCoord, TileData, TileInfo};
+use martin_tile_utils::{
+    TileCoord, TileData, TileInfo, count_mvt_features, decode_gzip, encode_gzip, tile_index,
+};
+use serde::{Deserialize, Serialize};
 use tilejson::TileJSON;
-use tracing::debug;
+use tracing::field::Empty;
+use tracing::{Instrument as _, debug, debug_span};
 
 use crate::CacheZoomRange;
 use crate::tiles::postgres::PostgresError::{
-    GetTileError, GetTileWithQueryError, PrepareQueryError,
+    CompressTileError, GetTileError, GetTileWithQueryError, InvalidSourceOutput, InvalidUrlQuery,
+    PoolTimeout, PostgresError, PrepareQueryError,
 };
 use crate::tiles::postgres::PostgresPool;
-use crate::tiles::postgres::utils::query_to_json;
-use crate::tiles::{BoxedSource, MartinCoreResult, Source, UrlQuery};
+use crate::tiles::postgres::utils::{query_to_json, typed_query_to_json};
+use crate::tiles::tile::layer_name;
+use crate::tiles::{
+    BoxedSource, EmptyTileBehavior, MartinCoreError, MartinCoreResult, Source, Tile, UrlQuery,
+};
 
 #[derive(Clone, Debug)]
 /// `PostgreSQL` tile source that executes SQL queries to generate tiles.
@@ -42,45 +56,110 @@ impl PostgresSource {
             cache_zoom,
         }
     }
-}
-
-#[async_trait]
-impl Source for PostgresSource {
-    fn get_id(&self) -> &str {
-        &self.id
-    }
 
-    fn get_tilejson(&self) -> &TileJSON {
-        &self.tilejson
+    /// SQL query information used to fetch tiles from this source.
+    #[must_use]
+    pub fn get_sql_info(&self) -> &PostgresSqlInfo {
+        &self.info
     }
 
-    fn get_tile_info(&self) -> TileInfo {
-        TileInfo::new(Mvt, Uncompressed)
+    /// Renders the tile query for the given zoom level for debugging, see [`PostgresSqlInfo::render_sql`].
+    #[must_use]
+    pub fn render_sql(&self, zoom: u8) -> String {
+        self.info.render_sql(zoom)
     }
 
-    fn clone_source(&self) -> BoxedSource {
-        Box::new(self.clone())
+    /// Runs the tile query once for a tile that should contain data, and checks that the result
+    /// is a non-empty and parseable MVT tile.
+    ///
+    /// The tile is the one containing the `TileJSON` center, or the center of the bounds at the
+    /// minimum zoom. This catches misconfigurations like a wrong geometry column, SRID or filter
+    /// before the first client request does. The returned error names the check that failed.
+    pub async fn validate_output(&self) -> MartinCoreResult<()> {
+        let xyz = self.sample_tile();
+        let tile = self.fetch_tile(xyz, None).await?;
+        let fail = |check| InvalidSourceOutput(self.id.clone(), xyz, check);
+        if tile.is_empty() {
+            return Err(fail("the tile is empty").into());
+        }
+        if self.info.tile_info.format != Mvt {
+            return Ok(());
+        }
+        let tile = match detect_gzip(&tile, self.info.tile_info).encoding {
+            Gzip => decode_gzip(&tile).map_err(|_| fail("the tile is not valid gzip"))?,
+            _ => tile,
+        };
+        match count_mvt_features(&tile) {
+            None => Err(fail("the tile is not a valid MVT").into()),
+            Some(0) => Err(fail("the tile has no features").into()),
+            Some(_) => Ok(()),
+        }
     }
 
-    fn support_url_query(&self) -> bool {
-        self.info.use_url_query
+    /// Tile used by [`validate_output`](Self::validate_output), see there.
+    fn sample_tile(&self) -> TileCoord {
+        let minzoom = self.tilejson.minzoom.unwrap_or(0);
+        let maxzoom = self.tilejson.maxzoom.unwrap_or(minzoom).max(minzoom);
+        let (lng, lat, z) = if let Some(center) = self.tilejson.center {
+            (center.longitude, center.latitude, center.zoom)
+        } else {
+            let b = self.tilejson.bounds.unwrap_or_default();
+            let lng = f64::midpoint(b.left, b.right);
+            (lng, f64::midpoint(b.bottom, b.top), minzoom)
+        };
+        let z = z.clamp(minzoom, maxzoom);
+        let (x, y) = tile_index(lng, lat, z);
+        TileCoord { z, x, y }
     }
 
-    fn benefits_from_concurrent_scraping(&self) -> bool {
-        // pg does not parallelize queries well internally and having more requests in flight is thus beneficial
-        true
-    }
+    /// Fetches the tile, returning an empty one outside of the zoom range or bounds.
+    async fn fetch_tile(
+        &self,
+        xyz: TileCoord,
+        url_query: Option<&UrlQuery>,
+    ) -> MartinCoreResult<TileData> {
+        if !self.is_valid_zoom(xyz.z) {
+            debug!("Tile {xyz} is outside of the zoom range of {}", self.id);
+            return Ok(TileData::new());
+        }
+        if self.info.is_clipped(xyz) {
+            debug!("Tile {xyz} is outside of the bounds of {}", self.id);
+            return Ok(TileData::new());
+        }
+        let mut conn = self.pool.get().await.map_err(|e| match e {
+            PoolTimeout(timeout, pool_id) => MartinCoreError::Timeout(
+                timeout,
+                format!(
+                    "waiting for a connection from the Postgres pool {pool_id} for {}",
+                    self.id
+                ),
+            ),
+            e => e.into(),
+        })?;
+        if !self.info.read_only {
+            return self.query_tile(&conn, xyz, url_query).await;
+        }
 
-    fn cache_zoom(&self) -> CacheZoomRange {
-        self.cache_zoom
+        let tx = conn
+            .build_transaction()
+            .read_only(true)
+            .start()
+            .await
+            .map_err(|e| PostgresError(e, "starting a read-only transaction"))?;
+        let tile = self.query_tile(&tx, xyz, url_query).await?;
+        tx.commit()
+            .await
+            .map_err(|e| PostgresError(e, "committing a read-only transaction"))?;
+        Ok(tile)
     }
 
-    async fn get_tile(
+    /// Runs the tile query using the given connection or transaction.
+    async fn query_tile(
         &self,
+        conn: &impl GenericClient,
         xyz: TileCoord,
         url_query: Option<&UrlQuery>,
     ) -> MartinCoreResult<TileData> {
-        let conn = self.pool.get().await?;
         let param_types: &[Type] = if self.support_url_query() {
             &[Type::INT2, Type::INT8, Type::INT8, Type::JSON]
         } else {
@@ -88,20 +167,29 @@ impl Source for PostgresSource {
         };
 
         let sql = &self.info.sql_query;
-        let prep_query = conn
-            .prepare_typed_cached(sql, param_types)
-            .await
-            .map_err(|e| {
-                PrepareQueryError(
-                    e,
-                    self.id.clone(),
-                    self.info.signature.clone(),
-                    self.info.sql_query.clone(),
-                )
-            })?;
+        let prep_query = if self.info.use_statement_cache {
+            conn.prepare_typed_cached(sql, param_types).await
+        } else {
+            conn.prepare_typed(sql, param_types).await
+        };
+        let prep_query = prep_query.map_err(|e| {
+            PrepareQueryError(
+                e,
+                self.id.clone(),
+                self.info.signature.clone(),
+                self.info.sql_query.clone(),
+            )
+        })?;
 
         let tile = if self.support_url_query() {
-            let json = query_to_json(url_query);
+            let json = if let Some(allowlist) = &self.info.url_query_allowlist {
+                typed_query_to_json(url_query, allowlist).map_err(|param| {
+                    let typ = allowlist[&param];
+                    InvalidUrlQuery(self.id.clone(), param, typ)
+                })?
+            } else {
+                query_to_json(url_query)
+            };
             debug!("SQL: {sql} [{xyz}, {json:?}]");
             let params: &[&(dyn ToSql + Sync)] = &[
                 &i16::from(xyz.z),
@@ -134,25 +222,367 @@ impl Source for PostgresSource {
     }
 }
 
+#[async_trait]
+impl Source for PostgresSource {
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn get_tilejson(&self) -> &TileJSON {
+        &self.tilejson
+    }
+
+    fn get_tile_info(&self) -> TileInfo {
+        self.info.tile_info
+    }
+
+    fn clone_source(&self) -> BoxedSource {
+        Box::new(self.clone())
+    }
+
+    fn support_url_query(&self) -> bool {
+        self.info.use_url_query
+    }
+
+    fn benefits_from_concurrent_scraping(&self) -> bool {
+        // pg does not parallelize queries well internally and having more requests in flight is thus beneficial
+        true
+    }
+
+    fn cache_zoom(&self) -> CacheZoomRange {
+        self.cache_zoom
+    }
+
+    /// Fetches the tile within a `get_tile` tracing span, which records the tile size in bytes
+    /// and the elapsed time in milliseconds once the tile is fetched.
+    async fn get_tile(
+        &self,
+        xyz: TileCoord,
+        url_query: Option<&UrlQuery>,
+    ) -> MartinCoreResult<TileData> {
+        let span = debug_span!(
+            "get_tile",
+            source = %self.id,
+            z = xyz.z,
+            x = xyz.x,
+            y = xyz.y,
+            bytes = Empty,
+            elapsed_ms = Empty,
+        );
+        let start = Instant::now();
+        let mut tile = self
+            .fetch_tile(xyz, url_query)
+            .instrument(span.clone())
+            .await?;
+        if tile.is_empty()
+            && let Some(empty) = self
+                .info
+                .empty_tile
+                .empty_tile(layer_name(&self.tilejson, &self.id), self.info.tile_info)
+        {
+            tile = empty;
+        }
+        span.record("bytes", tile.len());
+        span.record("elapsed_ms", start.elapsed().as_secs_f64() * 1000.0);
+        Ok(tile)
+    }
+
+    /// Same as [`get_tile`](Self::get_tile), but compresses the tile if the source is configured to,
+    /// unless it is smaller than [`COMPRESSION_MIN_SIZE`] or already gzip-compressed.
+    ///
+    /// Whether a tile is gzip-compressed is detected from its magic header, because pre-generated
+    /// tiles may be stored differently than the tile sampled when the source was created.
+    async fn get_tile_with_etag(
+        &self,
+        xyz: TileCoord,
+        url_query: Option<&UrlQuery>,
+    ) -> MartinCoreResult<Tile> {
+        let data = self.get_tile(xyz, url_query).await?;
+        let info = detect_gzip(&data, self.get_tile_info());
+        if info.encoding == Gzip {
+            return Ok(Tile::new_hash_etag(data, info));
+        }
+        match self.info.compression {
+            Some(PostgresCompression::Gzip) if data.len() >= COMPRESSION_MIN_SIZE => {
+                let data =
+                    encode_gzip(&data).map_err(|e| CompressTileError(e, self.id.clone(), xyz))?;
+                Ok(Tile::new_hash_etag(data, info.encoding(Gzip)))
+            }
+            _ => Ok(Tile::new_hash_etag(data, info)),
+        }
+    }
+}
+
+/// Tiles smaller than this many bytes are not compressed, as it would not save enough to be worth the CPU time.
+pub const COMPRESSION_MIN_SIZE: usize = 1024;
+
+/// Adjusts the gzip encoding of the tile info to match the magic header of the tile data.
+///
+/// Other encodings cannot be detected reliably, so they are kept as is.
+fn detect_gzip(data: &[u8], info: TileInfo) -> TileInfo {
+    let is_gzip = data.starts_with(b"\x1f\x8b");
+    match info.encoding {
+        Uncompressed if is_gzip => info.encoding(Gzip),
+        Gzip if !is_gzip && !data.is_empty() => info.encoding(Uncompressed),
+        _ => info,
+    }
+}
+
+/// Compression a [`PostgresSource`] applies to the generated tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostgresCompression {
+    /// Compress tiles with gzip
+    Gzip,
+}
+
+/// Kind of SQL query a [`PostgresSource`] uses to fetch its tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostgresQueryKind {
+    /// The query selects from a table or view, generating the tile with `ST_AsMVT`.
+    Table,
+    /// The query calls a user-provided function that returns the tile.
+    Function,
+    /// The query selects a pre-generated tile from a table by its zoom, column and row.
+    TileTable,
+}
+
+impl Display for PostgresQueryKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Table => f.write_str("table"),
+            Self::Function => f.write_str("function"),
+            Self::TileTable => f.write_str("tile table"),
+        }
+    }
+}
+
+/// Type a URL query parameter must have to be passed to a function source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlQueryParamType {
+    /// Any value, passed as a JSON string
+    String,
+    /// A floating point number
+    Number,
+    /// A 64-bit integer
+    Integer,
+    /// `true` or `false`
+    Boolean,
+}
+
+impl Display for UrlQueryParamType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String => f.write_str("string"),
+            Self::Number => f.write_str("number"),
+            Self::Integer => f.write_str("integer"),
+            Self::Boolean => f.write_str("boolean"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 /// SQL query information for `PostgreSQL` tile sources.
 pub struct PostgresSqlInfo {
     /// SQL query string.
     pub sql_query: String,
+    /// Whether the query is a table query or a function call.
+    pub kind: PostgresQueryKind,
     /// Whether the query uses URL query parameters.
     pub use_url_query: bool,
     /// Signature of the query.
     pub signature: String,
+    /// Whether the prepared statement is cached per connection and reused across requests.
+    ///
+    /// The cache is keyed by [`sql_query`](Self::sql_query). Disabling it forces a
+    /// re-prepare on every request, which is mostly useful for debugging.
+    pub use_statement_cache: bool,
+    /// Whether each tile query runs in a `READ ONLY` transaction.
+    ///
+    /// This prevents accidental writes, e.g. by a misbehaving function, and allows routing to replicas.
+    pub read_only: bool,
+    /// URL query parameters passed to the function and their types.
+    ///
+    /// Other parameters are dropped, and a value not matching its type is rejected.
+    /// If `None`, all parameters are passed as is.
+    pub url_query_allowlist: Option<BTreeMap<String, UrlQueryParamType>>,
+    /// Compression applied to the generated tiles before they are returned.
+    pub compression: Option<PostgresCompression>,
+    /// Format and encoding of the tiles returned by the query.
+    pub tile_info: TileInfo,
+    /// What to return for tiles without any data.
+    pub empty_tile: EmptyTileBehavior,
+    /// WGS84 bounds `[min_lng, min_lat, max_lng, max_lat]` of the source's data.
+    ///
+    /// Tiles outside of them are returned empty without querying the database.
+    pub clip_bounds: Option<[f64; 4]>,
 }
 
 impl PostgresSqlInfo {
     /// Creates new SQL query information.
     #[must_use]
-    pub fn new(query: String, has_query_params: bool, signature: String) -> Self {
+    pub fn new(
+        query: String,
+        kind: PostgresQueryKind,
+        has_query_params: bool,
+        signature: String,
+    ) -> Self {
         Self {
             sql_query: query,
+            kind,
             use_url_query: has_query_params,
             signature,
+            use_statement_cache: true,
+            read_only: false,
+            url_query_allowlist: None,
+            compression: None,
+            tile_info: TileInfo::new(Mvt, Uncompressed),
+            empty_tile: EmptyTileBehavior::None,
+            clip_bounds: None,
         }
     }
+
+    /// Enables or disables caching of the prepared statement. Enabled by default.
+    #[must_use]
+    pub fn with_statement_cache(mut self, enabled: bool) -> Self {
+        self.use_statement_cache = enabled;
+        self
+    }
+
+    /// Enables or disables running each tile query in a `READ ONLY` transaction. Disabled by default.
+    #[must_use]
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Restricts the URL query parameters passed to the function to the given names and types.
+    #[must_use]
+    pub fn with_url_query_allowlist(
+        mut self,
+        allowlist: Option<BTreeMap<String, UrlQueryParamType>>,
+    ) -> Self {
+        self.url_query_allowlist = allowlist;
+        self
+    }
+
+    /// Compresses the generated tiles. Disabled by default.
+    #[must_use]
+    pub fn with_compression(mut self, compression: Option<PostgresCompression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the format and encoding of the tiles returned by the query. Uncompressed MVT by default.
+    #[must_use]
+    pub fn with_tile_info(mut self, tile_info: TileInfo) -> Self {
+        self.tile_info = tile_info;
+        self
+    }
+
+    /// Sets what to return for tiles without any data. Nothing by default.
+    #[must_use]
+    pub fn with_empty_tile(mut self, empty_tile: EmptyTileBehavior) -> Self {
+        self.empty_tile = empty_tile;
+        self
+    }
+
+    /// Skips querying tiles outside of the given WGS84 bounds. Disabled by default.
+    #[must_use]
+    pub fn with_clip_bounds(mut self, bounds: Option<[f64; 4]>) -> Self {
+        self.clip_bounds = bounds;
+        self
+    }
+
+    /// Returns `true` if the tile is outside of the [clip bounds](Self::clip_bounds), and can only be empty.
+    #[must_use]
+    pub fn is_clipped(&self, xyz: TileCoord) -> bool {
+        let Some([left, bottom, right, top]) = self.clip_bounds else {
+            return false;
+        };
+        let [min_lng, min_lat, max_lng, max_lat] = xyz.lonlat_bounds();
+        // Tiles touching the bounds are kept, as their buffer may contain features
+        min_lng > right || max_lng < left || min_lat > top || max_lat < bottom
+    }
+
+    /// Renders the query for the given zoom level, so it can be pasted into `psql` for debugging.
+    ///
+    /// The zoom parameter `$1` is substituted, while the tile `x`/`y` (`$2`/`$3`) and the
+    /// optional url query (`$4`) placeholders are kept as is.
+    /// The query is prefixed with an SQL comment stating its [kind](PostgresQueryKind) and signature.
+    #[must_use]
+    pub fn render_sql(&self, zoom: u8) -> String {
+        let mut sql = format!("-- {} query for {}\n", self.kind, self.signature);
+        let mut rest = self.sql_query.as_str();
+        while let Some(pos) = rest.find("$1") {
+            let after = &rest[pos + 2..];
+            sql.push_str(&rest[..pos]);
+            if after.starts_with(|c: char| c.is_ascii_digit()) {
+                sql.push_str("$1");
+            } else {
+                sql.push_str(&zoom.to_string());
+            }
+            rest = after;
+        }
+        sql.push_str(rest);
+        sql
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_sql() {
+        let info = PostgresSqlInfo::new(
+            "SELECT tile FROM public.fn($1::integer, $2::integer, $3::integer, $4::json)"
+                .to_string(),
+            PostgresQueryKind::Function,
+            true,
+            "public.fn(integer, integer, integer, json) -> bytea".to_string(),
+        );
+        assert_eq!(
+            info.render_sql(7),
+            "-- function query for public.fn(integer, integer, integer, json) -> bytea\n\
+             SELECT tile FROM public.fn(7::integer, $2::integer, $3::integer, $4::json)"
+        );
+    }
+
+    #[test]
+    fn clip_bounds() {
+        let info = PostgresSqlInfo::new(
+            "SELECT 1".to_string(),
+            PostgresQueryKind::Table,
+            false,
+            "public.points.geom".to_string(),
+        );
+        let tile = |z, x, y| TileCoord { z, x, y };
+        assert!(!info.is_clipped(tile(5, 0, 0)));
+
+        // Switzerland
+        let info = info.with_clip_bounds(Some([5.9, 45.8, 10.5, 47.8]));
+        assert!(!info.is_clipped(tile(0, 0, 0)));
+        assert!(!info.is_clipped(tile(8, 133, 90)));
+        assert!(info.is_clipped(tile(8, 0, 0)));
+        assert!(info.is_clipped(tile(8, 133, 200)));
+    }
+
+    #[test]
+    fn detect_gzip_encoding() {
+        let mvt = TileInfo::new(Mvt, Uncompressed);
+        let gzipped = encode_gzip(b"\x1a\x03").unwrap();
+        assert_eq!(detect_gzip(&gzipped, mvt), mvt.encoding(Gzip));
+        assert_eq!(detect_gzip(b"\x1a\x03", mvt), mvt);
+        assert_eq!(
+            detect_gzip(&gzipped, mvt.encoding(Gzip)),
+            mvt.encoding(Gzip)
+        );
+        assert_eq!(detect_gzip(b"\x1a\x03", mvt.encoding(Gzip)), mvt);
+        assert_eq!(detect_gzip(b"", mvt.encoding(Gzip)), mvt.encoding(Gzip));
+
+        let brotli = mvt.encoding(martin_tile_utils::Encoding::Brotli);
+        assert_eq!(detect_gzip(b"\x1a\x03", brotli), brotli);
+    }
 }
@@ -13,13 +13,12 @@ use rustls::crypto::{verify_tls12_signature, verify_tls13_signature};
 use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
 use rustls::{DigitallySignedStruct, Error, SignatureScheme};
 use rustls_native_certs::load_native_certs;
-use rustls_pemfile::Item::Pkcs1Key;
 use tokio_postgres_rustls::MakeRustlsConnect;
-use tracing::{info, warn};
+use tracing::info;
 
 use crate::tiles::postgres::PostgresError::{
     BadConnectionString, CannotLoadRoots, CannotOpenCert, CannotParseCert, CannotUseClientKey,
-    InvalidPrivateKey, UnknownSslMode,
+    InvalidPrivateKey, TlsConfig, UnknownSslMode,
 };
 use crate::tiles::postgres::PostgresResult;
 
@@ -148,6 +147,30 @@ pub fn make_connector(
         SslModeOverride::VerifyFull => (true, true),
     };
 
+    // Load the client certificate pair before any TLS setup, so misconfiguration is reported early
+    let client_auth = match (ssl_cert, ssl_key) {
+        (Some(cert), Some(key)) => {
+            // Accepts PKCS#1 (RSA), PKCS#8 and SEC1 (EC) private keys
+            let key_der = rustls_pemfile::private_key(&mut cert_reader(key)?)
+                .map_err(|e| CannotParseCert(e, key.clone()))?
+                .ok_or_else(|| InvalidPrivateKey(key.clone()))?;
+            let certs = read_certs(cert)?;
+            if certs.is_empty() {
+                return Err(TlsConfig(format!(
+                    "client certificate file {} does not contain any PEM certificates",
+                    cert.display()
+                )));
+            }
+            Some((cert, key, certs, key_der))
+        }
+        (None, None) => None,
+        (Some(_), None) | (None, Some(_)) => {
+            return Err(TlsConfig(
+                "both ssl_cert and ssl_key must be set to use a client certificate with Postgres, but only one of them was set".to_string(),
+            ));
+        }
+    };
+
     let mut roots = rustls::RootCertStore::empty();
 
     if let Some(file) = &ssl_root_cert {
@@ -169,20 +192,16 @@ pub fn make_connector(
 
     let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
 
-    let mut builder = if let (Some(cert), Some(key)) = (ssl_cert, ssl_key) {
-        match rustls_pemfile::read_one(&mut cert_reader(key)?) {
-            Ok(Some(Pkcs1Key(rsa_key))) => builder
-                .with_client_auth_cert(read_certs(cert)?, rsa_key.into())
-                .map_err(|e| CannotUseClientKey(e, cert.clone(), key.clone()))?,
-            Ok(_) => Err(InvalidPrivateKey(key.clone()))?,
-            Err(e) => Err(CannotParseCert(e, key.clone()))?,
-        }
+    let mut builder = if let Some((cert, key, certs, key_der)) = client_auth {
+        info!(
+            "Using client certificate {} and key {} for Postgres authentication",
+            cert.display(),
+            key.display()
+        );
+        builder
+            .with_client_auth_cert(certs, key_der)
+            .map_err(|e| CannotUseClientKey(e, cert.clone(), key.clone()))?
     } else {
-        if ssl_key.is_some() || ssl_key.is_some() {
-            warn!(
-                "SSL client certificate and key files must be set to use client certificate with Postgres. Only one of them was set."
-            );
-        }
         builder.with_no_client_auth()
     };
 
@@ -246,4 +265,23 @@ mod tests {
         assert_eq!(cfg.get_ssl_mode(), SslMode::Require);
         assert_eq!(mode, SslModeOverride::VerifyCa);
     }
+
+    #[test]
+    fn test_make_connector_requires_cert_and_key() {
+        let path = PathBuf::from("client.pem");
+        let mode = SslModeOverride::Unmodified(SslMode::Disable);
+        for (cert, key) in [(Some(&path), None), (None, Some(&path))] {
+            let result = make_connector(cert, key, None, mode);
+            assert!(matches!(result, Err(TlsConfig(_))));
+        }
+    }
+
+    #[test]
+    fn test_make_connector_missing_key_file() {
+        let cert = PathBuf::from("does-not-exist-cert.pem");
+        let key = PathBuf::from("does-not-exist-key.pem");
+        let mode = SslModeOverride::Unmodified(SslMode::Disable);
+        let result = make_connector(Some(&cert), Some(&key), None, mode);
+        assert!(matches!(result, Err(CannotOpenCert(_, p)) if p == key));
+    }
 }
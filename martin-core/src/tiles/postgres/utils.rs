@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use postgres::types::Json;
 
 use crate::tiles::UrlQuery;
+use crate::tiles::postgres::UrlQueryParamType;
 
 /// Converts a `UrlQuery` into a semantically identical `Json<HashMap<String, serde_json::Value>>`.
 #[must_use]
@@ -19,3 +20,76 @@ pub fn query_to_json(query: Option<&UrlQuery>) -> Json<HashMap<String, serde_jso
 
     Json(query_as_json)
 }
+
+/// Converts a `UrlQuery` into JSON, keeping only the parameters from the `allowlist`
+/// and converting each value to its allowed type.
+///
+/// Returns the name of the first parameter whose value cannot be converted.
+pub fn typed_query_to_json(
+    query: Option<&UrlQuery>,
+    allowlist: &BTreeMap<String, UrlQueryParamType>,
+) -> Result<Json<HashMap<String, serde_json::Value>>, String> {
+    let mut query_as_json = HashMap::new();
+    for (k, v) in query.into_iter().flatten() {
+        let Some(typ) = allowlist.get(k) else {
+            continue;
+        };
+        let json_value = match typ {
+            UrlQueryParamType::String => Some(serde_json::Value::String(v.clone())),
+            UrlQueryParamType::Number => v
+                .parse::<f64>()
+                .ok()
+                .and_then(|v| serde_json::Number::from_f64(v).map(serde_json::Value::Number)),
+            UrlQueryParamType::Integer => v.parse::<i64>().ok().map(serde_json::Value::from),
+            UrlQueryParamType::Boolean => v.parse::<bool>().ok().map(serde_json::Value::Bool),
+        };
+        let json_value = json_value.ok_or_else(|| k.clone())?;
+        query_as_json.insert(k.clone(), json_value);
+    }
+
+    Ok(Json(query_as_json))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn typed_query() {
+        let allowlist = BTreeMap::from([
+            ("name".to_string(), UrlQueryParamType::String),
+            ("ratio".to_string(), UrlQueryParamType::Number),
+            ("limit".to_string(), UrlQueryParamType::Integer),
+            ("debug".to_string(), UrlQueryParamType::Boolean),
+        ]);
+        let query = UrlQuery::from_iter(
+            [
+                ("name", "42"),
+                ("ratio", "0.5"),
+                ("limit", "10"),
+                ("debug", "true"),
+                ("other", "dropped"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        let Json(json) = typed_query_to_json(Some(&query), &allowlist).unwrap();
+        assert_eq!(
+            json,
+            HashMap::from([
+                ("name".to_string(), Value::from("42")),
+                ("ratio".to_string(), Value::from(0.5)),
+                ("limit".to_string(), Value::from(10)),
+                ("debug".to_string(), Value::from(true)),
+            ])
+        );
+
+        let query = UrlQuery::from_iter([("limit".to_string(), "ten".to_string())]);
+        assert_eq!(
+            typed_query_to_json(Some(&query), &allowlist).unwrap_err(),
+            "limit"
+        );
+        assert!(typed_query_to_json(None, &allowlist).unwrap().0.is_empty());
+    }
+}
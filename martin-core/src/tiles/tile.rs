@@ -1,6 +1,9 @@
 use base64::Engine as _;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use martin_tile_utils::{TileData, TileInfo};
+use martin_tile_utils::{Encoding, Format, TileData, TileInfo, empty_mvt, encode_gzip};
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "mbtiles", feature = "postgres"))]
+use tilejson::TileJSON;
 
 /// Represents a single map tile with its raw data and metadata.
 ///
@@ -58,3 +61,72 @@ impl Tile {
         self.data.is_empty()
     }
 }
+
+/// What a source returns for a tile without any data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmptyTileBehavior {
+    /// Return no data, which the HTTP layer turns into a `204 No Content` response
+    #[default]
+    None,
+    /// Return an encoded vector tile with a single layer without any features.
+    /// Sources of other formats keep returning no data.
+    #[serde(alias = "empty_blob")]
+    EmptyBlob,
+}
+
+impl EmptyTileBehavior {
+    /// Returns the tile to serve instead of an empty one, or `None` if the tile should stay empty.
+    ///
+    /// The blob contains a single layer named `layer`, and is gzip-compressed if the source's tiles are.
+    #[must_use]
+    pub fn empty_tile(self, layer: &str, info: TileInfo) -> Option<TileData> {
+        if self == Self::None || info.format != Format::Mvt {
+            return None;
+        }
+        let tile = empty_mvt(layer);
+        match info.encoding {
+            Encoding::Uncompressed => Some(tile),
+            Encoding::Gzip => encode_gzip(&tile).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the vector layer of a source, used for [`EmptyTileBehavior::EmptyBlob`] tiles.
+///
+/// This is the first layer advertised in the source's `tilejson`, falling back to the source `id`.
+#[cfg(any(feature = "mbtiles", feature = "postgres"))]
+pub(crate) fn layer_name<'a>(tilejson: &'a TileJSON, id: &'a str) -> &'a str {
+    tilejson
+        .vector_layers
+        .as_ref()
+        .and_then(|layers| layers.first())
+        .map_or(id, |layer| &layer.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use martin_tile_utils::decode_gzip;
+
+    use super::*;
+
+    #[test]
+    fn empty_tile() {
+        let mvt = TileInfo::new(Format::Mvt, Encoding::Uncompressed);
+        assert_eq!(EmptyTileBehavior::None.empty_tile("points", mvt), None);
+
+        let blob = EmptyTileBehavior::EmptyBlob
+            .empty_tile("points", mvt)
+            .unwrap();
+        assert_eq!(blob, b"\x1a\x0d\x78\x02\x0a\x06points\x28\x80\x20".to_vec());
+
+        let gzipped = EmptyTileBehavior::EmptyBlob
+            .empty_tile("points", mvt.encoding(Encoding::Gzip))
+            .unwrap();
+        assert_eq!(decode_gzip(&gzipped).unwrap(), blob);
+
+        let png = TileInfo::new(Format::Png, Encoding::Internal);
+        assert_eq!(EmptyTileBehavior::EmptyBlob.empty_tile("points", png), None);
+    }
+}
@@ -29,6 +29,13 @@ pub fn encode_gzip(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
     encoder.finish()
 }
 
+/// Same as [`encode_gzip`], but with an explicit compression `level` from 0 (none) to 9 (best).
+pub fn encode_gzip_with_level(data: &[u8], level: u32) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
 pub fn decode_brotli(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
     let mut decoder = brotli::Decompressor::new(data, 4096);
     let mut decompressed = Vec::new();
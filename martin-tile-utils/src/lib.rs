@@ -16,8 +16,13 @@ pub const EARTH_RADIUS: f64 = EARTH_CIRCUMFERENCE / 2.0 / PI;
 
 pub const MAX_ZOOM: u8 = 30;
 
+/// Maximum latitude (in degrees) representable in [`WebMercator`](https://epsg.io/3857)
+pub const MAX_MERCATOR_LAT: f64 = 85.051_128_779_806_6;
+
 mod decoders;
 pub use decoders::*;
+mod mvt;
+pub use mvt::{count_mvt_features, empty_mvt};
 mod rectangle;
 pub use rectangle::{TileRect, append_rect};
 
@@ -31,6 +36,39 @@ pub struct TileCoord {
 pub type TileData = Vec<u8>;
 pub type Tile = (TileCoord, Option<TileData>);
 
+/// Numbering scheme of the tile rows (`y`).
+#[derive(
+    Default, Debug, Clone, Copy, Hash, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TileScheme {
+    /// [Slippy map tilenames](https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames), `y` 0 is at the top (north), as used by web maps
+    #[default]
+    Xyz,
+    /// [Tile Map Service](https://wiki.openstreetmap.org/wiki/TMS), `y` 0 is at the bottom (south), as used by `MBTiles`
+    Tms,
+}
+
+impl TileScheme {
+    /// Converts between the `y` of this scheme and the xyz `y`, both ways.
+    #[must_use]
+    pub fn convert_y(self, z: u8, y: u32) -> u32 {
+        match self {
+            Self::Xyz => y,
+            Self::Tms => (1_u32 << z) - 1 - y,
+        }
+    }
+}
+
+impl Display for TileScheme {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Xyz => "xyz",
+            Self::Tms => "tms",
+        })
+    }
+}
+
 impl Display for TileCoord {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if f.alternate() {
@@ -69,9 +107,144 @@ impl TileCoord {
         let side_len = 1_u32 << z;
         x < side_len && y < side_len
     }
+
+    /// Returns the tile one zoom level up which contains this tile, or `None` at zoom `0`.
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        let z = self.z.checked_sub(1)?;
+        Some(Self::new_unchecked(z, self.x / 2, self.y / 2))
+    }
+
+    /// Returns the four tiles one zoom level down which make up this tile,
+    /// ordered top-left, top-right, bottom-left, bottom-right.
+    ///
+    /// The children of a tile at [`MAX_ZOOM`] are beyond the supported zoom range,
+    /// see [`Self::is_possible_on_zoom_level`].
+    #[must_use]
+    pub fn children(&self) -> [Self; 4] {
+        let (z, x, y) = (self.z + 1, self.x * 2, self.y * 2);
+        [
+            Self::new_unchecked(z, x, y),
+            Self::new_unchecked(z, x + 1, y),
+            Self::new_unchecked(z, x, y + 1),
+            Self::new_unchecked(z, x + 1, y + 1),
+        ]
+    }
+
+    /// Returns the up to eight tiles at the same zoom level surrounding this tile,
+    /// ordered row by row starting at the top-left neighbor.
+    ///
+    /// Longitude wraps around, so the tiles at `x = 0` and `x = 2^z - 1` are neighbors.
+    /// Latitude does not, so tiles in the top and bottom rows have only five neighbors.
+    /// At low zoom levels, a tile reached by wrapping in both directions is only returned once,
+    /// and the tile itself is never returned.
+    #[must_use]
+    pub fn neighbors(&self) -> Vec<Self> {
+        let side_len = 1_u64 << self.z;
+        let mut res = Vec::with_capacity(8);
+        for dy in [-1, 0, 1] {
+            let Some(y) = self
+                .y
+                .checked_add_signed(dy)
+                .filter(|y| u64::from(*y) < side_len)
+            else {
+                continue;
+            };
+            for dx in [-1, 0, 1] {
+                let x = (u64::from(self.x) + side_len).wrapping_add_signed(dx) % side_len;
+                let x = u32::try_from(x).expect("x is smaller than 2^MAX_ZOOM");
+                let tile = Self::new_unchecked(self.z, x, y);
+                if tile != *self && !res.contains(&tile) {
+                    res.push(tile);
+                }
+            }
+        }
+        res
+    }
+
+    /// Converts this tile to a [Bing Maps quadkey](https://learn.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system).
+    ///
+    /// The quadkey has one digit per zoom level, so the zoom `0` tile is the empty string.
+    #[must_use]
+    pub fn to_quadkey(self) -> String {
+        (1..=self.z)
+            .rev()
+            .map(|i| {
+                let mask = 1 << (i - 1);
+                let digit = u8::from(self.x & mask != 0) + 2 * u8::from(self.y & mask != 0);
+                char::from(b'0' + digit)
+            })
+            .collect()
+    }
+
+    /// Parses a [Bing Maps quadkey](https://learn.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system).
+    ///
+    /// Returns `None` if the quadkey contains anything but the digits `0`-`3`,
+    /// or if it is longer than [`MAX_ZOOM`].
+    #[must_use]
+    pub fn from_quadkey(quadkey: &str) -> Option<Self> {
+        let z = u8::try_from(quadkey.len())
+            .ok()
+            .filter(|z| *z <= MAX_ZOOM)?;
+        let (mut x, mut y) = (0, 0);
+        for digit in quadkey.bytes() {
+            let digit = match digit {
+                b'0'..=b'3' => u32::from(digit - b'0'),
+                _ => None?,
+            };
+            x = (x << 1) | (digit & 1);
+            y = (y << 1) | (digit >> 1);
+        }
+        Some(Self::new_unchecked(z, x, y))
+    }
+
+    /// Iterates over every tile at `zoom` covering a WGS84 `bbox` of `[min_lng, min_lat, max_lng, max_lat]`.
+    ///
+    /// Latitudes are clamped to ±[`MAX_MERCATOR_LAT`] and longitudes to ±180°,
+    /// so a bbox reaching the poles yields the top and bottom rows of tiles.
+    /// Tiles are yielded row by row, starting at the top-left corner.
+    ///
+    /// # Panics
+    /// Panics if `zoom` is greater than [`MAX_ZOOM`].
+    pub fn iter_bbox(zoom: u8, bbox: [f64; 4]) -> impl Iterator<Item = Self> {
+        assert!(zoom <= MAX_ZOOM, "zoom {zoom} must be <= {MAX_ZOOM}");
+
+        let lng = |v: f64| v.clamp(-180.0, 180.0);
+        let lat = |v: f64| v.clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT);
+        let [left, bottom, right, top] = bbox;
+        let (min_x, min_y, max_x, max_y) =
+            bbox_to_xyz(lng(left), lat(bottom), lng(right), lat(top), zoom);
+
+        (min_y..=max_y)
+            .flat_map(move |y| (min_x..=max_x).map(move |x| Self::new_unchecked(zoom, x, y)))
+    }
+
+    /// Returns the [`WebMercator`](https://epsg.io/3857) bounds of this tile in meters
+    /// as `[min_x, min_y, max_x, max_y]`.
+    ///
+    /// Tile rows are counted from the top (XYZ scheme), so `y = 0` touches the north edge of the map.
+    ///
+    /// # Panics
+    /// Panics if the zoom is greater than [`MAX_ZOOM`].
+    #[must_use]
+    pub fn mercator_bounds(&self) -> [f64; 4] {
+        assert!(self.z <= MAX_ZOOM, "zoom {} must be <= {MAX_ZOOM}", self.z);
+        let tile_length = EARTH_CIRCUMFERENCE / f64::from(1_u32 << self.z);
+        tile_bbox(self.x, self.y, tile_length)
+    }
+
+    /// Returns the [WGS84](https://epsg.io/4326) bounds of this tile in degrees
+    /// as `[min_lng, min_lat, max_lng, max_lat]`.
+    ///
+    /// # Panics
+    /// Panics if the zoom is greater than [`MAX_ZOOM`].
+    #[must_use]
+    pub fn lonlat_bounds(&self) -> [f64; 4] {
+        xyz_to_bbox(self.z, self.x, self.y, self.x, self.y)
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum Format {
     Gif,
     Jpeg,
@@ -204,7 +377,7 @@ impl Encoding {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct TileInfo {
     pub format: Format,
     pub encoding: Encoding,
@@ -794,6 +967,221 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tile_coord_parent() {
+        assert_eq!(TileCoord::new_unchecked(0, 0, 0).parent(), None);
+        assert_eq!(
+            TileCoord::new_unchecked(1, 1, 0).parent(),
+            Some(TileCoord::new_unchecked(0, 0, 0))
+        );
+        assert_eq!(
+            TileCoord::new_unchecked(5, 13, 6).parent(),
+            Some(TileCoord::new_unchecked(4, 6, 3))
+        );
+        let max = (1 << MAX_ZOOM) - 1;
+        assert_eq!(
+            TileCoord::new_unchecked(MAX_ZOOM, max, max).parent(),
+            Some(TileCoord::new_unchecked(MAX_ZOOM - 1, max / 2, max / 2))
+        );
+    }
+
+    #[test]
+    fn test_tile_coord_neighbors() {
+        let t = |x, y| TileCoord::new_unchecked(3, x, y);
+        assert_eq!(
+            t(4, 4).neighbors(),
+            [
+                t(3, 3),
+                t(4, 3),
+                t(5, 3),
+                t(3, 4),
+                t(5, 4),
+                t(3, 5),
+                t(4, 5),
+                t(5, 5)
+            ]
+        );
+        // wraps in x
+        assert_eq!(
+            t(0, 4).neighbors(),
+            [
+                t(7, 3),
+                t(0, 3),
+                t(1, 3),
+                t(7, 4),
+                t(1, 4),
+                t(7, 5),
+                t(0, 5),
+                t(1, 5)
+            ]
+        );
+        assert_eq!(
+            t(7, 4).neighbors(),
+            [
+                t(6, 3),
+                t(7, 3),
+                t(0, 3),
+                t(6, 4),
+                t(0, 4),
+                t(6, 5),
+                t(7, 5),
+                t(0, 5)
+            ]
+        );
+        // clamped in y
+        assert_eq!(
+            t(0, 0).neighbors(),
+            [t(7, 0), t(1, 0), t(7, 1), t(0, 1), t(1, 1)]
+        );
+        assert_eq!(
+            t(7, 7).neighbors(),
+            [t(6, 6), t(7, 6), t(0, 6), t(6, 7), t(0, 7)]
+        );
+        // wrapping in both directions reaches the same tile
+        let t = |x, y| TileCoord::new_unchecked(1, x, y);
+        assert_eq!(t(0, 0).neighbors(), [t(1, 0), t(1, 1), t(0, 1)]);
+        assert!(TileCoord::new_unchecked(0, 0, 0).neighbors().is_empty());
+        let max = (1 << MAX_ZOOM) - 1;
+        let t = |x, y| TileCoord::new_unchecked(MAX_ZOOM, x, y);
+        assert_eq!(
+            t(max, max).neighbors(),
+            [
+                t(max - 1, max - 1),
+                t(max, max - 1),
+                t(0, max - 1),
+                t(max - 1, max),
+                t(0, max)
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(0, 0, 0, [-0.5, -0.5, 0.5, 0.5])]
+    #[case(1, 0, 0, [-0.5, 0.0, 0.0, 0.5])]
+    #[case(1, 1, 1, [0.0, -0.5, 0.5, 0.0])]
+    #[case(2, 1, 3, [-0.25, -0.5, 0.0, -0.25])]
+    fn test_tile_coord_mercator_bounds(
+        #[case] z: u8,
+        #[case] x: u32,
+        #[case] y: u32,
+        #[case] expected: [f64; 4],
+    ) {
+        let bounds = TileCoord::new_unchecked(z, x, y).mercator_bounds();
+        for (actual, expected) in bounds.into_iter().zip(expected) {
+            assert_relative_eq!(actual, expected * EARTH_CIRCUMFERENCE, epsilon = 1e-6);
+        }
+    }
+
+    #[rstest]
+    #[case(0, 0, 0, [-180.0, -MAX_MERCATOR_LAT, 180.0, MAX_MERCATOR_LAT])]
+    #[case(1, 0, 0, [-180.0, 0.0, 0.0, MAX_MERCATOR_LAT])]
+    #[case(1, 1, 1, [0.0, -MAX_MERCATOR_LAT, 180.0, 0.0])]
+    #[case(2, 2, 1, [0.0, 0.0, 90.0, 66.513_260_443_111_8])]
+    fn test_tile_coord_lonlat_bounds(
+        #[case] z: u8,
+        #[case] x: u32,
+        #[case] y: u32,
+        #[case] expected: [f64; 4],
+    ) {
+        let bounds = TileCoord::new_unchecked(z, x, y).lonlat_bounds();
+        for (actual, expected) in bounds.into_iter().zip(expected) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_tile_coord_children() {
+        assert_eq!(
+            TileCoord::new_unchecked(0, 0, 0).children(),
+            [
+                TileCoord::new_unchecked(1, 0, 0),
+                TileCoord::new_unchecked(1, 1, 0),
+                TileCoord::new_unchecked(1, 0, 1),
+                TileCoord::new_unchecked(1, 1, 1),
+            ]
+        );
+        let tile = TileCoord::new_unchecked(5, 13, 6);
+        for child in tile.children() {
+            assert!(TileCoord::is_possible_on_zoom_level(
+                child.z, child.x, child.y
+            ));
+            assert_eq!(child.parent(), Some(tile));
+        }
+
+        let max = (1 << MAX_ZOOM) - 1;
+        let tile = TileCoord::new_unchecked(MAX_ZOOM, max, max);
+        for child in tile.children() {
+            assert!(!TileCoord::is_possible_on_zoom_level(
+                child.z, child.x, child.y
+            ));
+            assert_eq!(child.parent(), Some(tile));
+        }
+    }
+
+    #[rstest]
+    #[case(0, 0, 0, "")]
+    #[case(1, 1, 0, "1")]
+    #[case(1, 0, 1, "2")]
+    #[case(3, 3, 5, "213")]
+    #[case(3, 7, 7, "333")]
+    fn test_tile_coord_quadkey(
+        #[case] z: u8,
+        #[case] x: u32,
+        #[case] y: u32,
+        #[case] quadkey: &str,
+    ) {
+        let tile = TileCoord::new_unchecked(z, x, y);
+        assert_eq!(tile.to_quadkey(), quadkey);
+        assert_eq!(TileCoord::from_quadkey(quadkey), Some(tile));
+    }
+
+    #[test]
+    fn test_tile_coord_quadkey_roundtrip_max_zoom() {
+        let max = (1 << MAX_ZOOM) - 1;
+        let tile = TileCoord::new_unchecked(MAX_ZOOM, max, 0);
+        let quadkey = tile.to_quadkey();
+        assert_eq!(quadkey, "1".repeat(usize::from(MAX_ZOOM)));
+        assert_eq!(TileCoord::from_quadkey(&quadkey), Some(tile));
+    }
+
+    #[rstest]
+    #[case::invalid_digit("0124")]
+    #[case::non_digit("01a")]
+    #[case::non_ascii("0ä")]
+    #[case::too_long(&"0".repeat(usize::from(MAX_ZOOM) + 1))]
+    fn test_tile_coord_invalid_quadkey(#[case] quadkey: &str) {
+        assert_eq!(TileCoord::from_quadkey(quadkey), None);
+    }
+
+    #[rstest]
+    #[case::world(0, [-180.0, -90.0, 180.0, 90.0], 1)]
+    #[case::world_z1(1, [-180.0, -85.0, 180.0, 85.0], 4)]
+    #[case::beyond_poles(2, [-200.0, -90.0, 200.0, 90.0], 16)]
+    #[case::northern_hemisphere(2, [-180.0, 0.1, 180.0, 90.0], 8)]
+    #[case::point(10, [13.4, 52.5, 13.4, 52.5], 1)]
+    fn test_tile_coord_iter_bbox(
+        #[case] zoom: u8,
+        #[case] bbox: [f64; 4],
+        #[case] expected_count: usize,
+    ) {
+        let tiles: Vec<_> = TileCoord::iter_bbox(zoom, bbox).collect();
+        assert_eq!(tiles.len(), expected_count, "{bbox:?}@z{zoom}");
+        assert!(
+            tiles
+                .iter()
+                .all(|t| TileCoord::is_possible_on_zoom_level(t.z, t.x, t.y))
+        );
+    }
+
+    #[test]
+    fn test_tile_coord_iter_bbox_poles() {
+        let tiles: Vec<_> = TileCoord::iter_bbox(3, [0.0, 89.0, 10.0, 90.0]).collect();
+        assert_eq!(tiles, vec![TileCoord::new_unchecked(3, 4, 0)]);
+
+        let tiles: Vec<_> = TileCoord::iter_bbox(3, [0.0, -90.0, 10.0, -89.0]).collect();
+        assert_eq!(tiles, vec![TileCoord::new_unchecked(3, 4, 7)]);
+    }
+
     #[test]
     fn xyz_format() {
         let xyz = TileCoord { z: 1, x: 2, y: 3 };
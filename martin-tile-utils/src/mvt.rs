@@ -0,0 +1,152 @@
+use crate::TileData;
+
+/// Counts the features in all layers of an uncompressed Mapbox Vector Tile,
+/// or returns `None` if the data is not a valid protobuf message.
+///
+/// Only the `layers` (field 3 of `Tile`) and `features` (field 2 of `Layer`) are looked at,
+/// so no full protobuf decoder is needed.
+#[must_use]
+pub fn count_mvt_features(tile: &[u8]) -> Option<usize> {
+    let mut count = 0;
+    for field in ProtoFields(tile) {
+        if let (3, Some(layer)) = field? {
+            for feature in ProtoFields(layer) {
+                if let (2, Some(_)) = feature? {
+                    count += 1;
+                }
+            }
+        }
+    }
+    Some(count)
+}
+
+/// Encodes a vector tile with a single version 2 layer without features, using an extent of 4096.
+#[must_use]
+pub fn empty_mvt(layer: &str) -> TileData {
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "each byte is masked to 7 bits"
+    )]
+    fn push_varint(buf: &mut Vec<u8>, mut value: usize) {
+        while value >= 0x80 {
+            buf.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    // Layer fields: version (15) = 2, name (1) = layer, extent (5) = 4096
+    let mut layer_msg = vec![0x78, 0x02, 0x0a];
+    push_varint(&mut layer_msg, layer.len());
+    layer_msg.extend_from_slice(layer.as_bytes());
+    layer_msg.extend_from_slice(&[0x28, 0x80, 0x20]);
+
+    // Tile field: layers (3)
+    let mut tile = vec![0x1a];
+    push_varint(&mut tile, layer_msg.len());
+    tile.extend(layer_msg);
+    tile
+}
+
+/// Iterates over the top-level fields of a protobuf message,
+/// yielding the field number and the payload of length-delimited fields,
+/// or `None` once if the message is malformed.
+struct ProtoFields<'a>(&'a [u8]);
+
+impl<'a> Iterator for ProtoFields<'a> {
+    type Item = Option<(u64, Option<&'a [u8]>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.read_field();
+        if field.is_none() {
+            // stop after the first error
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+impl<'a> ProtoFields<'a> {
+    fn read_field(&mut self) -> Option<(u64, Option<&'a [u8]>)> {
+        let key = self.read_varint()?;
+        let payload = match key & 0x7 {
+            0 => {
+                self.read_varint()?;
+                None
+            }
+            1 => {
+                self.skip(8)?;
+                None
+            }
+            2 => {
+                let len = usize::try_from(self.read_varint()?).ok()?;
+                Some(self.skip(len)?)
+            }
+            5 => {
+                self.skip(4)?;
+                None
+            }
+            _ => return None,
+        };
+        Some((key >> 3, payload))
+    }
+
+    fn read_varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first()?;
+            self.0 = rest;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn skip(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tile with a layer `a` without features, and a layer `b` with two empty features
+    const MVT_TWO_FEATURES: &[u8] = include_bytes!("../fixtures/two-features.mvt");
+    /// A tile with a single layer `a` without features
+    const MVT_NO_FEATURES: &[u8] = include_bytes!("../fixtures/no-features.mvt");
+
+    #[test]
+    fn mvt_features() {
+        assert_eq!(count_mvt_features(MVT_TWO_FEATURES), Some(2));
+        assert_eq!(count_mvt_features(MVT_NO_FEATURES), Some(0));
+        assert_eq!(count_mvt_features(&[]), Some(0));
+        // truncated layer
+        assert_eq!(count_mvt_features(&MVT_TWO_FEATURES[..10]), None);
+    }
+
+    #[test]
+    fn empty_mvt_round_trip() {
+        let tile = empty_mvt("points");
+        assert_eq!(count_mvt_features(&tile), Some(0));
+
+        let layers: Vec<_> = ProtoFields(&tile).collect::<Option<_>>().unwrap();
+        let [(3, Some(layer))] = layers.as_slice() else {
+            panic!("expected a single layer, got {layers:?}");
+        };
+        let name = ProtoFields(layer)
+            .map(Option::unwrap)
+            .find_map(|(field, payload)| (field == 1).then_some(payload).flatten());
+        assert_eq!(name, Some(b"points".as_slice()));
+    }
+}
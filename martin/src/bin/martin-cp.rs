@@ -266,7 +266,7 @@ type MartinCpResult<T> = Result<T, MartinCpError>;
 
 #[derive(thiserror::Error, Debug)]
 enum MartinCpError {
-    #[error(transparent)]
+    #[error("{}", .0.with_context_chain())]
     Martin(#[from] MartinError),
     #[error("Unable to parse encodings argument: {0}")]
     EncodingParse(#[from] ParseError),
@@ -513,6 +513,7 @@ async fn init_schema(
             init_mbtiles_schema(&mut *conn, mbt_type)
                 .await
                 .map_err(MbtilesError::from)?;
+            mbt.invalidate_type_cache();
             let mut tj = merge_tilejson(sources, String::new());
             tj.other.insert(
                 "format".to_string(),
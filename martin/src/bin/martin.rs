@@ -81,9 +81,9 @@ async fn main() {
     if let Err(e) = start(args).await {
         // Ensure the message is printed, even if the logging is disabled
         if tracing::event_enabled!(tracing::Level::ERROR) {
-            error!("{e}");
+            error!(code = e.code(), "{}", e.with_context_chain());
         } else {
-            eprintln!("{e}");
+            eprintln!("{}", e.with_context_chain());
         }
         std::process::exit(1);
     }
@@ -1,3 +1,4 @@
+use crate::error::redact_password;
 use crate::{MartinError, MartinResult};
 
 #[derive(Debug, Clone)]
@@ -64,7 +65,7 @@ impl Arguments {
         let mut unrecognized = Vec::new();
         for (i, value) in self.values.into_iter().enumerate() {
             if let State::Ignore = self.state[i] {
-                unrecognized.push(value);
+                unrecognized.push(redact_password(&value));
             }
         }
         if unrecognized.is_empty() {
@@ -72,10 +72,15 @@ impl PostgresArgs {
                 default_srid,
                 auto_bounds: self.auto_bounds,
                 max_feature_count: self.max_feature_count,
+                validate_output: None,
                 pool_size: self.pool_size,
+                acquire_timeout: None,
+                search_path: OptOneMany::NoVals,
+                on_connect_sql: OptOneMany::NoVals,
                 auto_publish: OptBoolObj::NoValue,
                 tables: None,
                 functions: None,
+                tile_tables: None,
                 unrecognized: UnrecognizedValues::default(),
             })
             .collect();
@@ -25,6 +25,7 @@ use crate::config::file::fonts::FontConfig;
 use crate::config::file::{Config, OnInvalid};
 #[cfg(feature = "postgres")]
 use crate::config::primitives::env::Env;
+use crate::error::redact_password;
 
 /// Defines the styles used for the CLI help output.
 const HELP_STYLES: Styles = Styles::styled()
@@ -96,7 +97,10 @@ impl Args {
         #[cfg(feature = "postgres")] env: &impl Env<'a>,
     ) -> MartinResult<()> {
         if self.meta.config.is_some() && !self.meta.connection.is_empty() {
-            return Err(ConfigAndConnectionsError(self.meta.connection));
+            let connections = self.meta.connection.iter();
+            return Err(ConfigAndConnectionsError(
+                connections.map(|c| redact_password(c)).collect(),
+            ));
         }
 
         if self.srv.cache_size.is_some() {
@@ -375,6 +379,12 @@ mod tests {
             .merge_into_config(&mut config, &FauxEnv::default())
             .unwrap_err();
         assert!(matches!(err, ConfigAndConnectionsError(..)));
+
+        let args = Args::parse_from(["martin", "--config", "c.toml", "postgres://u:pw@h/db"]);
+        let err = args
+            .merge_into_config(&mut config, &FauxEnv::default())
+            .unwrap_err();
+        assert!(matches!(&err, ConfigAndConnectionsError(v) if v == &["postgres://u:***@h/db"]));
     }
 
     #[test]
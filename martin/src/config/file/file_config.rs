@@ -386,7 +386,8 @@ async fn resolve_one_source_int<T: TileSourceConfiguration>(
         configs.insert(id.clone(), source);
         result = custom
             .new_sources_url(id.clone(), url.clone(), cache)
-            .await?;
+            .await
+            .map_err(|e| e.context(format!("while loading source '{id}'")))?;
         info!("Configured {dup}source {id} from {}", sanitize_url(&url));
     } else {
         let can = source.abs_path()?;
@@ -395,7 +396,11 @@ async fn resolve_one_source_int<T: TileSourceConfiguration>(
         let id = idr.resolve(id, can.to_string_lossy().to_string());
         info!("Configured {dup}source {id} from {}", can.display());
         configs.insert(id.clone(), source.clone());
-        result = custom.new_sources(id, source.into_path(), cache).await?;
+        let path = source.into_path();
+        result = custom
+            .new_sources(id.clone(), path, cache)
+            .await
+            .map_err(|e| e.context(format!("while loading source '{id}'")))?;
     }
     Ok(result)
 }
@@ -439,7 +444,8 @@ async fn resolve_one_path_int<T: TileSourceConfiguration>(
         results.push(
             custom
                 .new_sources_url(id.clone(), url.clone(), default_cache)
-                .await?,
+                .await
+                .map_err(|e| e.context(format!("while loading source '{id}'")))?,
         );
         info!("Configured source {id} from URL {}", sanitize_url(&url));
     } else {
@@ -473,7 +479,11 @@ async fn resolve_one_path_int<T: TileSourceConfiguration>(
             info!("Configured source {id} from {}", can.display());
             files.insert(can);
             configs.insert(id.clone(), FileConfigSrc::Path(path.clone()));
-            results.push(custom.new_sources(id, path, default_cache).await?);
+            let src = custom
+                .new_sources(id.clone(), path, default_cache)
+                .await
+                .map_err(|e| e.context(format!("while loading source '{id}'")))?;
+            results.push(src);
         }
     }
     Ok(results)
@@ -404,10 +404,13 @@ impl Config {
         Vec<TileSourceWarning>,
     )> {
         let mut sources_and_warnings: Vec<BoxFuture<_>> = Vec::new();
+        // human-readable origin of each entry in `sources_and_warnings`, used in error messages
+        let mut origins: Vec<String> = Vec::new();
 
         #[cfg(feature = "postgres")]
-        for s in self.postgres.iter_mut() {
+        for (idx, s) in self.postgres.iter_mut().enumerate() {
             sources_and_warnings.push(Box::pin(s.resolve(idr.clone(), self.cache.policy())));
+            origins.push(format!("postgres configuration #{}", idx + 1));
         }
 
         #[cfg(feature = "pmtiles")]
@@ -424,6 +427,7 @@ impl Config {
             }
             let val = resolve_files(cfg, idr, &["pmtiles"], self.cache.policy());
             sources_and_warnings.push(Box::pin(val));
+            origins.push("pmtiles configuration".to_string());
         }
 
         #[cfg(feature = "mbtiles")]
@@ -431,6 +435,7 @@ impl Config {
             let cfg = &mut self.mbtiles;
             let val = resolve_files(cfg, idr, &["mbtiles"], self.cache.policy());
             sources_and_warnings.push(Box::pin(val));
+            origins.push("mbtiles configuration".to_string());
         }
 
         #[cfg(feature = "unstable-cog")]
@@ -438,11 +443,13 @@ impl Config {
             let cfg = &mut self.cog;
             let val = resolve_files(cfg, idr, &["tif", "tiff"], self.cache.policy());
             sources_and_warnings.push(Box::pin(val));
+            origins.push("cog configuration".to_string());
         }
 
         let all_results = try_join_all(sources_and_warnings).await?;
         let (all_tile_sources, all_tile_warnings): (Vec<_>, Vec<_>) =
             all_results.into_iter().unzip();
+        check_unique_source_ids(&origins, &all_tile_sources)?;
 
         Ok((
             all_tile_sources,
@@ -470,6 +477,31 @@ impl Config {
     }
 }
 
+/// Makes sure that no two resolved tile sources share the same ID.
+///
+/// The ID resolver renames colliding sources, but two sections can still produce the same ID,
+/// e.g. when the same file is configured under the same name in both `pmtiles` and `mbtiles`.
+/// Without this check, one of the sources would silently replace the other.
+#[cfg(feature = "_tiles")]
+fn check_unique_source_ids(
+    origins: &[String],
+    sources: &[Vec<martin_core::tiles::BoxedSource>],
+) -> MartinResult<()> {
+    let mut seen = std::collections::HashMap::new();
+    for (origin, group) in origins.iter().zip(sources) {
+        for src in group {
+            if let Some(first) = seen.insert(src.get_id(), origin) {
+                return Err(
+                    MartinError::DuplicateSourceId(src.get_id().to_string()).context(format!(
+                        "while merging sources of the {first} and the {origin}"
+                    )),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Describes the action to take during startup when configuration is found to be invalid
 /// but Martin could still startup in a degraded state (ie, some sources not served).
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize, ValueEnum)]
@@ -868,4 +900,36 @@ mod tests {
             Some(Duration::from_secs(1800))
         );
     }
+
+    #[cfg(feature = "_tiles")]
+    #[test]
+    fn duplicate_source_ids_name_both_origins() {
+        use martin_core::tiles::BoxedSource;
+        use tilejson::tilejson;
+
+        use crate::srv::tiles::tests::TestSource;
+
+        let src = |id: &'static str| -> BoxedSource {
+            Box::new(TestSource {
+                id,
+                tj: tilejson! { tiles: vec![] },
+                data: Vec::new(),
+            })
+        };
+        let origins = vec![
+            "pmtiles configuration".to_string(),
+            "mbtiles configuration".to_string(),
+        ];
+
+        let sources = vec![vec![src("a"), src("b")], vec![src("c")]];
+        assert!(check_unique_source_ids(&origins, &sources).is_ok());
+
+        let sources = vec![vec![src("a"), src("b")], vec![src("c"), src("b")]];
+        let err = check_unique_source_ids(&origins, &sources).unwrap_err();
+        assert_eq!(err.code(), "duplicate_source_id");
+        assert_eq!(
+            err.with_context_chain(),
+            "while merging sources of the pmtiles configuration and the mbtiles configuration: Source ID 'b' is used by more than one source"
+        );
+    }
 }
@@ -1,8 +1,8 @@
 use std::fmt::Debug;
 use std::path::PathBuf;
 
-use martin_core::tiles::BoxedSource;
 use martin_core::tiles::mbtiles::MbtSource;
+use martin_core::tiles::{BoxedSource, EmptyTileBehavior};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -12,8 +12,13 @@ use crate::config::file::{
     UnrecognizedValues,
 };
 
+#[serde_with::skip_serializing_none]
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct MbtConfig {
+    /// What to return for tiles missing from the files: `none` for an empty `204` response,
+    /// or `empty-blob` for a vector tile without features [DEFAULT: none]
+    pub empty_tile_behavior: Option<EmptyTileBehavior>,
+
     #[serde(flatten, skip_serializing)]
     pub unrecognized: UnrecognizedValues,
 }
@@ -34,7 +39,10 @@ impl TileSourceConfiguration for MbtConfig {
         path: PathBuf,
         cache: CachePolicy,
     ) -> MartinResult<BoxedSource> {
-        Ok(Box::new(MbtSource::new(id, path, cache.zoom()).await?))
+        let source = MbtSource::new(id, path, cache.zoom())
+            .await?
+            .with_empty_tile(self.empty_tile_behavior.unwrap_or_default());
+        Ok(Box::new(source))
     }
 
     async fn new_sources_url(
@@ -54,6 +62,7 @@ mod tests {
 
     use indoc::indoc;
     use martin_core::CacheZoomRange;
+    use martin_core::tiles::EmptyTileBehavior;
 
     use crate::config::file::mbtiles::MbtConfig;
     use crate::config::file::{
@@ -135,4 +144,21 @@ mod tests {
             ]))
         );
     }
+
+    #[test]
+    fn parse_empty_tile_behavior() {
+        let cfg = serde_yaml::from_str::<FileConfigEnum<MbtConfig>>(indoc! {"
+            paths: /dir-path
+            empty_tile_behavior: empty-blob
+        "})
+        .unwrap();
+        let FileConfigEnum::Config(cfg) = cfg else {
+            panic!();
+        };
+        assert_eq!(
+            cfg.custom.empty_tile_behavior,
+            Some(EmptyTileBehavior::EmptyBlob)
+        );
+        assert!(cfg.custom.get_unrecognized_keys().is_empty());
+    }
 }
@@ -4,20 +4,25 @@ use std::collections::{BTreeMap, HashSet};
 
 use futures::future::join_all;
 use itertools::Itertools as _;
-use martin_core::tiles::BoxedSource;
 use martin_core::tiles::postgres::{
-    PostgresError, PostgresPool, PostgresResult, PostgresSource, PostgresSqlInfo,
+    PostgresError, PostgresPool, PostgresPoolOptions, PostgresResult, PostgresSource,
+    PostgresSqlInfo,
 };
+use martin_core::tiles::{BoxedSource, Source as _};
+use postgres_protocol::escape::escape_identifier;
 use tracing::{debug, error, info, warn};
 
 use crate::config::args::BoundsCalcType;
 use crate::config::file::postgres::resolver::{
-    query_available_function, query_available_tables, table_to_query,
+    detect_tile_table_format, is_valid_extent, query_available_function, query_available_tables,
+    query_table_columns, table_to_query, tile_table_to_query, validate_function_return_type,
+};
+use crate::config::file::postgres::utils::{
+    clip_bounds, find_info, find_kv_ignore_case, normalize_key,
 };
-use crate::config::file::postgres::utils::{find_info, find_kv_ignore_case, normalize_key};
 use crate::config::file::postgres::{
     FuncInfoSources, FunctionInfo, POOL_SIZE_DEFAULT, PostgresCfgPublish, PostgresCfgPublishFuncs,
-    PostgresConfig, PostgresInfo, TableInfo, TableInfoSources,
+    PostgresConfig, PostgresInfo, TableInfo, TableInfoSources, TileTableInfoSources,
 };
 use crate::config::file::{CachePolicy, ConfigFileError, ConfigFileResult, TileSourceWarning};
 use crate::config::primitives::IdResolver;
@@ -42,12 +47,16 @@ pub struct PostgresAutoDiscoveryBuilder {
     ///
     /// Can be either a positive integer or unlimited if omitted.
     max_feature_count: Option<usize>,
+    /// Check that every source produces a non-empty and parseable tile before serving it
+    validate_output: bool,
     auto_functions: Option<PostgresAutoDiscoveryBuilderFunctions>,
     auto_tables: Option<PostgresAutoDiscoveryBuilderTables>,
     id_resolver: IdResolver,
     /// Associative arrays of table sources
     tables: TableInfoSources,
     functions: FuncInfoSources,
+    /// Associative arrays of tables with pre-generated tiles
+    tile_tables: TileTableInfoSources,
 }
 
 /// Configuration for auto-discovering `PostgreSQL` functions.
@@ -99,7 +108,7 @@ impl PostgresAutoDiscoveryBuilder {
         id_resolver: IdResolver,
         default_cache: CachePolicy,
     ) -> ConfigFileResult<Self> {
-        let pool = PostgresPool::new(
+        let pool = PostgresPool::new_with_options(
             config
                 .connection_string
                 .as_ref()
@@ -109,6 +118,9 @@ impl PostgresAutoDiscoveryBuilder {
             config.ssl_certificates.ssl_key.as_ref(),
             config.ssl_certificates.ssl_root_cert.as_ref(),
             config.pool_size.unwrap_or(POOL_SIZE_DEFAULT),
+            PostgresPoolOptions::default()
+                .with_acquire_timeout(config.acquire_timeout)
+                .with_on_connect_sql(on_connect_sql(config)),
         )
         .await
         .map_err(ConfigFileError::PostgresPoolCreationFailed)?;
@@ -121,9 +133,11 @@ impl PostgresAutoDiscoveryBuilder {
             default_cache,
             auto_bounds: config.auto_bounds.unwrap_or_default(),
             max_feature_count: config.max_feature_count,
+            validate_output: config.validate_output.unwrap_or_default(),
             id_resolver,
             tables: config.tables.clone().unwrap_or_default(),
             functions: config.functions.clone().unwrap_or_default(),
+            tile_tables: config.tile_tables.clone().unwrap_or_default(),
             auto_functions,
             auto_tables,
         })
@@ -160,10 +174,17 @@ impl PostgresAutoDiscoveryBuilder {
         let mut pending = Vec::new();
         for (id, cfg_inf) in &self.tables {
             // TODO: move this validation to serde somehow?
-            if cfg_inf.extent == Some(0) {
-                return Err(PostgresError::InvalidTableExtent(
+            check_extent(id, &cfg_inf.format_id(), cfg_inf.extent)?;
+            if let Some(tolerance) = cfg_inf
+                .simplify
+                .iter()
+                .flat_map(BTreeMap::values)
+                .find(|v| !v.is_finite() || **v < 0.0)
+            {
+                return Err(PostgresError::InvalidTableSimplify(
                     id.clone(),
                     cfg_inf.format_id(),
+                    *tolerance,
                 ));
             }
 
@@ -193,6 +214,11 @@ impl PostgresAutoDiscoveryBuilder {
 
         // Sort the discovered sources by schema, table and geometry column to ensure a consistent behavior
         if let Some(auto_tables) = &self.auto_tables {
+            check_extent(
+                &auto_tables.source_id_format,
+                "auto_publish.tables",
+                auto_tables.extent,
+            )?;
             let schemas = auto_tables
                 .schemas
                 .clone()
@@ -221,8 +247,12 @@ impl PostgresAutoDiscoveryBuilder {
                             .replace("{table}", &table)
                             .replace("{column}", &geom_column);
                         let id2 = self.resolve_id(&source_id, &db_inf);
-                        let Some(srid) = db_inf.calc_srid(&id2, 0, self.default_srid) else {
-                            continue;
+                        let srid = match db_inf.calc_srid(&id2, 0, self.default_srid) {
+                            Ok(srid) => srid,
+                            Err(error) => {
+                                warn!("{error}");
+                                continue;
+                            }
                         };
                         db_inf.srid = srid;
                         update_auto_fields(&id2, &mut db_inf, auto_tables);
@@ -261,6 +291,9 @@ impl PostgresAutoDiscoveryBuilder {
             }
         }
 
+        let res = self
+            .validate_sources(res, &mut info_map, &mut warnings)
+            .await;
         Ok((res, info_map, warnings))
     }
 
@@ -294,6 +327,14 @@ impl PostgresAutoDiscoveryBuilder {
                     info_map.insert(id2, merged_inf);
                 }
                 Err(error) => {
+                    // Fail fast if the function exists, but can never produce a tile
+                    validate_function_return_type(
+                        &self.pool,
+                        id,
+                        &cfg_inf.schema,
+                        &cfg_inf.function,
+                    )
+                    .await?;
                     warnings.push(TileSourceWarning::SourceError {
                         source_id: id.clone(),
                         error,
@@ -343,6 +384,84 @@ impl PostgresAutoDiscoveryBuilder {
                 }
             }
         }
+        let res = self
+            .validate_sources(res, &mut info_map, &mut warnings)
+            .await;
+        Ok((res, info_map, warnings))
+    }
+
+    /// Instantiates the configured sources of pre-generated tiles.
+    ///
+    /// Unlike tables and functions, these are never auto-discovered.
+    pub async fn instantiate_tile_tables(
+        &self,
+    ) -> PostgresResult<(
+        Vec<BoxedSource>,
+        TileTableInfoSources,
+        Vec<TileSourceWarning>,
+    )> {
+        let mut warnings = Vec::new();
+        let mut res = Vec::new();
+        let mut info_map = TileTableInfoSources::new();
+
+        for (id, cfg_inf) in &self.tile_tables {
+            let columns =
+                match query_table_columns(&self.pool, &cfg_inf.schema, &cfg_inf.table).await {
+                    Ok(columns) => columns,
+                    Err(error) => {
+                        warnings.push(TileSourceWarning::SourceError {
+                            source_id: id.clone(),
+                            error: error.to_string(),
+                        });
+                        continue;
+                    }
+                };
+            let merged_inf = match cfg_inf.resolve_columns(id, &columns) {
+                Ok(merged_inf) => merged_inf,
+                Err(error) => {
+                    warnings.push(TileSourceWarning::SourceError {
+                        source_id: id.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            let mut pg_sql_info = tile_table_to_query(id, &merged_inf);
+            match detect_tile_table_format(&self.pool, &merged_inf).await {
+                Ok(Some(tile_info)) => pg_sql_info = pg_sql_info.with_tile_info(tile_info),
+                Ok(None) => warn!(
+                    "Source {id} has no tiles in {}.{}, assuming uncompressed MVT",
+                    merged_inf.schema, merged_inf.table
+                ),
+                Err(error) => {
+                    warnings.push(TileSourceWarning::SourceError {
+                        source_id: id.clone(),
+                        error: error.to_string(),
+                    });
+                    continue;
+                }
+            }
+
+            let id2 = self.resolve_id(id, &merged_inf);
+            self.add_func_src(
+                &mut res,
+                id2.clone(),
+                &merged_inf,
+                pg_sql_info.clone(),
+                merged_inf.cache.unwrap_or_default(),
+            );
+            warn_on_rename(id, &id2, "Tile table");
+            info!(
+                "Configured source {id2} from pre-generated {} tiles {}",
+                pg_sql_info.tile_info, pg_sql_info.signature
+            );
+            debug!("{id2} query: {}", pg_sql_info.sql_query);
+            info_map.insert(id2, merged_inf);
+        }
+
+        let res = self
+            .validate_sources(res, &mut info_map, &mut warnings)
+            .await;
         Ok((res, info_map, warnings))
     }
 
@@ -377,9 +496,22 @@ impl PostgresAutoDiscoveryBuilder {
             "geometry column",
             id,
         )?;
-        let merged_table_info = table_info_for_geometry_column
-            .append_cfg_info(table_info_from_config, id, self.default_srid)
-            .ok_or_else(|| format!("Failed to merge config info for table {id}"))?;
+        let mut merged_table_info = table_info_for_geometry_column.append_cfg_info(
+            table_info_from_config,
+            id,
+            self.default_srid,
+        )?;
+        for column in table_info_from_config
+            .extra_geometry_columns
+            .iter()
+            .flatten()
+        {
+            let extra_info = find_info(table_infos_for_table, column, "geometry column", id)?;
+            let srid = extra_info.calc_srid(id, 0, self.default_srid)?;
+            merged_table_info
+                .extra_geometries
+                .push((extra_info.geometry_column.clone(), srid));
+        }
         Ok(merged_table_info)
     }
 
@@ -415,7 +547,25 @@ impl PostgresAutoDiscoveryBuilder {
             find_info(function_infos_for_schema, function_name, "function", id)?;
         let merged_function_info =
             table_info_from_schema.append_cfg_info(function_info_from_config);
-        Ok((merged_function_info, function_sql_info.clone()))
+        let function_sql_info = function_sql_info
+            .clone()
+            .with_statement_cache(merged_function_info.statement_cache.unwrap_or(true))
+            .with_read_only(merged_function_info.read_only.unwrap_or_default())
+            .with_url_query_allowlist(merged_function_info.query_params.clone())
+            .with_compression(merged_function_info.compression)
+            .with_empty_tile(merged_function_info.empty_tile_behavior.unwrap_or_default())
+            .with_clip_bounds(clip_bounds(
+                id,
+                merged_function_info.clip_to_bounds,
+                merged_function_info.bounds,
+            ));
+        if merged_function_info.query_params.is_some() && !function_sql_info.use_url_query {
+            warn!(
+                "Function source {id} sets query_params, but {} has no json argument to receive them",
+                function_sql_info.signature
+            );
+        }
+        Ok((merged_function_info, function_sql_info))
     }
 
     fn resolve_id<T: PostgresInfo>(&self, id: &str, src_inf: &T) -> String {
@@ -425,7 +575,7 @@ impl PostgresAutoDiscoveryBuilder {
 
     fn add_func_src(
         &self,
-        sources: &mut Vec<BoxedSource>,
+        sources: &mut Vec<PostgresSource>,
         id: String,
         pg_info: &impl PostgresInfo,
         sql_info: PostgresSqlInfo,
@@ -434,7 +584,39 @@ impl PostgresAutoDiscoveryBuilder {
         let tilejson = pg_info.to_tilejson(id.clone());
         let cache = cache.or(self.default_cache);
         let source = PostgresSource::new(id, sql_info, tilejson, self.pool.clone(), cache.zoom());
-        sources.push(Box::new(source));
+        sources.push(source);
+    }
+
+    /// Boxes the sources, dropping the ones that fail [`PostgresSource::validate_output`]
+    /// with a warning if output validation is enabled.
+    async fn validate_sources<T>(
+        &self,
+        sources: Vec<PostgresSource>,
+        info_map: &mut BTreeMap<String, T>,
+        warnings: &mut Vec<TileSourceWarning>,
+    ) -> Vec<BoxedSource> {
+        if !self.validate_output {
+            return sources
+                .into_iter()
+                .map(|s| Box::new(s) as BoxedSource)
+                .collect();
+        }
+        let results = join_all(sources.iter().map(PostgresSource::validate_output)).await;
+        let mut res = Vec::new();
+        for (source, result) in sources.into_iter().zip(results) {
+            match result {
+                Ok(()) => res.push(Box::new(source) as BoxedSource),
+                Err(error) => {
+                    let source_id = source.get_id().to_string();
+                    info_map.remove(&source_id);
+                    warnings.push(TileSourceWarning::SourceError {
+                        source_id,
+                        error: error.to_string(),
+                    });
+                }
+            }
+        }
+        res
     }
 
     fn configured_tables(&self) -> HashSet<(String, String)> {
@@ -515,6 +697,22 @@ fn update_auto_fields(
     );
 }
 
+/// SQL statements to run on every new connection: setting the configured `search_path` first,
+/// followed by the user-provided `on_connect_sql` statements.
+fn on_connect_sql(config: &PostgresConfig) -> Vec<String> {
+    let search_path = config
+        .search_path
+        .iter()
+        .map(|s| escape_identifier(s))
+        .join(", ");
+    let search_path =
+        (!search_path.is_empty()).then(|| format!("SET search_path TO {search_path}"));
+    search_path
+        .into_iter()
+        .chain(config.on_connect_sql.iter().cloned())
+        .collect()
+}
+
 fn calc_auto(
     config: &PostgresConfig,
 ) -> (
@@ -577,7 +775,9 @@ fn calc_auto(
 
 fn use_auto_publish(config: &PostgresConfig, for_functions: bool) -> bool {
     match &config.auto_publish {
-        NoValue => config.tables.is_none() && config.functions.is_none(),
+        NoValue => {
+            config.tables.is_none() && config.functions.is_none() && config.tile_tables.is_none()
+        }
         Object(funcs) => {
             if for_functions {
                 // If auto_publish.functions is set, and currently asking for .tables which is missing,
@@ -599,6 +799,23 @@ fn use_auto_publish(config: &PostgresConfig, for_functions: bool) -> bool {
     }
 }
 
+/// Reject a zero extent, and warn about extents that do not line up with the clients' rendering grid.
+fn check_extent(id: &str, table: &str, extent: Option<u32>) -> PostgresResult<()> {
+    match extent {
+        Some(0) => Err(PostgresError::InvalidTableExtent(
+            id.to_string(),
+            table.to_string(),
+        )),
+        Some(extent) if !is_valid_extent(extent) => {
+            warn!(
+                "Extent {extent} of source {id} for table {table} is not a power of two between 64 and 65536, geometries may be distorted when rendered"
+            );
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
 fn warn_on_rename(old_id: &String, new_id: &String, typ: &str) {
     if old_id != new_id {
         warn!("{typ} source {old_id} was renamed to {new_id} due to ID conflict");
@@ -657,6 +874,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_on_connect_sql() {
+        let parse = |content: &str| on_connect_sql(&serde_yaml::from_str(content).unwrap());
+        assert!(parse("{}").is_empty());
+        assert_eq!(
+            parse("{search_path: tiles, on_connect_sql: SET statement_timeout = '1s'}"),
+            vec![
+                r#"SET search_path TO "tiles""#.to_string(),
+                "SET statement_timeout = '1s'".to_string(),
+            ]
+        );
+        assert_eq!(
+            parse(r#"search_path: [my "schema", public]"#),
+            vec![r#"SET search_path TO "my ""schema""", "public""#.to_string()]
+        );
+    }
+
     #[test]
     #[expect(clippy::too_many_lines)]
     fn test_auto_publish_no_auto() {
@@ -887,4 +1121,58 @@ mod tests {
             ),
         }
     }
+
+    #[tokio::test]
+    async fn test_function_with_wrong_return_type_fails() {
+        use testcontainers_modules::postgres::Postgres;
+        use testcontainers_modules::testcontainers::ImageExt as _;
+        use testcontainers_modules::testcontainers::runners::AsyncRunner as _;
+
+        let container = Postgres::default()
+            .with_name("postgis/postgis")
+            .with_tag("11-3.0") // purposely very old and stable
+            .start()
+            .await
+            .expect("container launched");
+
+        let host = container.get_host().await.unwrap();
+        let port = container.get_host_port_ipv4(5432).await.unwrap();
+
+        let config_yaml = indoc! {r"
+            functions:
+              text_function:
+                schema: public
+                function: text_function
+        "};
+        let mut config: PostgresConfig = serde_yaml::from_str(config_yaml).unwrap();
+        config.connection_string = Some(format!(
+            "postgres://postgres:postgres@{host}:{port}/postgres?sslmode=disable"
+        ));
+
+        let builder = PostgresAutoDiscoveryBuilder::new(
+            &config,
+            IdResolver::default(),
+            CachePolicy::default(),
+        )
+        .await
+        .expect("Failed to create builder");
+        builder
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .batch_execute(
+                "CREATE FUNCTION public.text_function(z integer, x integer, y integer) RETURNS text
+                 AS $$ SELECT 'not a tile' $$ LANGUAGE sql IMMUTABLE",
+            )
+            .await
+            .unwrap();
+
+        let err = builder.instantiate_functions().await.unwrap_err();
+        assert!(
+            matches!(&err, PostgresError::InvalidFunctionSignature(id, func, ret)
+                if id == "text_function" && func == "public.text_function" && ret == "text"),
+            "unexpected error: {err}"
+        );
+    }
 }
@@ -1,7 +1,7 @@
 use std::ops::Add as _;
 use std::time::Duration;
 
-use futures::future::try_join;
+use futures::future::try_join3;
 use futures::pin_mut;
 use martin_core::tiles::BoxedSource;
 use serde::{Deserialize, Serialize};
@@ -9,7 +9,7 @@ use tilejson::TileJSON;
 use tokio::time::timeout;
 use tracing::warn;
 
-use super::{FuncInfoSources, TableInfoSources};
+use super::{FuncInfoSources, TableInfoSources, TileTableInfoSources};
 use crate::MartinResult;
 use crate::config::args::{BoundsCalcType, DEFAULT_BOUNDS_TIMEOUT};
 use crate::config::file::postgres::PostgresAutoDiscoveryBuilder;
@@ -60,8 +60,31 @@ pub struct PostgresConfig {
     ///
     /// Can be either a positive integer or unlimited if omitted.
     pub max_feature_count: Option<usize>,
+    /// Fetch one tile of every source at startup, and treat sources that return an empty
+    /// or unparseable tile as invalid, see `on_invalid`. [DEFAULT: false]
+    ///
+    /// The tile is the one containing the `TileJSON` center at its zoom, or if the source has no center,
+    /// the one containing the center of its bounds at its minimum zoom.
+    /// This catches a wrong geometry column, SRID or filter at startup instead of on the first request.
+    pub validate_output: Option<bool>,
     /// Maximum Postgres connections pool size [DEFAULT: 20]
     pub pool_size: Option<usize>,
+    /// Maximum time a tile request waits for a connection if the pool is exhausted.
+    ///
+    /// Requests that time out are answered with `503 Service Unavailable` instead of piling up.
+    /// Supports human-readable formats: "500ms", "5s". Waits indefinitely if omitted.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "humantime_serde"
+    )]
+    pub acquire_timeout: Option<Duration>,
+    /// Schemas to look up unqualified names in, set as the `search_path` of every new connection
+    #[serde(default, skip_serializing_if = "OptOneMany::is_none")]
+    pub search_path: OptOneMany<String>,
+    /// SQL statements to run on every new connection, e.g. to set `search_path` or `statement_timeout`
+    #[serde(default, skip_serializing_if = "OptOneMany::is_none")]
+    pub on_connect_sql: OptOneMany<String>,
     /// Enable/disable/configure automatic discovery of tables and functions.
     ///
     /// You may set this to `OptBoolObj::Bool(false)` to disable.
@@ -71,6 +94,8 @@ pub struct PostgresConfig {
     pub tables: Option<TableInfoSources>,
     /// Associative arrays of function sources
     pub functions: Option<FuncInfoSources>,
+    /// Associative arrays of tables with pre-generated tiles
+    pub tile_tables: Option<TileTableInfoSources>,
 
     #[serde(flatten, skip_serializing)]
     pub unrecognized: UnrecognizedValues,
@@ -192,20 +217,37 @@ impl PostgresConfig {
                 }
             },
         );
-        let ((mut tables, tbl_info, mut tbl_warnings), (funcs, func_info, func_warnings)) =
-            try_join(inst_tables, pg.instantiate_functions()).await?;
+        let (
+            (mut tables, tbl_info, mut tbl_warnings),
+            (funcs, func_info, func_warnings),
+            (tile_tables, tile_tbl_info, tile_tbl_warnings),
+        ) = try_join3(
+            inst_tables,
+            pg.instantiate_functions(),
+            pg.instantiate_tile_tables(),
+        )
+        .await?;
 
         self.tables = Some(tbl_info);
         self.functions = Some(func_info);
+        if !tile_tbl_info.is_empty() {
+            self.tile_tables = Some(tile_tbl_info);
+        }
         tables.extend(funcs);
+        tables.extend(tile_tables);
         tbl_warnings.extend(func_warnings);
+        tbl_warnings.extend(tile_tbl_warnings);
         Ok((tables, tbl_warnings))
     }
 }
 
 impl ConfigurationLivecycleHooks for PostgresConfig {
     fn finalize(&mut self) -> ConfigFileResult<()> {
-        if self.tables.is_none() && self.functions.is_none() && self.auto_publish.is_none() {
+        if self.tables.is_none()
+            && self.functions.is_none()
+            && self.tile_tables.is_none()
+            && self.auto_publish.is_none()
+        {
             self.auto_publish = OptBoolObj::Bool(true);
         }
 
@@ -244,6 +286,15 @@ impl ConfigurationLivecycleHooks for PostgresConfig {
                 );
             }
         }
+        if let Some(ref ts) = self.tile_tables {
+            for (k, v) in ts {
+                copy_unrecognized_keys_from_config(
+                    &mut keys,
+                    &format!("tile_tables.{k}."),
+                    &v.unrecognized,
+                );
+            }
+        }
 
         keys.extend(
             self.ssl_certificates
@@ -286,10 +337,11 @@ mod tests {
     use std::path::Path;
 
     use indoc::indoc;
+    use martin_tile_utils::TileScheme;
     use tilejson::Bounds;
 
     use super::*;
-    use crate::config::file::postgres::{FunctionInfo, TableInfo};
+    use crate::config::file::postgres::{FunctionInfo, TableInfo, TileTableInfo};
     use crate::config::file::{Config, parse_config};
     use crate::config::primitives::OptOneMany::{Many, One};
     use crate::config::primitives::env::FauxEnv;
@@ -361,6 +413,9 @@ mod tests {
               connection_string: 'postgres://postgres@localhost:5432/db'
               default_srid: 4326
               pool_size: 20
+              acquire_timeout: 5s
+              search_path: [tiles, public]
+              on_connect_sql: SET statement_timeout = '10s'
               max_feature_count: 100
 
               tables:
@@ -369,6 +424,7 @@ mod tests {
                   table: table_source
                   srid: 4326
                   geometry_column: geom
+                  extra_geometry_columns: [centroid]
                   id_column: ~
                   minzoom: 0
                   maxzoom: 30
@@ -387,12 +443,16 @@ mod tests {
                   minzoom: 0
                   maxzoom: 30
                   bounds: [-180.0, -90.0, 180.0, 90.0]
+                  statement_cache: false
         "},
             &Config {
                 postgres: One(PostgresConfig {
                     connection_string: Some("postgres://postgres@localhost:5432/db".to_string()),
                     default_srid: Some(4326),
                     pool_size: Some(20),
+                    acquire_timeout: Some(Duration::from_secs(5)),
+                    search_path: Many(vec!["tiles".to_string(), "public".to_string()]),
+                    on_connect_sql: One("SET statement_timeout = '10s'".to_string()),
                     max_feature_count: Some(100),
                     tables: Some(BTreeMap::from([(
                         "table_source".to_string(),
@@ -401,6 +461,7 @@ mod tests {
                             table: "table_source".to_string(),
                             srid: 4326,
                             geometry_column: "geom".to_string(),
+                            extra_geometry_columns: Some(vec!["centroid".to_string()]),
                             minzoom: Some(0),
                             maxzoom: Some(30),
                             bounds: Some([-180, -90, 180, 90].into()),
@@ -417,13 +478,51 @@ mod tests {
                     )])),
                     functions: Some(BTreeMap::from([(
                         "function_zxy_query".to_string(),
-                        FunctionInfo::new_extended(
-                            "public".to_string(),
-                            "function_zxy_query".to_string(),
-                            0,
-                            30,
-                            Bounds::MAX,
-                        ),
+                        FunctionInfo {
+                            statement_cache: Some(false),
+                            ..FunctionInfo::new_extended(
+                                "public".to_string(),
+                                "function_zxy_query".to_string(),
+                                0,
+                                30,
+                                Bounds::MAX,
+                            )
+                        },
+                    )])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_pg_tile_tables() {
+        assert_config(
+            indoc! {"
+            postgres:
+              connection_string: 'postgres://postgres@localhost:5432/db'
+              tile_tables:
+                basemap:
+                  schema: public
+                  table: tiles
+                  y_column: row
+                  scheme: xyz
+                  maxzoom: 14
+        "},
+            &Config {
+                postgres: One(PostgresConfig {
+                    connection_string: Some("postgres://postgres@localhost:5432/db".to_string()),
+                    tile_tables: Some(BTreeMap::from([(
+                        "basemap".to_string(),
+                        TileTableInfo {
+                            schema: "public".to_string(),
+                            table: "tiles".to_string(),
+                            y_column: Some("row".to_string()),
+                            scheme: Some(TileScheme::Xyz),
+                            maxzoom: Some(14),
+                            ..Default::default()
+                        },
                     )])),
                     ..Default::default()
                 }),
@@ -1,5 +1,7 @@
 use std::collections::BTreeMap;
 
+use martin_core::tiles::EmptyTileBehavior;
+use martin_core::tiles::postgres::{PostgresCompression, UrlQueryParamType};
 use serde::{Deserialize, Serialize};
 use tilejson::{Bounds, TileJSON};
 
@@ -33,6 +35,26 @@ pub struct FunctionInfo {
     /// Zoom-level bounds for tile caching.
     pub cache: Option<CachePolicy>,
 
+    /// Reuse the prepared tile query on each connection instead of re-preparing it per request [DEFAULT: true]
+    pub statement_cache: Option<bool>,
+
+    /// Run each tile query in a `READ ONLY` transaction [DEFAULT: false]
+    pub read_only: Option<bool>,
+
+    /// Compress generated tiles larger than 1 KiB before returning them, e.g. `gzip`
+    pub compression: Option<PostgresCompression>,
+
+    /// What to return for tiles without data: `none` for an empty `204` response,
+    /// or `empty-blob` for a vector tile without features [DEFAULT: none]
+    pub empty_tile_behavior: Option<EmptyTileBehavior>,
+
+    /// Return empty tiles outside of the `bounds` without querying the database [DEFAULT: false]
+    pub clip_to_bounds: Option<bool>,
+
+    /// URL query parameters passed to the function's `json` argument, with their types.
+    /// If set, other parameters are dropped and values of the wrong type are rejected.
+    pub query_params: Option<BTreeMap<String, UrlQueryParamType>>,
+
     /// `TileJSON` provided by the SQL function comment. Not serialized.
     #[serde(skip)]
     pub tilejson: Option<serde_json::Value>,
@@ -1,5 +1,7 @@
 use std::collections::{BTreeMap, HashMap};
 
+use martin_core::tiles::EmptyTileBehavior;
+use martin_core::tiles::postgres::PostgresCompression;
 use serde::{Deserialize, Serialize};
 use tilejson::{Bounds, TileJSON, VectorLayer};
 use tracing::{info, warn};
@@ -13,7 +15,9 @@ pub type TableInfoSources = BTreeMap<String, TableInfo>;
 #[serde_with::skip_serializing_none]
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
 pub struct TableInfo {
-    /// ID of the layer as specified in a tile (`ST_AsMVT` parameter)
+    /// ID of the layer as specified in a tile (`ST_AsMVT` parameter), defaults to the source ID.
+    /// It must match the `source-layer` of the style layers using this source.
+    #[serde(alias = "layer_name")]
     pub layer_id: Option<String>,
 
     /// Table schema
@@ -28,6 +32,15 @@ pub struct TableInfo {
     /// Geometry column name
     pub geometry_column: String,
 
+    /// Additional geometry columns of the same table.
+    ///
+    /// Each one is encoded as its own layer of the tile, named after the column.
+    pub extra_geometry_columns: Option<Vec<String>>,
+
+    /// Resolved names and SRIDs of the `extra_geometry_columns`
+    #[serde(skip)]
+    pub extra_geometries: Vec<(String, i32)>,
+
     /// Geometry column has a spatial index
     #[serde(skip)]
     pub geometry_index: Option<bool>,
@@ -39,7 +52,10 @@ pub struct TableInfo {
     #[serde(skip)]
     pub relkind: Option<char>,
 
-    /// Feature id column name
+    /// Feature id column name, emitted as the id of each MVT feature.
+    /// Must be an integer column. Also accepted as `promote_id`, matching the style's `promoteId`
+    /// when the id is used for feature state.
+    #[serde(alias = "promote_id")]
     pub id_column: Option<String>,
 
     /// An integer specifying the minimum zoom level
@@ -63,12 +79,42 @@ pub struct TableInfo {
     /// Boolean to control if geometries should be clipped or encoded as is
     pub clip_geom: Option<bool>,
 
+    /// Mapping of zoom levels to `ST_SimplifyPreserveTopology` tolerances in web mercator meters.
+    ///
+    /// Each tolerance applies from its zoom level up to the next configured one.
+    /// Zoom levels below the lowest configured one are not simplified.
+    pub simplify: Option<BTreeMap<u8, f64>>,
+
+    /// How features are selected for a tile: `bbox` to pre-filter with the `&&` bounding box operator
+    /// and let `ST_AsMVTGeom` clip the geometries, or `intersects` to use `ST_Intersects` [DEFAULT: bbox]
+    pub spatial_filter: Option<SpatialFilter>,
+
+    /// SQL boolean expression to filter features before they are encoded, e.g. `population > 1000`.
+    /// It is validated with `EXPLAIN` when the source is created.
+    pub filter: Option<String>,
+
     /// Geometry type
     pub geometry_type: Option<String>,
 
     /// Zoom-level bounds for tile caching.
     pub cache: Option<CachePolicy>,
 
+    /// Reuse the prepared tile query on each connection instead of re-preparing it per request [DEFAULT: true]
+    pub statement_cache: Option<bool>,
+
+    /// Run each tile query in a `READ ONLY` transaction [DEFAULT: false]
+    pub read_only: Option<bool>,
+
+    /// Compress generated tiles larger than 1 KiB before returning them, e.g. `gzip`
+    pub compression: Option<PostgresCompression>,
+
+    /// What to return for tiles without data: `none` for an empty `204` response,
+    /// or `empty-blob` for a vector tile without features [DEFAULT: none]
+    pub empty_tile_behavior: Option<EmptyTileBehavior>,
+
+    /// Return empty tiles outside of the `bounds` (configured or computed) without querying the database [DEFAULT: false]
+    pub clip_to_bounds: Option<bool>,
+
     /// List of columns, that should be encoded as tile properties
     pub properties: Option<BTreeMap<String, String>>,
 
@@ -84,6 +130,20 @@ pub struct TableInfo {
     pub tilejson: Option<serde_json::Value>,
 }
 
+/// Spatial predicate the tile query of a table source uses to select the features of a tile.
+///
+/// Both can use a `GiST` index on the geometry column.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpatialFilter {
+    /// `geom && envelope`, only comparing bounding boxes.
+    /// Features whose bounding box overlaps the tile but whose geometry does not are clipped away.
+    #[default]
+    Bbox,
+    /// `ST_Intersects(geom, envelope)`, which runs an exact intersection test after the index scan.
+    Intersects,
+}
+
 impl PostgresInfo for TableInfo {
     fn format_id(&self) -> String {
         format!("{}.{}.{}", self.schema, self.table, self.geometry_column)
@@ -115,20 +175,33 @@ impl PostgresInfo for TableInfo {
             minzoom: None,
             other: BTreeMap::default(),
         };
-        tilejson.vector_layers = Some(vec![layer]);
+        let mut layers = vec![layer];
+        for (column, _) in &self.extra_geometries {
+            layers.push(VectorLayer {
+                id: column.clone(),
+                fields: self.properties.clone().unwrap_or_default(),
+                description: None,
+                maxzoom: None,
+                minzoom: None,
+                other: BTreeMap::default(),
+            });
+        }
+        tilejson.vector_layers = Some(layers);
         patch_json(tilejson, self.tilejson.as_ref())
     }
 }
 
+/// Column types that can be used as MVT feature ids by `ST_AsMVT`
+const INTEGER_TYPES: [&str; 3] = ["int2", "int4", "int8"];
+
 impl TableInfo {
     /// For a given table info discovered from the database, append the configuration info provided by the user
-    #[must_use]
     pub fn append_cfg_info(
         &self,
         cfg_inf: &Self,
         new_id: &String,
         default_srid: Option<i32>,
-    ) -> Option<Self> {
+    ) -> Result<Self, String> {
         // Assume cfg_inf and self have the same schema/table/geometry_column
         let mut inf = Self {
             // These values must match the database exactly
@@ -155,52 +228,164 @@ impl TableInfo {
             _ => {}
         }
 
+        if let Some(layer_id) = &cfg_inf.layer_id {
+            if layer_id.trim().is_empty() {
+                return Err(format!("Layer id of source {new_id} must not be empty"));
+            }
+            if !layer_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                warn!(
+                    "Layer id '{layer_id}' of source {new_id} contains characters other than ASCII letters, digits, '_' and '-', which some clients do not handle well"
+                );
+            }
+        }
+
         let empty = BTreeMap::new();
         let props = self.properties.as_ref().unwrap_or(&empty);
 
         if let Some(id_column) = &cfg_inf.id_column {
-            let prop = normalize_key(props, id_column.as_str(), "id_column", new_id)?;
+            let prop = normalize_key(props, id_column.as_str(), "id_column", new_id)
+                .ok_or_else(|| format!("Failed to merge config info for table {new_id}"))?;
+            if let Some(typ) = props.get(&prop)
+                && !INTEGER_TYPES.contains(&typ.as_str())
+            {
+                return Err(format!(
+                    "Feature id column {prop} of source {new_id} has type {typ}, but MVT feature ids must be one of {}",
+                    INTEGER_TYPES.join(", ")
+                ));
+            }
             inf.prop_mapping.insert(id_column.clone(), prop);
         }
 
         if let Some(p) = &cfg_inf.properties {
             for key in p.keys() {
-                let prop = normalize_key(props, key.as_str(), "property", new_id)?;
+                let prop = normalize_key(props, key.as_str(), "property", new_id)
+                    .ok_or_else(|| format!("Failed to merge config info for table {new_id}"))?;
                 inf.prop_mapping.insert(key.clone(), prop);
             }
         }
 
-        Some(inf)
+        Ok(inf)
     }
 
-    /// Determine the SRID value to use for a table, or None if unknown, assuming self is a table info from the database
+    /// Determine the SRID value to use for a table, assuming self is a table info from the database
     ///
     /// Tries to use `default_srid` if a spatial table has SRID 0.
-    #[must_use]
-    pub fn calc_srid(&self, new_id: &str, cfg_srid: i32, default_srid: Option<i32>) -> Option<i32> {
+    /// Returns a descriptive error if the SRID is unknown or conflicts with the configured one,
+    /// because serving such a table would produce misplaced geometries.
+    pub fn calc_srid(
+        &self,
+        new_id: &str,
+        cfg_srid: i32,
+        default_srid: Option<i32>,
+    ) -> Result<i32, String> {
         match (self.srid, cfg_srid, default_srid) {
             (0, 0, Some(default_srid)) => {
                 info!(
                     "Table {} has SRID=0, using provided default SRID={default_srid}",
                     self.format_id()
                 );
-                Some(default_srid)
-            }
-            (0, 0, None) => {
-                let info = "To use this table source, set default or specify this table SRID in the config file, or set the default SRID with  --default-srid=...";
-                warn!("Table {} has SRID=0, skipping. {info}", self.format_id());
-                None
+                Ok(default_srid)
             }
-            (0, cfg, _) => Some(cfg), // Use the configured SRID
-            (src, 0, _) => Some(src), // Use the source SRID
-            (src, cfg, _) if src != cfg => {
-                warn!(
-                    "Table {} has SRID={src}, but source {new_id} has SRID={cfg}",
-                    self.format_id()
-                );
-                None
-            }
-            (_, cfg, _) => Some(cfg),
+            (0, 0, None) => Err(format!(
+                "Table {} has SRID=0, skipping. To use this table source, set default or specify this table SRID in the config file, or set the default SRID with  --default-srid=...",
+                self.format_id()
+            )),
+            (0, cfg, _) => Ok(cfg), // Use the configured SRID
+            (src, 0, _) => Ok(src), // Use the source SRID
+            (src, cfg, _) if src != cfg => Err(format!(
+                "Table {} has SRID={src}, but source {new_id} has SRID={cfg}",
+                self.format_id()
+            )),
+            (_, cfg, _) => Ok(cfg),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_info(srid: i32) -> TableInfo {
+        TableInfo {
+            schema: "public".to_string(),
+            table: "points".to_string(),
+            geometry_column: "geom".to_string(),
+            srid,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn id_column_type() {
+        let mut db = db_info(4326);
+        db.properties = Some(BTreeMap::from([
+            ("gid".to_string(), "int4".to_string()),
+            ("name".to_string(), "text".to_string()),
+        ]));
+        let cfg: TableInfo = serde_yaml::from_str(
+            "schema: public\ntable: points\ngeometry_column: geom\nsrid: 4326\npromote_id: gid",
+        )
+        .unwrap();
+        let inf = db.append_cfg_info(&cfg, &"src".to_string(), None).unwrap();
+        assert_eq!(inf.id_column.as_deref(), Some("gid"));
+        assert_eq!(inf.prop_mapping["gid"], "gid");
+
+        let cfg = TableInfo {
+            id_column: Some("name".to_string()),
+            ..cfg
+        };
+        let err = db
+            .append_cfg_info(&cfg, &"src".to_string(), None)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Feature id column name of source src has type text, but MVT feature ids must be one of int2, int4, int8"
+        );
+    }
+
+    #[test]
+    fn layer_id() {
+        let db = db_info(4326);
+        let cfg: TableInfo = serde_yaml::from_str(
+            "schema: public\ntable: points\ngeometry_column: geom\nsrid: 4326\nlayer_name: pois",
+        )
+        .unwrap();
+        let inf = db.append_cfg_info(&cfg, &"src".to_string(), None).unwrap();
+        assert_eq!(inf.layer_id.as_deref(), Some("pois"));
+        assert_eq!(
+            inf.to_tilejson("src".to_string()).vector_layers.unwrap()[0].id,
+            "pois"
+        );
+
+        let cfg = TableInfo {
+            layer_id: Some(" ".to_string()),
+            ..cfg
+        };
+        let err = db
+            .append_cfg_info(&cfg, &"src".to_string(), None)
+            .unwrap_err();
+        assert_eq!(err, "Layer id of source src must not be empty");
+    }
+
+    #[test]
+    fn calc_srid() {
+        assert_eq!(db_info(4326).calc_srid("src", 0, None), Ok(4326));
+        assert_eq!(db_info(4326).calc_srid("src", 4326, None), Ok(4326));
+        assert_eq!(db_info(0).calc_srid("src", 3857, None), Ok(3857));
+        assert_eq!(db_info(0).calc_srid("src", 0, Some(900_913)), Ok(900_913));
+
+        let err = db_info(0).calc_srid("src", 0, None).unwrap_err();
+        assert!(
+            err.starts_with("Table public.points.geom has SRID=0"),
+            "{err}"
+        );
+        let err = db_info(4326).calc_srid("src", 3857, None).unwrap_err();
+        assert_eq!(
+            err,
+            "Table public.points.geom has SRID=4326, but source src has SRID=3857"
+        );
+    }
+}
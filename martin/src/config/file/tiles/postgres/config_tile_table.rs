@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+
+use martin_core::tiles::EmptyTileBehavior;
+use martin_tile_utils::TileScheme;
+use serde::{Deserialize, Serialize};
+use tilejson::{Bounds, TileJSON};
+
+use super::PostgresInfo;
+use crate::config::file::{CachePolicy, UnrecognizedValues};
+
+pub type TileTableInfoSources = BTreeMap<String, TileTableInfo>;
+
+/// Column names of the `MBTiles` tiles table, which uses the TMS scheme
+const MBTILES_COLUMNS: [&str; 3] = ["zoom_level", "tile_column", "tile_row"];
+/// Column names commonly used for tiles in the XYZ scheme
+const XYZ_COLUMNS: [&str; 3] = ["z", "x", "y"];
+
+/// A table or view with pre-generated tiles, served as is without calling `ST_AsMVT`.
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct TileTableInfo {
+    /// Table schema
+    pub schema: String,
+
+    /// Table name
+    pub table: String,
+
+    /// `bytea` column with the tile data.
+    /// Detected if the table has exactly one `bytea` column.
+    pub tile_column: Option<String>,
+
+    /// Zoom level column. Detected if the table has `zoom_level` or `z` columns.
+    pub zoom_column: Option<String>,
+
+    /// Tile column (x) column. Detected if the table has `tile_column` or `x` columns.
+    pub x_column: Option<String>,
+
+    /// Tile row (y) column. Detected if the table has `tile_row` or `y` columns.
+    pub y_column: Option<String>,
+
+    /// Numbering scheme of the tile rows.
+    /// Defaults to `tms` if the row column is named `tile_row` like in `MBTiles`, and to `xyz` otherwise.
+    pub scheme: Option<TileScheme>,
+
+    /// An integer specifying the minimum zoom level
+    pub minzoom: Option<u8>,
+
+    /// An integer specifying the maximum zoom level. MUST be >= minzoom
+    pub maxzoom: Option<u8>,
+
+    /// The maximum extent of available map tiles. Bounds MUST define an area
+    /// covered by all zoom levels. The bounds are represented in WGS:84
+    /// latitude and longitude values, in the order left, bottom, right, top.
+    /// Values may be integers or floating point numbers.
+    pub bounds: Option<Bounds>,
+
+    /// Zoom-level bounds for tile caching.
+    pub cache: Option<CachePolicy>,
+
+    /// Reuse the prepared tile query on each connection instead of re-preparing it per request [DEFAULT: true]
+    pub statement_cache: Option<bool>,
+
+    /// Run each tile query in a `READ ONLY` transaction [DEFAULT: false]
+    pub read_only: Option<bool>,
+
+    /// What to return for tiles without data: `none` for an empty `204` response,
+    /// or `empty-blob` for a vector tile without features [DEFAULT: none]
+    pub empty_tile_behavior: Option<EmptyTileBehavior>,
+
+    /// Return empty tiles outside of the `bounds` without querying the database [DEFAULT: false]
+    pub clip_to_bounds: Option<bool>,
+
+    #[serde(flatten, skip_serializing)]
+    pub unrecognized: UnrecognizedValues,
+}
+
+impl PostgresInfo for TileTableInfo {
+    fn format_id(&self) -> String {
+        format!(
+            "{}.{}.{}",
+            self.schema,
+            self.table,
+            self.tile_column.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn to_tilejson(&self, source_id: String) -> TileJSON {
+        let mut tilejson = tilejson::tilejson! {
+            tiles: vec![],  // tile source is required, but not yet known
+            name: source_id,
+            description: self.format_id(),
+        };
+        tilejson.minzoom = self.minzoom;
+        tilejson.maxzoom = self.maxzoom;
+        tilejson.bounds = self.bounds;
+        tilejson
+    }
+}
+
+impl TileTableInfo {
+    /// Fills in the tile, zoom, x and y columns and the scheme that were not configured,
+    /// given the names and types of the table's columns as discovered from the database.
+    ///
+    /// Returns an error message if a column cannot be detected or does not exist.
+    pub fn resolve_columns(
+        &self,
+        id: &str,
+        columns: &BTreeMap<String, String>,
+    ) -> Result<Self, String> {
+        let table = format!("{}.{}", self.schema, self.table);
+        if columns.is_empty() {
+            return Err(format!(
+                "Table {table} of the source {id} does not exist or has no columns"
+            ));
+        }
+
+        let tile_column = if let Some(column) = &self.tile_column {
+            column.clone()
+        } else {
+            let mut bytea = columns.iter().filter(|(_, typ)| *typ == "bytea");
+            match (bytea.next(), bytea.next()) {
+                (Some((column, _)), None) => column.clone(),
+                _ => {
+                    return Err(format!(
+                        "Unable to detect the tile data column of the source {id}, table {table} must have exactly one bytea column or set tile_column"
+                    ));
+                }
+            }
+        };
+        match columns.get(&tile_column).map(String::as_str) {
+            Some("bytea") => {}
+            Some(typ) => {
+                return Err(format!(
+                    "Tile data column {tile_column} of the source {id} has type {typ}, but must be bytea"
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "Tile data column {tile_column} of the source {id} does not exist in table {table}"
+                ));
+            }
+        }
+
+        let detected = [MBTILES_COLUMNS, XYZ_COLUMNS]
+            .into_iter()
+            .find(|names| names.iter().all(|n| columns.contains_key(*n)));
+        let mut coord_columns = Vec::with_capacity(3);
+        for (idx, (configured, kind)) in [
+            (&self.zoom_column, "zoom_column"),
+            (&self.x_column, "x_column"),
+            (&self.y_column, "y_column"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let column = match (configured, detected) {
+                (Some(column), _) => column.clone(),
+                (None, Some(names)) => names[idx].to_string(),
+                (None, None) => {
+                    return Err(format!(
+                        "Unable to detect the {kind} of the source {id}, table {table} has neither {} nor {} columns",
+                        MBTILES_COLUMNS.join("/"),
+                        XYZ_COLUMNS.join("/"),
+                    ));
+                }
+            };
+            if !columns.contains_key(&column) {
+                return Err(format!(
+                    "Column {column} of the source {id} does not exist in table {table}"
+                ));
+            }
+            coord_columns.push(column);
+        }
+        let [zoom_column, x_column, y_column] =
+            <[String; 3]>::try_from(coord_columns).expect("three coordinate columns");
+
+        let scheme = self.scheme.unwrap_or(if y_column == MBTILES_COLUMNS[2] {
+            TileScheme::Tms
+        } else {
+            TileScheme::Xyz
+        });
+
+        Ok(Self {
+            tile_column: Some(tile_column),
+            zoom_column: Some(zoom_column),
+            x_column: Some(x_column),
+            y_column: Some(y_column),
+            scheme: Some(scheme),
+            ..self.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(cols: &[(&str, &str)]) -> BTreeMap<String, String> {
+        cols.iter()
+            .map(|(n, t)| ((*n).to_string(), (*t).to_string()))
+            .collect()
+    }
+
+    fn info() -> TileTableInfo {
+        TileTableInfo {
+            schema: "public".to_string(),
+            table: "tiles".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resolve_mbtiles_columns() {
+        let cols = columns(&[
+            ("zoom_level", "integer"),
+            ("tile_column", "integer"),
+            ("tile_row", "integer"),
+            ("tile_data", "bytea"),
+        ]);
+        let inf = info().resolve_columns("src", &cols).unwrap();
+        assert_eq!(inf.tile_column.as_deref(), Some("tile_data"));
+        assert_eq!(inf.zoom_column.as_deref(), Some("zoom_level"));
+        assert_eq!(inf.x_column.as_deref(), Some("tile_column"));
+        assert_eq!(inf.y_column.as_deref(), Some("tile_row"));
+        assert_eq!(inf.scheme, Some(TileScheme::Tms));
+        assert_eq!(inf.format_id(), "public.tiles.tile_data");
+    }
+
+    #[test]
+    fn resolve_xyz_columns() {
+        let cols = columns(&[
+            ("z", "smallint"),
+            ("x", "integer"),
+            ("y", "integer"),
+            ("mvt", "bytea"),
+            ("gzipped", "bytea"),
+        ]);
+        let err = info().resolve_columns("src", &cols).unwrap_err();
+        assert!(err.contains("exactly one bytea column"), "{err}");
+
+        let cfg = TileTableInfo {
+            tile_column: Some("mvt".to_string()),
+            ..info()
+        };
+        let inf = cfg.resolve_columns("src", &cols).unwrap();
+        assert_eq!(inf.zoom_column.as_deref(), Some("z"));
+        assert_eq!(inf.scheme, Some(TileScheme::Xyz));
+
+        let cfg = TileTableInfo {
+            tile_column: Some("z".to_string()),
+            ..info()
+        };
+        let err = cfg.resolve_columns("src", &cols).unwrap_err();
+        assert!(err.contains("must be bytea"), "{err}");
+    }
+
+    #[test]
+    fn resolve_configured_columns() {
+        let cols = columns(&[
+            ("lvl", "integer"),
+            ("col", "integer"),
+            ("tile_row", "integer"),
+            ("data", "bytea"),
+        ]);
+        let err = info().resolve_columns("src", &cols).unwrap_err();
+        assert!(err.contains("Unable to detect the zoom_column"), "{err}");
+
+        let cfg = TileTableInfo {
+            zoom_column: Some("lvl".to_string()),
+            x_column: Some("col".to_string()),
+            y_column: Some("tile_row".to_string()),
+            scheme: Some(TileScheme::Xyz),
+            ..info()
+        };
+        let inf = cfg.resolve_columns("src", &cols).unwrap();
+        assert_eq!(inf.tile_column.as_deref(), Some("data"));
+        assert_eq!(inf.scheme, Some(TileScheme::Xyz));
+
+        let cfg = TileTableInfo {
+            y_column: Some("row".to_string()),
+            ..cfg
+        };
+        let err = cfg.resolve_columns("src", &cols).unwrap_err();
+        assert!(err.contains("Column row"), "{err}");
+
+        let err = info().resolve_columns("src", &BTreeMap::new()).unwrap_err();
+        assert!(err.contains("does not exist"), "{err}");
+    }
+}
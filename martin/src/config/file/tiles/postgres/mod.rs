@@ -7,6 +7,9 @@ pub use config_function::*;
 mod config_table;
 pub use config_table::*;
 
+mod config_tile_table;
+pub use config_tile_table::*;
+
 pub(crate) mod utils;
 
 mod builder;
@@ -1,5 +1,7 @@
 mod query_functions;
 mod query_tables;
+mod query_tile_tables;
 
-pub use query_functions::query_available_function;
-pub use query_tables::{query_available_tables, table_to_query};
+pub use query_functions::{query_available_function, validate_function_return_type};
+pub use query_tables::{is_valid_extent, query_available_tables, table_to_query};
+pub use query_tile_tables::{detect_tile_table_format, query_table_columns, tile_table_to_query};
@@ -4,8 +4,10 @@ use std::collections::BTreeMap;
 use std::fmt::Write as _;
 use std::iter::zip;
 
-use martin_core::tiles::postgres::PostgresError::PostgresError;
-use martin_core::tiles::postgres::{PostgresPool, PostgresResult, PostgresSqlInfo};
+use martin_core::tiles::postgres::PostgresError::{InvalidFunctionSignature, PostgresError};
+use martin_core::tiles::postgres::{
+    PostgresPool, PostgresQueryKind, PostgresResult, PostgresSqlInfo,
+};
 use postgres_protocol::escape::escape_identifier;
 use serde_json::Value;
 use tracing::{debug, warn};
@@ -107,6 +109,7 @@ pub async fn query_available_function(pool: &PostgresPool) -> PostgresResult<Sql
                     (
                         PostgresSqlInfo::new(
                             query,
+                            PostgresQueryKind::Function,
                             input_types.len() == 4,
                             format!(
                                 "{schema}.{function}({}) -> {ret_inf}",
@@ -124,6 +127,44 @@ pub async fn query_available_function(pool: &PostgresPool) -> PostgresResult<Sql
     Ok(res)
 }
 
+/// Checks that a configured function, if it exists, returns a tile,
+/// i.e. either `bytea` or a record with a `bytea` column.
+///
+/// Functions with the right return type but other issues, e.g. wrong arguments, are not reported here.
+pub async fn validate_function_return_type(
+    pool: &PostgresPool,
+    id: &str,
+    schema: &str,
+    function: &str,
+) -> PostgresResult<()> {
+    let rows = pool
+        .get()
+        .await?
+        .query(
+            r"
+SELECT pg_get_function_result(pg_proc.oid) AS result,
+       pg_proc.prorettype IN ('bytea'::regtype, 'record'::regtype) AS valid
+FROM pg_proc
+INNER JOIN pg_namespace ON pg_proc.pronamespace = pg_namespace.oid
+WHERE lower(pg_namespace.nspname) = lower($1) AND lower(pg_proc.proname) = lower($2)",
+            &[&schema, &function],
+        )
+        .await
+        .map_err(|e| PostgresError(e, "querying function return types"))?;
+
+    if rows.iter().any(|row| row.get::<_, bool>("valid")) {
+        return Ok(());
+    }
+    match rows.first() {
+        Some(row) => Err(InvalidFunctionSignature(
+            id.to_string(),
+            format!("{schema}.{function}"),
+            row.get("result"),
+        )),
+        None => Ok(()),
+    }
+}
+
 fn jsonb_to_vec(jsonb: Option<Value>) -> Option<Vec<String>> {
     jsonb.map(|json| {
         json.as_array()
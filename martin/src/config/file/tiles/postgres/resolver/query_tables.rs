@@ -3,8 +3,11 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 
 use futures::pin_mut;
-use martin_core::tiles::postgres::PostgresError::PostgresError;
-use martin_core::tiles::postgres::{PostgresPool, PostgresResult, PostgresSqlInfo};
+use itertools::Itertools as _;
+use martin_core::tiles::postgres::PostgresError::{InvalidTableFilter, PostgresError};
+use martin_core::tiles::postgres::{
+    PostgresPool, PostgresQueryKind, PostgresResult, PostgresSqlInfo,
+};
 use martin_tile_utils::EARTH_CIRCUMFERENCE_DEGREES;
 use postgis::ewkb;
 use postgres_protocol::escape::{escape_identifier, escape_literal};
@@ -14,12 +17,15 @@ use tokio::time::timeout;
 use tracing::{debug, warn};
 
 use crate::config::args::{BoundsCalcType, DEFAULT_BOUNDS_TIMEOUT};
-use crate::config::file::postgres::{PostgresInfo as _, TableInfo};
+use crate::config::file::postgres::utils::clip_bounds;
+use crate::config::file::postgres::{PostgresInfo as _, SpatialFilter, TableInfo};
 
 /// Map of `PostgreSQL` tables organized by schema, table, and geometry column.
 pub type SqlTableInfoMapMapMap = BTreeMap<String, BTreeMap<String, BTreeMap<String, TableInfo>>>;
 
 const DEFAULT_EXTENT: u32 = 4096;
+const MIN_EXTENT: u32 = 64;
+const MAX_EXTENT: u32 = 65536;
 const DEFAULT_BUFFER: u32 = 64;
 const DEFAULT_CLIP_GEOM: bool = true;
 
@@ -109,6 +115,13 @@ pub async fn query_available_tables(
     Ok(res)
 }
 
+/// MVT extent should be a power of two, otherwise the tile grid does not line up with
+/// the clients' rendering grid and features get distorted.
+#[must_use]
+pub fn is_valid_extent(extent: u32) -> bool {
+    extent.is_power_of_two() && (MIN_EXTENT..=MAX_EXTENT).contains(&extent)
+}
+
 /// Generate an SQL snippet to escape a column name, and optionally alias it.
 /// Assumes to not be the first column in a SELECT statement.
 fn escape_with_alias(mapping: &HashMap<String, String>, field: &str) -> String {
@@ -139,39 +152,16 @@ pub async fn table_to_query(
     let srid = info.srid;
 
     if info.bounds.is_none() {
-        match bounds_type {
-            BoundsCalcType::Skip => {}
-            BoundsCalcType::Calc => {
-                debug!("Computing {} table bounds for {id}", info.format_id());
-                info.bounds = calc_bounds(&pool, &schema, &table, &geometry_column, srid).await?;
-            }
-            BoundsCalcType::Quick => {
-                debug!(
-                    "Computing {} table bounds with {}s timeout for {id}",
-                    info.format_id(),
-                    DEFAULT_BOUNDS_TIMEOUT.as_secs()
-                );
-                let bounds = calc_bounds(&pool, &schema, &table, &geometry_column, srid);
-                pin_mut!(bounds);
-                if let Ok(bounds) = timeout(DEFAULT_BOUNDS_TIMEOUT, &mut bounds).await {
-                    info.bounds = bounds?;
-                } else {
-                    warn!(
-                        "Timeout computing {} bounds for {id}, aborting query. Use --auto-bounds=calc to wait until complete, or check the table for missing indices.",
-                        info.format_id(),
-                    );
-                }
-            }
-        }
-
-        if let Some(bounds) = info.bounds {
-            debug!(
-                "The computed bounds for {id} from {} are {bounds}",
-                info.format_id()
-            );
-        }
+        info.bounds = table_bounds(&pool, &id, &info, bounds_type).await?;
     }
 
+    let filter_clause = if let Some(filter) = &info.filter {
+        validate_filter(&pool, &id, &schema, &table, filter).await?;
+        format!("\n    AND ({filter})")
+    } else {
+        String::new()
+    };
+
     let properties = if let Some(props) = &info.properties {
         props
             .keys()
@@ -192,65 +182,232 @@ pub async fn table_to_query(
 
     let extent = info.extent.unwrap_or(DEFAULT_EXTENT);
     let buffer = info.buffer.unwrap_or(DEFAULT_BUFFER);
-    let margin = f64::from(buffer) / f64::from(extent);
-
-    // When calculating the bounding box to search within, a few considerations must be made when
-    // using a margin. The ST_TileEnvelope margin parameter is for use with SRID 3857.
-    // For SRID 4326, ST_Expand is used and provided with SRID 4326 specific units (degrees).
-    // If the table uses a non-standard SRID, it will fall back to existing behavior.
-    //
-    // For more context, if SRID 4326 were to be used with ST_TileEnvelope and margin
-    // parameter, the resultant bounding box for tiles on the antimeridian would be calculated
-    // incorrectly. For example, with a margin of 2 units, the antimeridian edge would transform
-    // from -180 to +178. This results in a bbox that stretches from the easternmost edge of a tile
-    // (plus margin) around the map to the westernmost edge of the tile (minus margin). The
-    // resulting bbox covers none of the original tile. In contrast, for this example, ST_Expand
-    // will result in a westernmost edge (minus margin) of -182.
-    let bbox_search = if buffer == 0 {
-        format!("ST_Transform(ST_TileEnvelope($1::integer, $2::integer, $3::integer), {srid})")
-    } else if pool.supports_tile_margin() && srid == 3857 {
-        format!(
-            "ST_Transform(ST_TileEnvelope($1::integer, $2::integer, $3::integer, margin => {margin}), {srid})"
-        )
-    } else if srid == 4326 {
-        format!(
-            "ST_Expand(ST_Transform(ST_TileEnvelope($1::integer, $2::integer, $3::integer), {srid}), ({margin} * {EARTH_CIRCUMFERENCE_DEGREES}) / 2^$1::integer)"
-        )
+    let buffer = if buffer > extent {
+        warn!(
+            "Buffer {buffer} of source {id} exceeds its extent {extent}, clamping the buffer to {extent}"
+        );
+        extent
     } else {
-        format!("ST_Transform(ST_TileEnvelope($1::integer, $2::integer, $3::integer), {srid})")
+        buffer
+    };
+    let layer = LayerQuery {
+        schema: &schema,
+        table: &table,
+        extent,
+        buffer,
+        clip_geom: info.clip_geom.unwrap_or(DEFAULT_CLIP_GEOM),
+        simplify_tolerance: info.simplify.as_ref().and_then(simplify_tolerance_sql),
+        spatial_filter: info.spatial_filter.unwrap_or_default(),
+        supports_tile_margin: pool.supports_tile_margin(),
+        id_name: &id_name,
+        fields: &format!("{id_field}{properties}"),
+        filter_clause: &filter_clause,
+        limit_clause: &max_feature_count.map_or(String::new(), |v| format!("LIMIT {v}")),
     };
 
-    let limit_clause = max_feature_count.map_or(String::new(), |v| format!("LIMIT {v}"));
     let layer_id = escape_literal(info.layer_id.as_ref().unwrap_or(&id));
-    let clip_geom = info.clip_geom.unwrap_or(DEFAULT_CLIP_GEOM);
-    let query = format!(
-        r"
+    let query = layer.sql(&layer_id, &geometry_column, srid);
+    let query = if info.extra_geometries.is_empty() {
+        format!("{query};")
+    } else {
+        // Each geometry column is encoded as its own layer. MVT layers can be concatenated as is.
+        let mut layers = vec![query];
+        for (column, srid) in &info.extra_geometries {
+            layers.push(layer.sql(&escape_literal(column), &escape_identifier(column), *srid));
+        }
+        let layers = layers
+            .iter()
+            .map(|layer| format!("COALESCE(({layer}), ''::bytea)"))
+            .collect::<Vec<_>>()
+            .join("\n||\n");
+        format!("SELECT\n{layers};")
+    };
+
+    let sql_info = PostgresSqlInfo::new(query, PostgresQueryKind::Table, false, info.format_id())
+        .with_statement_cache(info.statement_cache.unwrap_or(true))
+        .with_read_only(info.read_only.unwrap_or_default())
+        .with_compression(info.compression)
+        .with_empty_tile(info.empty_tile_behavior.unwrap_or_default())
+        .with_clip_bounds(clip_bounds(&id, info.clip_to_bounds, info.bounds));
+    Ok((id, sql_info, info))
+}
+
+/// The parts of a table tile query that are shared by all geometry columns (layers) of a table.
+struct LayerQuery<'a> {
+    schema: &'a str,
+    table: &'a str,
+    extent: u32,
+    buffer: u32,
+    clip_geom: bool,
+    simplify_tolerance: Option<String>,
+    spatial_filter: SpatialFilter,
+    supports_tile_margin: bool,
+    id_name: &'a str,
+    fields: &'a str,
+    filter_clause: &'a str,
+    limit_clause: &'a str,
+}
+
+impl LayerQuery<'_> {
+    /// Generate the query encoding a single geometry column as an MVT layer.
+    fn sql(&self, layer_id: &str, geometry_column: &str, srid: i32) -> String {
+        let Self {
+            schema,
+            table,
+            extent,
+            buffer,
+            clip_geom,
+            id_name,
+            fields,
+            filter_clause,
+            limit_clause,
+            ..
+        } = self;
+        let bbox_search = self.bbox_search(srid);
+        let spatial_filter = match self.spatial_filter {
+            SpatialFilter::Bbox => format!("{geometry_column} && {bbox_search}"),
+            SpatialFilter::Intersects => format!("ST_Intersects({geometry_column}, {bbox_search})"),
+        };
+        // Tiles are always in web mercator, so only reproject geometries stored in other SRIDs
+        let geometry = if srid == 3857 {
+            format!("ST_CurveToLine({geometry_column}::geometry)")
+        } else {
+            format!("ST_Transform(ST_CurveToLine({geometry_column}::geometry), 3857)")
+        };
+        let geometry = if let Some(tolerance) = &self.simplify_tolerance {
+            format!("ST_SimplifyPreserveTopology({geometry}, {tolerance})")
+        } else {
+            geometry
+        };
+
+        format!(
+            r"
 SELECT
   ST_AsMVT(tile, {layer_id}, {extent}, 'geom'{id_name})
 FROM (
   SELECT
     ST_AsMVTGeom(
-        ST_Transform(ST_CurveToLine({geometry_column}::geometry), 3857),
+        {geometry},
         ST_TileEnvelope($1::integer, $2::integer, $3::integer),
         {extent}, {buffer}, {clip_geom}
     ) AS geom
-    {id_field}{properties}
+    {fields}
   FROM
     {schema}.{table}
   WHERE
-    {geometry_column} && {bbox_search}
+    {spatial_filter}{filter_clause}
   {limit_clause}
-) AS tile;
-"
-    )
-    .trim()
-    .to_string();
-
-    Ok((
-        id,
-        PostgresSqlInfo::new(query, false, info.format_id()),
-        info,
-    ))
+) AS tile"
+        )
+        .trim()
+        .to_string()
+    }
+
+    /// Generate the bounding box of the requested tile (including the buffer) to search for features in.
+    fn bbox_search(&self, srid: i32) -> String {
+        // When calculating the bounding box to search within, a few considerations must be made when
+        // using a margin. The ST_TileEnvelope margin parameter is for use with SRID 3857.
+        // For SRID 4326, ST_Expand is used and provided with SRID 4326 specific units (degrees).
+        // If the table uses a non-standard SRID, it will fall back to existing behavior.
+        //
+        // For more context, if SRID 4326 were to be used with ST_TileEnvelope and margin
+        // parameter, the resultant bounding box for tiles on the antimeridian would be calculated
+        // incorrectly. For example, with a margin of 2 units, the antimeridian edge would transform
+        // from -180 to +178. This results in a bbox that stretches from the easternmost edge of a tile
+        // (plus margin) around the map to the westernmost edge of the tile (minus margin). The
+        // resulting bbox covers none of the original tile. In contrast, for this example, ST_Expand
+        // will result in a westernmost edge (minus margin) of -182.
+        let margin = f64::from(self.buffer) / f64::from(self.extent);
+        if self.buffer != 0 && self.supports_tile_margin && srid == 3857 {
+            format!("ST_TileEnvelope($1::integer, $2::integer, $3::integer, margin => {margin})")
+        } else if self.buffer != 0 && srid == 4326 {
+            format!(
+                "ST_Expand(ST_Transform(ST_TileEnvelope($1::integer, $2::integer, $3::integer), {srid}), ({margin} * {EARTH_CIRCUMFERENCE_DEGREES}) / 2^$1::integer)"
+            )
+        } else if srid == 3857 {
+            "ST_TileEnvelope($1::integer, $2::integer, $3::integer)".to_string()
+        } else {
+            format!("ST_Transform(ST_TileEnvelope($1::integer, $2::integer, $3::integer), {srid})")
+        }
+    }
+}
+
+/// Compute the bounds of a table according to the `bounds_type`, or `None` if skipped or timed out.
+async fn table_bounds(
+    pool: &PostgresPool,
+    id: &str,
+    info: &TableInfo,
+    bounds_type: BoundsCalcType,
+) -> PostgresResult<Option<Bounds>> {
+    let schema = escape_identifier(&info.schema);
+    let table = escape_identifier(&info.table);
+    let geometry_column = escape_identifier(&info.geometry_column);
+    let mut bounds = None;
+    match bounds_type {
+        BoundsCalcType::Skip => {}
+        BoundsCalcType::Calc => {
+            debug!("Computing {} table bounds for {id}", info.format_id());
+            bounds = calc_bounds(pool, &schema, &table, &geometry_column, info.srid).await?;
+        }
+        BoundsCalcType::Quick => {
+            debug!(
+                "Computing {} table bounds with {}s timeout for {id}",
+                info.format_id(),
+                DEFAULT_BOUNDS_TIMEOUT.as_secs()
+            );
+            let calc = calc_bounds(pool, &schema, &table, &geometry_column, info.srid);
+            pin_mut!(calc);
+            if let Ok(calc) = timeout(DEFAULT_BOUNDS_TIMEOUT, &mut calc).await {
+                bounds = calc?;
+            } else {
+                warn!(
+                    "Timeout computing {} bounds for {id}, aborting query. Use --auto-bounds=calc to wait until complete, or check the table for missing indices.",
+                    info.format_id(),
+                );
+            }
+        }
+    }
+
+    if let Some(bounds) = bounds {
+        debug!(
+            "The computed bounds for {id} from {} are {bounds}",
+            info.format_id()
+        );
+    }
+    Ok(bounds)
+}
+
+/// Generate an SQL expression picking the simplification tolerance for the requested zoom (`$1`).
+/// Returns `None` if no tolerances are configured.
+fn simplify_tolerance_sql(tolerances: &BTreeMap<u8, f64>) -> Option<String> {
+    if tolerances.is_empty() {
+        return None;
+    }
+    let cases = tolerances
+        .iter()
+        .rev()
+        .map(|(zoom, tolerance)| format!("WHEN $1::integer >= {zoom} THEN {tolerance:?}"))
+        .join(" ");
+    Some(format!("CASE {cases} ELSE 0.0 END"))
+}
+
+/// Make sure the filter is a valid boolean expression for the table by asking Postgres to plan it.
+async fn validate_filter(
+    pool: &PostgresPool,
+    id: &str,
+    schema: &str,
+    table: &str,
+    filter: &str,
+) -> PostgresResult<()> {
+    pool.get()
+        .await?
+        .execute(
+            &format!("EXPLAIN SELECT 1 FROM {schema}.{table} WHERE ({filter})"),
+            &[],
+        )
+        .await
+        .map_err(|e| InvalidTableFilter(e, id.to_string(), filter.to_string()))?;
+    Ok(())
 }
 
 /// Compute the bounds of a table. This could be slow if the table is large or has no geo index.
@@ -300,3 +457,28 @@ pub fn polygon_to_bbox(polygon: &ewkb::Polygon) -> Option<Bounds> {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_extent() {
+        assert!(is_valid_extent(512));
+        assert!(is_valid_extent(DEFAULT_EXTENT));
+        assert!(is_valid_extent(8192));
+        assert!(!is_valid_extent(0));
+        assert!(!is_valid_extent(32));
+        assert!(!is_valid_extent(4000));
+        assert!(!is_valid_extent(131_072));
+    }
+
+    #[test]
+    fn simplify_tolerance() {
+        assert_eq!(simplify_tolerance_sql(&BTreeMap::new()), None);
+        assert_eq!(
+            simplify_tolerance_sql(&BTreeMap::from([(0, 1000.0), (10, 10.5), (14, 0.0)])).unwrap(),
+            "CASE WHEN $1::integer >= 14 THEN 0.0 WHEN $1::integer >= 10 THEN 10.5 WHEN $1::integer >= 0 THEN 1000.0 ELSE 0.0 END"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! `PostgreSQL` pre-generated tile table discovery.
+
+use std::collections::BTreeMap;
+
+use martin_core::tiles::postgres::PostgresError::PostgresError;
+use martin_core::tiles::postgres::{
+    PostgresPool, PostgresQueryKind, PostgresResult, PostgresSqlInfo,
+};
+use martin_tile_utils::{TileInfo, TileScheme};
+use postgres_protocol::escape::escape_identifier;
+use tracing::debug;
+
+use crate::config::file::postgres::utils::clip_bounds;
+use crate::config::file::postgres::{PostgresInfo as _, TileTableInfo};
+
+/// Queries the names and types of all columns of a table, view or materialized view.
+///
+/// Returns an empty map if the table does not exist.
+pub async fn query_table_columns(
+    pool: &PostgresPool,
+    schema: &str,
+    table: &str,
+) -> PostgresResult<BTreeMap<String, String>> {
+    let rows = pool
+        .get()
+        .await?
+        .query(
+            r"
+SELECT attname::text AS name, format_type(atttypid, NULL) AS type
+FROM pg_attribute
+WHERE attrelid = to_regclass(quote_ident($1) || '.' || quote_ident($2))
+  AND attnum > 0
+  AND NOT attisdropped",
+            &[&schema, &table],
+        )
+        .await
+        .map_err(|e| PostgresError(e, "querying tile table columns"))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get("name"), row.get("type")))
+        .collect())
+}
+
+/// Builds the query selecting a tile by its XYZ coordinates for the source `id`.
+///
+/// The tile table info must have all its columns resolved, see [`TileTableInfo::resolve_columns`].
+#[must_use]
+pub fn tile_table_to_query(id: &str, info: &TileTableInfo) -> PostgresSqlInfo {
+    let column = |c: &Option<String>| escape_identifier(c.as_deref().unwrap_or_default());
+    let tile_column = column(&info.tile_column);
+    let zoom_column = column(&info.zoom_column);
+    let x_column = column(&info.x_column);
+    let y_column = column(&info.y_column);
+    let schema = escape_identifier(&info.schema);
+    let table = escape_identifier(&info.table);
+    let tile_scheme = info.scheme.unwrap_or(TileScheme::Xyz);
+    let y = match tile_scheme {
+        TileScheme::Xyz => "$3",
+        TileScheme::Tms => "(1::bigint << $1::integer) - 1 - $3",
+    };
+
+    let query = format!(
+        "SELECT {tile_column} FROM {schema}.{table} WHERE {zoom_column} = $1 AND {x_column} = $2 AND {y_column} = {y}"
+    );
+    let signature = format!(
+        "{} by ({}, {}, {}) in {tile_scheme} scheme",
+        info.format_id(),
+        info.zoom_column.as_deref().unwrap_or_default(),
+        info.x_column.as_deref().unwrap_or_default(),
+        info.y_column.as_deref().unwrap_or_default(),
+    );
+
+    PostgresSqlInfo::new(query, PostgresQueryKind::TileTable, false, signature)
+        .with_statement_cache(info.statement_cache.unwrap_or(true))
+        .with_read_only(info.read_only.unwrap_or_default())
+        .with_empty_tile(info.empty_tile_behavior.unwrap_or_default())
+        .with_clip_bounds(clip_bounds(id, info.clip_to_bounds, info.bounds))
+}
+
+/// Detects the format and encoding of the pre-generated tiles from any one of them.
+///
+/// Returns `None` if the table has no tiles.
+pub async fn detect_tile_table_format(
+    pool: &PostgresPool,
+    info: &TileTableInfo,
+) -> PostgresResult<Option<TileInfo>> {
+    let tile_column = escape_identifier(info.tile_column.as_deref().unwrap_or_default());
+    let schema = escape_identifier(&info.schema);
+    let table = escape_identifier(&info.table);
+    let query = format!(
+        "SELECT {tile_column} FROM {schema}.{table} WHERE {tile_column} IS NOT NULL LIMIT 1"
+    );
+
+    let row = pool
+        .get()
+        .await?
+        .query_opt(&query, &[])
+        .await
+        .map_err(|e| PostgresError(e, "sampling a pre-generated tile"))?;
+    let info = row.map(|row| TileInfo::detect(row.get::<_, &[u8]>(0)));
+    debug!("Detected {info:?} tiles in {schema}.{table}");
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_table_queries() {
+        let mut info = TileTableInfo {
+            schema: "public".to_string(),
+            table: "Tiles".to_string(),
+            tile_column: Some("tile_data".to_string()),
+            zoom_column: Some("zoom_level".to_string()),
+            x_column: Some("tile_column".to_string()),
+            y_column: Some("tile_row".to_string()),
+            scheme: Some(TileScheme::Tms),
+            ..Default::default()
+        };
+        let sql = tile_table_to_query("tiles", &info);
+        assert_eq!(
+            sql.sql_query,
+            r#"SELECT "tile_data" FROM "public"."Tiles" WHERE "zoom_level" = $1 AND "tile_column" = $2 AND "tile_row" = (1::bigint << $1::integer) - 1 - $3"#
+        );
+        assert_eq!(
+            sql.signature,
+            "public.Tiles.tile_data by (zoom_level, tile_column, tile_row) in tms scheme"
+        );
+        assert_eq!(sql.kind, PostgresQueryKind::TileTable);
+
+        info.scheme = Some(TileScheme::Xyz);
+        let sql = tile_table_to_query("tiles", &info);
+        assert!(sql.sql_query.ends_with(r#""tile_row" = $3"#));
+    }
+}
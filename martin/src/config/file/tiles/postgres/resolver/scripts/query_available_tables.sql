@@ -96,11 +96,49 @@ annotated_geography_columns AS (
     GROUP BY 1, 2, 3, 4, 5, 6
 ),
 
+--
+annotated_matview_columns AS (
+    -- geometry columns of materialized views that are missing from geometry_columns,
+    -- e.g. because of the privilege filter, validated directly via pg_attribute
+    SELECT
+        ns.nspname AS schema, -- noqa: RF04
+        cls.relname AS name, -- noqa: RF04
+        attr.attname AS geom,
+        postgis_typmod_srid(attr.atttypmod) AS srid,
+        upper(postgis_typmod_type(attr.atttypmod))::varchar AS type,
+        cls.relkind,
+        bool_or(sic.column_name IS NOT null) AS geom_idx
+    FROM pg_attribute AS attr
+    INNER JOIN pg_catalog.pg_class AS cls ON attr.attrelid = cls.oid
+    INNER JOIN pg_catalog.pg_namespace AS ns ON cls.relnamespace = ns.oid
+    INNER JOIN pg_catalog.pg_type AS tp ON attr.atttypid = tp.oid
+    LEFT JOIN spatially_indexed_columns AS sic
+        ON
+            ns.nspname = sic.table_schema
+            AND cls.relname = sic.table_name
+            AND attr.attname = sic.column_name
+    WHERE
+        cls.relkind = 'm'
+        AND tp.typname = 'geometry'
+        AND NOT attr.attisdropped
+        AND attr.attnum > 0
+        AND NOT EXISTS (
+            SELECT 1 FROM geometry_columns
+            WHERE
+                geometry_columns.f_table_schema = ns.nspname
+                AND geometry_columns.f_table_name = cls.relname
+                AND geometry_columns.f_geometry_column = attr.attname
+        )
+    GROUP BY 1, 2, 3, 4, 5, 6
+),
+
 --
 annotated_geo_columns AS (
     SELECT * FROM annotated_geometry_columns
     UNION
     SELECT * FROM annotated_geography_columns
+    UNION
+    SELECT * FROM annotated_matview_columns
 ),
 
 --
@@ -1,8 +1,8 @@
 use std::collections::BTreeMap;
 
 use itertools::Itertools as _;
-use tilejson::TileJSON;
-use tracing::{error, info};
+use tilejson::{Bounds, TileJSON};
+use tracing::{error, info, warn};
 
 #[must_use]
 pub fn normalize_key<T>(
@@ -90,6 +90,22 @@ pub fn find_kv_ignore_case<'a, T>(
     }
 }
 
+/// Returns the bounds to clip tile requests to if `clip_to_bounds` is enabled.
+#[must_use]
+pub fn clip_bounds(
+    id: &str,
+    clip_to_bounds: Option<bool>,
+    bounds: Option<Bounds>,
+) -> Option<[f64; 4]> {
+    if !clip_to_bounds.unwrap_or_default() {
+        return None;
+    }
+    if bounds.is_none() {
+        warn!("Source {id} sets clip_to_bounds, but has no bounds to clip to");
+    }
+    bounds.map(|b| [b.left, b.bottom, b.right, b.top])
+}
+
 #[must_use]
 pub fn patch_json(target: TileJSON, patch: Option<&serde_json::Value>) -> TileJSON {
     let Some(tj) = patch else {
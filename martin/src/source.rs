@@ -3,10 +3,12 @@ use std::sync::Arc;
 use actix_web::error::ErrorNotFound;
 use dashmap::DashMap;
 use martin_core::tiles::catalog::TileCatalog;
-use martin_core::tiles::{BoxedSource, Source};
+use martin_core::tiles::{BoxedSource, MartinCoreError, MartinCoreResult, Source};
 use martin_tile_utils::TileInfo;
 use tracing::debug;
 
+use crate::srv::map_tile_error;
+
 /// Thread-safe registry of tile sources indexed by ID.
 ///
 /// Uses a [`DashMap`] for concurrent access without explicit locking.
@@ -47,14 +49,12 @@ impl TileSources {
         self.0.iter().map(|v| v.key().clone()).collect()
     }
 
-    /// Gets a source by ID, returning 404 error if not found.
-    pub fn get_source(&self, id: &str) -> actix_web::Result<BoxedSource> {
-        Ok(self
-            .0
+    /// Gets a source by ID, returning [`MartinCoreError::SourceNotFound`] if there is no such source.
+    pub fn get_source(&self, id: &str) -> MartinCoreResult<BoxedSource> {
+        self.0
             .get(id)
-            .ok_or_else(|| ErrorNotFound(format!("Source {id} does not exist")))?
-            .value()
-            .clone())
+            .map(|src| src.value().clone())
+            .ok_or_else(|| MartinCoreError::SourceNotFound(id.to_string()))
     }
 
     /// Gets multiple sources for composite tiles, ensuring format compatibility.
@@ -74,7 +74,7 @@ impl TileSources {
         let mut use_url_query = false;
 
         for id in source_ids.split(',') {
-            let src = self.get_source(id)?;
+            let src = self.get_source(id).map_err(|e| map_tile_error(&e))?;
             let src_inf = src.get_tile_info();
             use_url_query |= src.support_url_query();
 
@@ -8,10 +8,12 @@ mod admin;
 pub use admin::Catalog;
 
 #[cfg(feature = "_tiles")]
-mod tiles;
+pub(crate) mod tiles;
 #[cfg(feature = "_tiles")]
 pub use tiles::content::DynTileSource;
 #[cfg(feature = "_tiles")]
+pub(crate) use tiles::content::map_tile_error;
+#[cfg(feature = "_tiles")]
 pub use tiles::metadata::merge_tilejson;
 
 #[cfg(feature = "sprites")]
@@ -1,6 +1,8 @@
 use actix_http::ContentEncoding;
 use actix_http::header::Quality;
-use actix_web::error::{ErrorBadRequest, ErrorNotAcceptable, ErrorNotFound};
+use actix_web::error::{
+    ErrorBadRequest, ErrorNotAcceptable, ErrorNotFound, ErrorServiceUnavailable,
+};
 use actix_web::http::header::{
     AcceptEncoding, CONTENT_ENCODING, ETAG, Encoding as HeaderEnc, EntityTag, IfNoneMatch,
     LOCATION, Preference,
@@ -8,14 +10,16 @@ use actix_web::http::header::{
 use actix_web::web::{Data, Path, Query};
 use actix_web::{HttpMessage as _, HttpRequest, HttpResponse, Result as ActixResult, route};
 use futures::future::try_join_all;
-use martin_core::tiles::{BoxedSource, Tile, TileCache, UrlQuery};
+#[cfg(feature = "postgres")]
+use martin_core::tiles::postgres::PostgresError;
+use martin_core::tiles::{BoxedSource, MartinCoreError, Tile, TileCache, UrlQuery};
 use martin_tile_utils::{
     Encoding, Format, TileCoord, TileData, TileInfo, decode_brotli, decode_gzip, decode_zlib,
     decode_zstd, encode_brotli, encode_gzip, encode_zlib, encode_zstd,
 };
 use serde::Deserialize;
 use std::sync::Arc;
-use tracing::warn;
+use tracing::{debug, warn};
 
 use crate::config::args::PreferredEncoding;
 use crate::config::file::srv::SrvConfig;
@@ -30,6 +34,31 @@ const SUPPORTED_ENC: &[HeaderEnc] = &[
     HeaderEnc::identity(),
 ];
 
+/// Maps tile source errors to HTTP errors.
+///
+/// Both unknown sources and missing tiles are reported as `404 Not Found`, but with different bodies.
+/// Coordinates outside of the zoom level are reported as `400 Bad Request`.
+/// Timeouts, including a saturated Postgres pool, are reported as `503 Service Unavailable` so that clients can retry later.
+pub fn map_tile_error(e: &MartinCoreError) -> actix_web::Error {
+    match e {
+        MartinCoreError::NotFound(_) => ErrorNotFound(e.to_string()),
+        MartinCoreError::SourceNotFound(_) => {
+            debug!("{e}");
+            ErrorNotFound(e.to_string())
+        }
+        MartinCoreError::InvalidCoordinate(_) => ErrorBadRequest(e.to_string()),
+        MartinCoreError::Timeout(..) => {
+            warn!("{e}");
+            ErrorServiceUnavailable(e.to_string())
+        }
+        #[cfg(feature = "postgres")]
+        MartinCoreError::PostgresError(PostgresError::InvalidUrlQuery(..)) => {
+            ErrorBadRequest(e.to_string())
+        }
+        _ => map_internal_error(e),
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct TileRequest {
     source_ids: String,
@@ -56,12 +85,12 @@ async fn get_tile(
         srv_config.preferred_encoding,
     )?;
 
-    src.get_http_response(TileCoord {
-        z: path.z,
-        x: path.x,
-        y: path.y,
-    })
-    .await
+    let xyz = TileCoord::new_checked(path.z, path.x, path.y).ok_or_else(|| {
+        map_tile_error(&MartinCoreError::InvalidCoordinate(
+            TileCoord::new_unchecked(path.z, path.x, path.y),
+        ))
+    })?;
+    src.get_http_response(xyz).await
 }
 
 #[derive(Deserialize, Clone)]
@@ -186,7 +215,9 @@ impl<'a> DynTileSource<'a> {
         let cache = manager.tile_cache().as_ref();
 
         if sources.is_empty() {
-            return Err(ErrorNotFound("No valid sources found"));
+            return Err(map_tile_error(&MartinCoreError::NotFound(
+                "No valid sources found".to_string(),
+            )));
         }
 
         let mut query_obj = None;
@@ -253,7 +284,7 @@ impl<'a> DynTileSource<'a> {
             }
         }))
         .await
-        .map_err(|e| map_internal_error(e.as_ref()))?;
+        .map_err(|e| map_tile_error(e.as_ref()))?;
 
         let mut layer_count = 0;
         let mut last_non_empty_layer = 0;
@@ -288,8 +319,11 @@ impl<'a> DynTileSource<'a> {
                     combined_etag.push_str(&tile.etag);
                 }
 
-                let (concat_data, effective_info) = if self.info.encoding == Encoding::Uncompressed
-                    || self.info.encoding == Encoding::Gzip
+                // Sources may compress some tiles and not others, so check the actual tiles
+                let encoding = tiles[0].info.encoding;
+                let (concat_data, effective_info) = if (encoding == Encoding::Uncompressed
+                    || encoding == Encoding::Gzip)
+                    && tiles.iter().all(|t| t.info.encoding == encoding)
                 {
                     // Gzip multi-stream is valid; uncompressed concat is fine
                     let data = tiles
@@ -297,7 +331,7 @@ impl<'a> DynTileSource<'a> {
                         .map(|t| t.data)
                         .collect::<Vec<_>>()
                         .concat();
-                    (data, self.info)
+                    (data, self.info.encoding(encoding))
                 } else {
                     // Decompress first, concat raw MVT, let recompress re-encode
                     let mut raw = Vec::new();
@@ -478,7 +512,7 @@ mod tests {
 
     use super::*;
     use crate::config::file::OnInvalid;
-    use crate::srv::tiles::tests::{CompressedTestSource, TestSource};
+    use crate::srv::tiles::tests::{CompressedTestSource, GzipOnTheFlyTestSource, TestSource};
 
     fn test_manager(sources: Vec<Vec<BoxedSource>>) -> TileSourceManager {
         TileSourceManager::from_sources(None, OnInvalid::Abort, sources)
@@ -663,4 +697,88 @@ mod tests {
             "decoded content mismatch for src={src_enc:?}, accept={accept:?}"
         );
     }
+
+    #[rstest]
+    #[case(None, Encoding::Uncompressed)]
+    #[case(Some("gzip"), Encoding::Gzip)]
+    #[case(Some("br"), Encoding::Brotli)]
+    #[actix_rt::test]
+    async fn test_mixed_encoding_mvt_merge(
+        #[case] accept: Option<&str>,
+        #[case] expected_enc: Encoding,
+    ) {
+        let gzipped = GzipOnTheFlyTestSource {
+            id: "gzipped",
+            tj: tilejson! { tiles: vec![] },
+            data: vec![1, 2, 3],
+        };
+        let plain = TestSource {
+            id: "plain",
+            tj: tilejson! { tiles: vec![] },
+            data: vec![4, 5, 6],
+        };
+        let mgr = test_manager(vec![vec![Box::new(gzipped), Box::new(plain)]]);
+
+        let accept_enc = accept.map(|s| AcceptEncoding(vec![s.parse().unwrap()]));
+        let src =
+            DynTileSource::new(&mgr, "gzipped,plain", None, "", accept_enc, None, None).unwrap();
+        let tile = src
+            .get_tile_content(TileCoord { z: 0, x: 0, y: 0 })
+            .await
+            .unwrap();
+
+        assert_eq!(tile.info.encoding, expected_enc);
+        let decoded = decompress_tile(&tile.data, tile.info.encoding);
+        assert_eq!(decoded, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn unknown_source_is_not_found() {
+        let src = TestSource {
+            id: "known",
+            tj: tilejson! { tiles: vec![] },
+            data: vec![1],
+        };
+        let mgr = test_manager(vec![vec![Box::new(src)]]);
+        let Err(err) = DynTileSource::new(&mgr, "known,missing", None, "", None, None, None) else {
+            panic!("expected an error for an unknown source");
+        };
+        let resp = err.error_response();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        assert_eq!(err.to_string(), "Source missing does not exist");
+    }
+
+    #[test]
+    fn tile_error_status_codes() {
+        use actix_web::http::StatusCode;
+
+        let status = |e: MartinCoreError| map_tile_error(&e).as_response_error().status_code();
+        assert_eq!(
+            status(MartinCoreError::NotFound("tile".to_string())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(MartinCoreError::SourceNotFound("missing".to_string())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(MartinCoreError::InvalidCoordinate(TileCoord {
+                z: 1,
+                x: 5,
+                y: 0
+            })),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(MartinCoreError::Timeout(
+                std::time::Duration::from_secs(1),
+                "fetching a tile".to_string()
+            )),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            status(MartinCoreError::OtherError("boom".into())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
 }
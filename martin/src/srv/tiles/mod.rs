@@ -5,8 +5,8 @@ pub mod metadata;
 pub mod tests {
     use async_trait::async_trait;
     use martin_core::CacheZoomRange;
-    use martin_core::tiles::{BoxedSource, MartinCoreResult, Source, UrlQuery};
-    use martin_tile_utils::{Encoding, Format, TileCoord, TileData, TileInfo};
+    use martin_core::tiles::{BoxedSource, MartinCoreResult, Source, Tile, UrlQuery};
+    use martin_tile_utils::{Encoding, Format, TileCoord, TileData, TileInfo, encode_gzip};
     use tilejson::TileJSON;
 
     #[derive(Debug, Clone)]
@@ -86,4 +86,56 @@ pub mod tests {
             Ok(self.data.clone())
         }
     }
+
+    /// A test source that declares uncompressed tiles, but gzips them on the fly,
+    /// like a Postgres source with compression enabled.
+    #[derive(Debug, Clone)]
+    pub struct GzipOnTheFlyTestSource {
+        pub id: &'static str,
+        pub tj: TileJSON,
+        pub data: TileData,
+    }
+
+    #[async_trait]
+    impl Source for GzipOnTheFlyTestSource {
+        fn get_id(&self) -> &str {
+            self.id
+        }
+
+        fn get_tilejson(&self) -> &TileJSON {
+            &self.tj
+        }
+
+        fn get_tile_info(&self) -> TileInfo {
+            TileInfo::new(Format::Mvt, Encoding::Uncompressed)
+        }
+
+        fn clone_source(&self) -> BoxedSource {
+            Box::new(self.clone())
+        }
+
+        fn cache_zoom(&self) -> CacheZoomRange {
+            CacheZoomRange::default()
+        }
+
+        async fn get_tile(
+            &self,
+            _xyz: TileCoord,
+            _url_query: Option<&UrlQuery>,
+        ) -> MartinCoreResult<TileData> {
+            Ok(self.data.clone())
+        }
+
+        async fn get_tile_with_etag(
+            &self,
+            xyz: TileCoord,
+            url_query: Option<&UrlQuery>,
+        ) -> MartinCoreResult<Tile> {
+            let data = encode_gzip(&self.get_tile(xyz, url_query).await?).unwrap();
+            Ok(Tile::new_hash_etag(
+                data,
+                self.get_tile_info().encoding(Encoding::Gzip),
+            ))
+        }
+    }
 }
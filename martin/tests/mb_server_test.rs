@@ -250,6 +250,16 @@ async fn mbt_get_mvt() {
     assert_eq!(body.len(), 1828);
 }
 
+#[actix_rt::test]
+#[tracing_test::traced_test]
+async fn mbt_get_mvt_invalid_coordinate() {
+    let (config, _conns) = config("mbt_get_mvt_invalid_coordinate").await;
+    let app = create_app!(&config);
+    let req = test_get("/m_mvt/1/5/0").to_request();
+    let response = call_service(&app, req).await;
+    assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+}
+
 /// get an MVT tile with accepted gzip enc
 #[actix_rt::test]
 #[tracing_test::traced_test]
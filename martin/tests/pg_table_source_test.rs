@@ -319,3 +319,178 @@ async fn table_bounds_empty_table_ok() {
       gid: int4
     ");
 }
+
+#[actix_rt::test]
+async fn table_filter() {
+    let mock = mock_sources(mock_pgcfg(indoc! {"
+        connection_string: $DATABASE_URL
+        tables:
+          no_features:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+            filter: gid < 0
+          not_a_boolean:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+            filter: gid + 1
+    "}))
+    .await;
+
+    let tile = source(&mock, "no_features")
+        .get_tile(TileCoord { z: 0, x: 0, y: 0 }, None)
+        .await
+        .unwrap();
+    assert!(tile.is_empty());
+
+    let catalog = mock.0.tile_manager.tile_sources().get_catalog();
+    assert!(!catalog.contains_key("not_a_boolean"));
+}
+
+#[actix_rt::test]
+async fn table_buffer_exceeding_extent() {
+    let mock = mock_sources(mock_pgcfg(indoc! {"
+        connection_string: $DATABASE_URL
+        tables:
+          huge_buffer:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+            extent: 512
+            buffer: 100000
+    "}))
+    .await;
+
+    let tile = source(&mock, "huge_buffer")
+        .get_tile(TileCoord { z: 0, x: 0, y: 0 }, None)
+        .await
+        .unwrap();
+    assert!(!tile.is_empty());
+}
+
+#[actix_rt::test]
+async fn table_zoom_range() {
+    let mock = mock_sources(mock_pgcfg(indoc! {"
+        connection_string: $DATABASE_URL
+        tables:
+          zoomed:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+            minzoom: 1
+            maxzoom: 2
+    "}))
+    .await;
+
+    let src = source(&mock, "zoomed");
+    let tile = src
+        .get_tile(TileCoord { z: 0, x: 0, y: 0 }, None)
+        .await
+        .unwrap();
+    assert!(tile.is_empty());
+    let tile = src
+        .get_tile(TileCoord { z: 1, x: 0, y: 0 }, None)
+        .await
+        .unwrap();
+    assert!(!tile.is_empty());
+}
+
+#[actix_rt::test]
+async fn table_materialized_view() {
+    let mock = mock_sources(mock_pgcfg(indoc! {"
+        connection_string: $DATABASE_URL
+        tables:
+          mv:
+            schema: public
+            table: mat_view
+            srid: 4326
+            geometry_column: geom
+    "}))
+    .await;
+
+    let tile = source(&mock, "mv")
+        .get_tile(TileCoord { z: 0, x: 0, y: 0 }, None)
+        .await
+        .unwrap();
+    assert!(!tile.is_empty());
+}
+
+#[actix_rt::test]
+async fn table_empty_tile_behavior() {
+    let mock = mock_sources(mock_pgcfg(indoc! {"
+        connection_string: $DATABASE_URL
+        tables:
+          empty_blob:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+            minzoom: 1
+            empty_tile_behavior: empty-blob
+    "}))
+    .await;
+
+    let tile = source(&mock, "empty_blob")
+        .get_tile(TileCoord { z: 0, x: 0, y: 0 }, None)
+        .await
+        .unwrap();
+    assert_eq!(tile, b"\x1a\x11\x78\x02\x0a\x0aempty_blob\x28\x80\x20");
+}
+
+#[actix_rt::test]
+async fn table_validate_output() {
+    let mock = mock_sources(mock_pgcfg(indoc! {"
+        connection_string: $DATABASE_URL
+        validate_output: true
+        tables:
+          valid:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+          no_features:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+            filter: gid < 0
+    "}))
+    .await;
+
+    let catalog = mock.0.tile_manager.tile_sources().get_catalog();
+    assert!(catalog.contains_key("valid"));
+    assert!(!catalog.contains_key("no_features"));
+}
+
+#[actix_rt::test]
+async fn table_spatial_filter() {
+    let mock = mock_sources(mock_pgcfg(indoc! {"
+        connection_string: $DATABASE_URL
+        tables:
+          bbox:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+          intersects:
+            schema: public
+            table: table_source
+            srid: 4326
+            geometry_column: geom
+            spatial_filter: intersects
+    "}))
+    .await;
+
+    for id in ["bbox", "intersects"] {
+        let tile = source(&mock, id)
+            .get_tile(TileCoord { z: 0, x: 0, y: 0 }, None)
+            .await
+            .unwrap();
+        assert!(!tile.is_empty(), "{id}");
+    }
+}
@@ -0,0 +1,98 @@
+use std::pin::Pin;
+
+use futures::{Stream, TryStreamExt as _};
+use martin_tile_utils::Tile;
+
+use crate::MbtResult;
+
+/// A batch of `(z, x, y, data)` tiles as accepted by [`Mbtiles::insert_tiles`](crate::Mbtiles::insert_tiles), using XYZ coordinates.
+pub type TileBatch = Vec<(u8, u32, u32, Vec<u8>)>;
+
+/// Groups a stream of tiles, e.g. from [`Mbtiles::stream_tiles`](crate::Mbtiles::stream_tiles),
+/// into batches of up to `batch_size` tiles that can be passed to [`Mbtiles::insert_tiles`](crate::Mbtiles::insert_tiles).
+///
+/// Tiles without data are dropped. The last batch may be smaller than `batch_size`,
+/// and no empty batches are yielded. Errors are passed through as soon as they are encountered,
+/// discarding the tiles collected for the current batch.
+///
+/// ```
+/// # use futures::TryStreamExt as _;
+/// # use mbtiles::{Mbtiles, batched_tiles};
+/// # async fn batched_example() -> mbtiles::MbtResult<()> {
+/// let mbt = Mbtiles::new("example.mbtiles")?;
+/// let mut conn = mbt.open_readonly().await?;
+/// let mut batches = batched_tiles(mbt.stream_tiles(&mut conn), 1000);
+/// while let Some(batch) = batches.try_next().await? {
+///     println!("Got {} tiles", batch.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Panics
+/// Panics if `batch_size` is zero.
+pub fn batched_tiles<'e>(
+    tiles: impl Stream<Item = MbtResult<Tile>> + Send + 'e,
+    batch_size: usize,
+) -> Pin<Box<dyn Stream<Item = MbtResult<TileBatch>> + Send + 'e>> {
+    assert!(batch_size > 0, "batch_size must be greater than zero");
+    let tiles = tiles.try_filter_map(|(coord, data)| async move {
+        Ok(data.map(|data| (coord.z, coord.x, coord.y, data)))
+    });
+    Box::pin(tiles.try_chunks(batch_size).map_err(|e| e.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use martin_tile_utils::TileCoord;
+
+    use super::*;
+    use crate::MbtError;
+
+    #[expect(clippy::unnecessary_wraps)]
+    fn tile(x: u32, data: Option<Vec<u8>>) -> MbtResult<Tile> {
+        Ok((TileCoord { z: 3, x, y: 0 }, data))
+    }
+
+    #[actix_rt::test]
+    async fn batches() {
+        let tiles = (0..5)
+            .map(|x| tile(x, Some(vec![1])))
+            .chain([tile(5, None)]);
+        let batches: Vec<_> = batched_tiles(stream::iter(tiles), 2)
+            .try_collect()
+            .await
+            .unwrap();
+        let sizes: Vec<_> = batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [2, 2, 1]);
+        assert_eq!(batches[2], [(3, 4, 0, vec![1])]);
+
+        let batches: Vec<_> = batched_tiles(stream::iter([tile(0, None)]), 2)
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(batches.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn batches_with_error() {
+        let tiles = [
+            tile(0, Some(vec![1])),
+            tile(1, Some(vec![2])),
+            tile(2, Some(vec![3])),
+            Err(MbtError::NoTilesFound),
+            tile(3, Some(vec![4])),
+        ];
+        let mut batches = batched_tiles(stream::iter(tiles), 2);
+        assert_eq!(batches.try_next().await.unwrap().unwrap().len(), 2);
+        assert!(matches!(
+            batches.try_next().await,
+            Err(MbtError::NoTilesFound)
+        ));
+        assert_eq!(
+            batches.try_next().await.unwrap(),
+            Some(vec![(3, 3, 0, vec![4])])
+        );
+    }
+}
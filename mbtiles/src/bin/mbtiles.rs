@@ -6,8 +6,8 @@ use clap::{Parser, Subcommand, ValueEnum};
 use enum_display::EnumDisplay;
 use log::error;
 use mbtiles::{
-    AggHashType, CopyDuplicateMode, CopyType, IntegrityCheckType, MbtResult, MbtTypeCli, Mbtiles,
-    MbtilesCopier, PatchTypeCli, UpdateZoomType, apply_patch,
+    AggHashType, BlankPolicy, CopyDuplicateMode, CopyType, IntegrityCheckType, MbtResult,
+    MbtTypeCli, Mbtiles, MbtilesCopier, PatchTypeCli, UpdateZoomType, apply_patch,
 };
 use serde::{Deserialize, Serialize};
 use tilejson::Bounds;
@@ -144,6 +144,15 @@ pub struct CopyArgs {
     /// Specify the type of patch file to generate.
     #[arg(long, requires("diff_with_file"), default_value_t=PatchTypeCli::default())]
     patch_type: PatchTypeCli,
+    /// Do not copy blank tiles: vector tiles without features, and single-color raster tiles.
+    #[arg(long, conflicts_with_all(["diff_with_file", "apply_patch"]))]
+    skip_blank: bool,
+    /// Do not copy tiles identical to the blank tile stored in this file.
+    #[arg(long, conflicts_with_all(["diff_with_file", "apply_patch", "skip_blank"]))]
+    skip_blank_sample: Option<PathBuf>,
+    /// Print what would be copied, without modifying the destination file.
+    #[arg(long, conflicts_with_all(["diff_with_file", "apply_patch"]))]
+    dry_run: bool,
 }
 
 #[derive(Clone, Default, PartialEq, Debug, clap::Args)]
@@ -229,6 +238,7 @@ impl SharedCopyOpts {
             validate: self.validate,
             // Constants
             dst_type: None, // Taken from dst_type_cli
+            skip_blank: None,
         }
     }
 }
@@ -262,14 +272,23 @@ async fn main_int() -> anyhow::Result<()> {
             meta_set_value(file.as_path(), &key, value.as_deref()).await?;
         }
         Commands::Copy(args) => {
-            let copier = args.options.into_copier(
+            let mut copier = args.options.into_copier(
                 args.src_file,
                 args.dst_file,
                 args.diff_with_file,
                 args.apply_patch,
                 args.patch_type,
             );
-            copier.run().await?;
+            if let Some(sample) = args.skip_blank_sample {
+                copier.skip_blank = Some(BlankPolicy::Sample(std::fs::read(sample)?));
+            } else if args.skip_blank {
+                copier.skip_blank = Some(BlankPolicy::Heuristic);
+            }
+            if args.dry_run {
+                print!("{}", copier.dry_run().await?);
+            } else {
+                copier.run().await?;
+            }
         }
         Commands::Diff(args) => {
             let copier = args.options.into_copier(
@@ -495,6 +514,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_copy_skip_blank_arguments() {
+        let Copy(args) =
+            Args::parse_from(["mbtiles", "copy", "src", "dst", "--skip-blank"]).command
+        else {
+            panic!("expected copy command");
+        };
+        assert!(args.skip_blank);
+        assert_eq!(
+            Args::try_parse_from([
+                "mbtiles",
+                "copy",
+                "src",
+                "dst",
+                "--skip-blank",
+                "--diff-with-file",
+                "diff"
+            ])
+            .unwrap_err()
+            .kind(),
+            ErrorKind::ArgumentConflict
+        );
+    }
+
     #[test]
     fn test_copy_diff_with_file_arguments() {
         assert_eq!(
@@ -0,0 +1,107 @@
+use martin_tile_utils::{Encoding, Format, TileInfo, count_mvt_features, decode_gzip, decode_zlib};
+
+/// How [`MbtilesCopier`](crate::MbtilesCopier) decides that a tile is blank and can be skipped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlankPolicy {
+    /// Skip tiles that are byte-for-byte identical to this sample blank tile.
+    Sample(Vec<u8>),
+    /// Skip vector tiles without any features, and raster tiles in which every pixel has the same color.
+    ///
+    /// Raster tiles are only inspected if the `raster` feature is enabled, and are never skipped otherwise.
+    Heuristic,
+}
+
+impl BlankPolicy {
+    /// Returns `true` if the tile should be treated as blank.
+    ///
+    /// Tiles that cannot be decoded are never blank.
+    #[must_use]
+    pub fn is_blank(&self, tile: &[u8]) -> bool {
+        match self {
+            Self::Sample(sample) => tile == sample.as_slice(),
+            Self::Heuristic => is_blank_tile(tile),
+        }
+    }
+}
+
+fn is_blank_tile(tile: &[u8]) -> bool {
+    if tile.is_empty() {
+        return true;
+    }
+    let info = TileInfo::detect(tile);
+    match (info.format, info.encoding) {
+        (Format::Mvt, Encoding::Uncompressed) => count_mvt_features(tile) == Some(0),
+        (Format::Mvt, Encoding::Gzip) => {
+            decode_gzip(tile).is_ok_and(|v| count_mvt_features(&v) == Some(0))
+        }
+        (Format::Mvt, Encoding::Zlib) => {
+            decode_zlib(tile).is_ok_and(|v| count_mvt_features(&v) == Some(0))
+        }
+        #[cfg(feature = "raster")]
+        (Format::Png | Format::Jpeg | Format::Webp | Format::Gif, _) => is_single_color(tile),
+        _ => false,
+    }
+}
+
+/// Checks if every pixel of a raster tile has the same value.
+#[cfg(feature = "raster")]
+fn is_single_color(tile: &[u8]) -> bool {
+    let Ok(img) = image::load_from_memory(tile) else {
+        return false;
+    };
+    let pixel_size = usize::from(img.color().bytes_per_pixel());
+    let mut pixels = img.as_bytes().chunks_exact(pixel_size);
+    let first = pixels.next();
+    pixels.all(|p| Some(p) == first)
+}
+
+#[cfg(test)]
+mod tests {
+    use martin_tile_utils::encode_gzip;
+
+    use super::*;
+
+    /// A tile with a layer `a` without features, and a layer `b` with two empty features
+    const MVT_TWO_FEATURES: &[u8] =
+        include_bytes!("../../martin-tile-utils/fixtures/two-features.mvt");
+    /// A tile with a single layer `a` without features
+    const MVT_NO_FEATURES: &[u8] =
+        include_bytes!("../../martin-tile-utils/fixtures/no-features.mvt");
+
+    #[test]
+    fn heuristic() {
+        let policy = BlankPolicy::Heuristic;
+        assert!(policy.is_blank(&[]));
+        assert!(policy.is_blank(MVT_NO_FEATURES));
+        assert!(policy.is_blank(&encode_gzip(MVT_NO_FEATURES).unwrap()));
+        assert!(!policy.is_blank(MVT_TWO_FEATURES));
+        assert!(!policy.is_blank(&encode_gzip(MVT_TWO_FEATURES).unwrap()));
+        assert!(!policy.is_blank(br#"{"foo":"bar"}"#));
+    }
+
+    #[cfg(feature = "raster")]
+    #[test]
+    fn heuristic_raster() {
+        use std::io::Cursor;
+
+        use image::{ImageFormat, Rgba, RgbaImage};
+
+        let png = |img: RgbaImage| {
+            let mut buf = Cursor::new(Vec::new());
+            img.write_to(&mut buf, ImageFormat::Png).unwrap();
+            buf.into_inner()
+        };
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+        assert!(BlankPolicy::Heuristic.is_blank(&png(img.clone())));
+        img.put_pixel(1, 2, Rgba([255, 0, 0, 255]));
+        assert!(!BlankPolicy::Heuristic.is_blank(&png(img)));
+    }
+
+    #[test]
+    fn sample() {
+        let policy = BlankPolicy::Sample(vec![1, 2, 3]);
+        assert!(policy.is_blank(&[1, 2, 3]));
+        assert!(!policy.is_blank(&[1, 2]));
+        assert!(!policy.is_blank(&[]));
+    }
+}
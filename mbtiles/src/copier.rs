@@ -1,11 +1,16 @@
+use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use enum_display::EnumDisplay;
 use itertools::Itertools as _;
 use log::{debug, info, trace, warn};
 use martin_tile_utils::{MAX_ZOOM, bbox_to_xyz};
 use serde::{Deserialize, Serialize};
+use size_format::SizeFormatterSI;
 use sqlite_hashes::rusqlite::Connection;
+use sqlite_hashes::rusqlite::functions::FunctionFlags;
 use sqlx::{Connection as _, Executor as _, Row as _, SqliteConnection, query};
 use tilejson::Bounds;
 
@@ -21,25 +26,30 @@ use crate::queries::{
     create_tiles_with_hash_view, detach_db, init_mbtiles_schema, is_empty_database,
 };
 use crate::{
-    AGG_TILES_HASH, AGG_TILES_HASH_AFTER_APPLY, AGG_TILES_HASH_BEFORE_APPLY, AggHashType, CopyType,
-    MbtError, MbtType, MbtTypeCli, Mbtiles, NormalizedSchema, action_with_rusqlite,
-    get_bsdiff_tbl_name, invert_y_value, reset_db_settings,
+    AGG_TILES_HASH, AGG_TILES_HASH_AFTER_APPLY, AGG_TILES_HASH_BEFORE_APPLY, AggHashType,
+    BlankPolicy, CopyType, MbtError, MbtType, MbtTypeCli, Mbtiles, NormalizedSchema,
+    action_with_rusqlite, get_bsdiff_tbl_name, invert_y_value, reset_db_settings,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumDisplay)]
 #[enum_display(case = "Kebab")]
 #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 pub enum CopyDuplicateMode {
+    /// Replace the existing tile
     Override,
+    /// Keep the existing tile
     Ignore,
+    /// Fail if the existing tile is different
     Abort,
+    /// Replace the existing tile only if it is different, skipping the write of identical tiles
+    OverrideIfChanged,
 }
 
 impl CopyDuplicateMode {
     #[must_use]
     pub fn to_sql(self) -> &'static str {
         match self {
-            Self::Override => "OR REPLACE",
+            Self::Override | Self::OverrideIfChanged => "OR REPLACE",
             Self::Ignore => "OR IGNORE",
             Self::Abort => "OR ABORT",
         }
@@ -78,6 +88,42 @@ pub struct MbtilesCopier {
     pub force: bool,
     /// Perform `agg_hash` validation on the original and destination files.
     pub validate: bool,
+    /// Do not copy tiles that are blank according to this policy. Not supported with diffs and patches.
+    pub skip_blank: Option<BlankPolicy>,
+}
+
+/// What a copy would do, as computed by [`MbtilesCopier::dry_run`] without touching the destination.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CopyPlan {
+    /// Type of the source file
+    pub src_type: MbtType,
+    /// Type of the destination file, either detected or the one a new file would be created with
+    pub dst_type: MbtType,
+    /// Whether the destination file is new or empty, and its schema would be created
+    pub dst_is_new: bool,
+    /// How tiles already present in the destination would be handled
+    pub on_duplicate: CopyDuplicateMode,
+    /// Number of source tiles matching the zoom and bbox filters.
+    /// Blank tiles are included even if they would be skipped.
+    pub tiles: u64,
+    /// Total size of the tile data of the matching tiles, an estimate of the size added to the destination.
+    /// This does not account for `SQLite` overhead or deduplication.
+    pub tile_bytes: u64,
+    /// Names of the metadata values that would be written, including `agg_tiles_hash` if it would be updated
+    pub metadata: Vec<String>,
+}
+
+impl Display for CopyPlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:15} {}", "Source type:", self.src_type)?;
+        let is_new = if self.dst_is_new { "new" } else { "existing" };
+        writeln!(f, "{:15} {} ({is_new})", "Destination:", self.dst_type)?;
+        writeln!(f, "{:15} {}", "On duplicate:", self.on_duplicate)?;
+        writeln!(f, "{:15} {}", "Tiles:", self.tiles)?;
+        let tile_bytes = SizeFormatterSI::new(self.tile_bytes);
+        writeln!(f, "{:15} {tile_bytes:.2}B", "Tile data:")?;
+        writeln!(f, "{:15} {}", "Metadata:", self.metadata.join(", "))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -93,6 +139,15 @@ impl MbtilesCopier {
         MbtileCopierInt::new(self)?.run().await
     }
 
+    /// Computes what [`run`](Self::run) would do without modifying the destination file,
+    /// failing in the same way if the copy is not possible.
+    ///
+    /// Diffing and applying a patch are not supported.
+    #[hotpath::measure]
+    pub async fn dry_run(self) -> MbtResult<CopyPlan> {
+        MbtileCopierInt::new(self)?.dry_run().await
+    }
+
     pub(crate) fn dst_type(&self) -> Option<MbtType> {
         self.dst_type.or_else(|| {
             self.dst_type_cli.map(|t| match t {
@@ -112,6 +167,11 @@ impl MbtileCopierInt {
         if options.apply_patch.is_some() && options.diff_with_file.is_some() {
             return Err(MbtError::CannotApplyPatchAndDiff);
         }
+        if options.skip_blank.is_some()
+            && (options.apply_patch.is_some() || options.diff_with_file.is_some())
+        {
+            return Err(MbtError::CannotSkipBlankWithDiffOrPatch);
+        }
         // We may want to resolve the files to absolute paths here, but will need to avoid various non-file cases
         if options.src_file == options.dst_file {
             return Err(MbtError::SameSourceAndDestination(options.src_file));
@@ -178,19 +238,7 @@ impl MbtileCopierInt {
         self.src_mbt.attach_to(&mut conn, "sourceDb").await?;
 
         let dst_type = if is_empty_db {
-            let mut dt = self.options.dst_type().unwrap_or(src_type);
-            // When copying from a DedupId source, always create standard Hash schema in destination
-            if let Normalized {
-                hash_view,
-                schema: NormalizedSchema::DedupId,
-            } = dt
-            {
-                dt = Normalized {
-                    hash_view,
-                    schema: NormalizedSchema::Hash,
-                };
-            }
-            dt
+            self.new_dst_type(src_type)
         } else {
             self.validate_dst_type(self.dst_mbt.detect_type(&mut conn).await?)?
         };
@@ -210,6 +258,7 @@ impl MbtileCopierInt {
         self.copy_with_rusqlite(
             &mut conn,
             on_duplicate,
+            src_type,
             dst_type,
             &get_select_from(src_type, dst_type),
         )
@@ -224,6 +273,91 @@ impl MbtileCopierInt {
         Ok(conn)
     }
 
+    #[hotpath::measure]
+    async fn dry_run(self) -> MbtResult<CopyPlan> {
+        if self.options.diff_with_file.is_some() || self.options.apply_patch.is_some() {
+            return Err(MbtError::CannotDryRunDiffOrPatch);
+        }
+
+        let mut conn = self.src_mbt.open_readonly().await?;
+        let src_type = self.src_mbt.detect_type(&mut conn).await?;
+        let (tiles, tile_bytes) = if self.options.copy.copy_tiles() {
+            let sql = format!(
+                "SELECT COUNT(*), COALESCE(SUM(LENGTH(tile_data)), 0) FROM tiles WHERE TRUE {}",
+                self.get_where_clause("")
+            );
+            let row = query(&sql).fetch_one(&mut conn).await?;
+            let tiles: i64 = row.get(0);
+            let tile_bytes: i64 = row.get(1);
+            (tiles.unsigned_abs(), tile_bytes.unsigned_abs())
+        } else {
+            (0, 0)
+        };
+        let mut metadata: Vec<String> = if self.options.copy.copy_metadata() {
+            query("SELECT name FROM metadata")
+                .fetch_all(&mut conn)
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if self.options.copy.copy_tiles() && !self.options.skip_agg_tiles_hash {
+            metadata.push(AGG_TILES_HASH.to_string());
+        }
+        conn.close().await?;
+
+        let dst_conn = if self.options.dst_file.exists() {
+            Some(self.dst_mbt.open_readonly().await?)
+        } else {
+            // In-memory and URI databases can be opened even though no such file exists
+            self.dst_mbt.open_readonly().await.ok()
+        };
+        let mut existing_type = None;
+        if let Some(mut conn) = dst_conn {
+            if !is_empty_database(&mut conn).await? {
+                let dst_type = self.dst_mbt.detect_type(&mut conn).await?;
+                existing_type = Some(self.validate_dst_type(dst_type)?);
+            }
+            conn.close().await?;
+        }
+
+        let on_duplicate = match (self.options.on_duplicate, existing_type) {
+            (Some(on_duplicate), _) => on_duplicate,
+            (None, None) => CopyDuplicateMode::Override,
+            (None, Some(_)) => return Err(MbtError::DestinationFileExists(self.options.dst_file)),
+        };
+
+        Ok(CopyPlan {
+            src_type,
+            dst_type: existing_type.unwrap_or_else(|| self.new_dst_type(src_type)),
+            dst_is_new: existing_type.is_none(),
+            on_duplicate,
+            tiles,
+            tile_bytes,
+            metadata: metadata.into_iter().sorted().dedup().collect(),
+        })
+    }
+
+    /// Type of a new destination file when copying from a source of the given type
+    fn new_dst_type(&self, src_type: MbtType) -> MbtType {
+        let dst_type = self.options.dst_type().unwrap_or(src_type);
+        // When copying from a DedupId source, always create standard Hash schema in destination
+        if let Normalized {
+            hash_view,
+            schema: NormalizedSchema::DedupId,
+        } = dst_type
+        {
+            Normalized {
+                hash_view,
+                schema: NormalizedSchema::Hash,
+            }
+        } else {
+            dst_type
+        }
+    }
+
     /// Compare two files, and write their difference to the diff file
     #[hotpath::measure]
     async fn run_with_diff(
@@ -267,6 +401,7 @@ impl MbtileCopierInt {
         self.copy_with_rusqlite(
             &mut conn,
             CopyDuplicateMode::Override,
+            src_info.mbt_type,
             dst_type,
             &get_select_from_with_diff(dif_info.mbt_type, dst_type, patch_type),
         )
@@ -341,6 +476,7 @@ impl MbtileCopierInt {
         self.copy_with_rusqlite(
             &mut conn,
             CopyDuplicateMode::Override,
+            src_type,
             dst_type,
             &get_select_from_apply_patch(src_type, &dif_info, dst_type),
         )
@@ -428,12 +564,13 @@ impl MbtileCopierInt {
         &self,
         conn: &mut SqliteConnection,
         on_duplicate: CopyDuplicateMode,
+        src_type: MbtType,
         dst_type: MbtType,
         select_from: &str,
     ) -> Result<(), MbtError> {
         if self.options.copy.copy_tiles() {
             action_with_rusqlite(conn, |c| {
-                self.copy_tiles(c, dst_type, on_duplicate, select_from)
+                self.copy_tiles(c, src_type, dst_type, on_duplicate, select_from)
             })
             .await?;
         } else {
@@ -504,13 +641,35 @@ impl MbtileCopierInt {
     fn copy_tiles(
         &self,
         rusqlite_conn: &Connection,
+        src_type: MbtType,
         dst_type: MbtType,
         on_duplicate: CopyDuplicateMode,
         select_from: &str,
     ) -> Result<(), MbtError> {
         let on_dupl = on_duplicate.to_sql();
-        let where_clause = self.get_where_clause("");
-        let sql_cond = Self::get_on_duplicate_sql_cond(on_duplicate, dst_type);
+        let mut where_clause = self.get_where_clause("");
+        let sql_cond = Self::get_on_duplicate_sql_cond(on_duplicate, src_type, dst_type);
+
+        // Blank tiles are counted while copying to avoid scanning the source one more time
+        let blank_tiles = Arc::new(AtomicU64::new(0));
+        if let Some(policy) = &self.options.skip_blank {
+            let policy = policy.clone();
+            let blank_tiles = Arc::clone(&blank_tiles);
+            rusqlite_conn.create_scalar_function(
+                "is_blank_tile",
+                1,
+                // not deterministic: every call counts the skipped tiles
+                FunctionFlags::SQLITE_UTF8,
+                move |ctx| {
+                    let is_blank = ctx.get_raw(0).as_blob().is_ok_and(|v| policy.is_blank(v));
+                    if is_blank {
+                        blank_tiles.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(is_blank)
+                },
+            )?;
+            where_clause.push_str(" AND NOT is_blank_tile(tile_data)");
+        }
 
         let sql = match dst_type {
             Flat => {
@@ -551,7 +710,21 @@ impl MbtileCopierInt {
         };
 
         debug!("Copying to {dst_type} with {sql}");
-        rusqlite_conn.execute(&sql, [])?;
+        // Normalized files already filtered blank tiles once while copying images
+        blank_tiles.store(0, Ordering::Relaxed);
+        let copied = rusqlite_conn.execute(&sql, [])?;
+        if self.options.skip_blank.is_some() {
+            info!(
+                "Skipped {} blank tiles",
+                blank_tiles.load(Ordering::Relaxed)
+            );
+        }
+        if on_duplicate == CopyDuplicateMode::OverrideIfChanged {
+            info!(
+                "Copied {copied} tiles, skipping the ones that are identical in {}",
+                self.dst_mbt
+            );
+        }
 
         Ok(())
     }
@@ -615,14 +788,25 @@ impl MbtileCopierInt {
         } else {
             init_mbtiles_schema(&mut *conn, dst).await?;
         }
+        self.dst_mbt.invalidate_type_cache();
 
         Ok(())
     }
 
     /// Returns WHERE condition SQL depending on the override and destination type
-    fn get_on_duplicate_sql_cond(on_duplicate: CopyDuplicateMode, dst_type: MbtType) -> String {
+    fn get_on_duplicate_sql_cond(
+        on_duplicate: CopyDuplicateMode,
+        src_type: MbtType,
+        dst_type: MbtType,
+    ) -> String {
         match on_duplicate {
             CopyDuplicateMode::Ignore | CopyDuplicateMode::Override => String::new(),
+            CopyDuplicateMode::OverrideIfChanged => {
+                format!(
+                    "AND NOT {}",
+                    Self::get_unchanged_tile_sql_cond(src_type, dst_type)
+                )
+            }
             CopyDuplicateMode::Abort => {
                 let (main_table, tile_identifier) = match dst_type {
                     Flat => ("tiles", "tile_data"),
@@ -645,6 +829,45 @@ impl MbtileCopierInt {
         }
     }
 
+    /// Returns SQL condition matching source tiles that already exist with identical content in the destination.
+    ///
+    /// Hashed destinations are compared by hash, flat ones by the tile data itself.
+    fn get_unchanged_tile_sql_cond(src_type: MbtType, dst_type: MbtType) -> String {
+        let (src_table, src_id) = match (dst_type, src_type) {
+            (Flat, _) => ("tiles", "sourceDb.tiles.tile_data".to_string()),
+            (_, Flat) => ("tiles", "md5_hex(sourceDb.tiles.tile_data)".to_string()),
+            (_, FlatWithHash) => (
+                "tiles_with_hash",
+                "sourceDb.tiles_with_hash.tile_hash".to_string(),
+            ),
+            (_, Normalized { schema, .. }) => (
+                schema.map_table(),
+                format!(
+                    "sourceDb.{}.{}",
+                    schema.map_table(),
+                    schema.tile_id_column()
+                ),
+            ),
+        };
+        let (dst_table, dst_id) = match dst_type {
+            Flat => ("tiles", "tile_data"),
+            FlatWithHash => ("tiles_with_hash", "tile_hash"),
+            Normalized { schema, .. } => (schema.map_table(), schema.tile_id_column()),
+        };
+
+        format!(
+            "EXISTS (
+                 SELECT 1
+                 FROM main.{dst_table} AS dstTiles
+                 WHERE
+                     dstTiles.zoom_level = sourceDb.{src_table}.zoom_level
+                     AND dstTiles.tile_column = sourceDb.{src_table}.tile_column
+                     AND dstTiles.tile_row = sourceDb.{src_table}.tile_row
+                     AND dstTiles.{dst_id} = {src_id}
+             )"
+        )
+    }
+
     /// Format SQL WHERE clause and return it along with the query arguments.
     /// Note that there is no risk of SQL injection here, as the arguments are integers.
     fn get_where_clause(&self, prefix: &str) -> String {
@@ -1072,6 +1295,45 @@ mod tests {
         verify_copy_with_zoom_filter(opt, 2).await;
     }
 
+    #[actix_rt::test]
+    async fn dry_run() {
+        let script = include_str!("../../tests/fixtures/mbtiles/world_cities.sql");
+        let (_mbt, _conn, src_file) = temp_named_mbtiles("src_dry_run_mem", script).await;
+        let dir = tempfile::tempdir().unwrap();
+        let dst_file = dir.path().join("dry_run.mbtiles");
+
+        let opt = MbtilesCopier {
+            src_file: src_file.clone(),
+            dst_file: dst_file.clone(),
+            min_zoom: Some(2),
+            max_zoom: Some(4),
+            ..Default::default()
+        };
+        let plan = opt.clone().dry_run().await.unwrap();
+        assert!(!dst_file.exists());
+        assert_eq!(plan.src_type, Flat);
+        assert_eq!(plan.dst_type, Flat);
+        assert!(plan.dst_is_new);
+        assert_eq!(plan.on_duplicate, CopyDuplicateMode::Override);
+        assert!(plan.metadata.contains(&AGG_TILES_HASH.to_string()));
+
+        let mut dst_conn = opt.clone().run().await.unwrap();
+        assert_eq!(
+            get_one::<u64>(&mut dst_conn, "SELECT COUNT(*) FROM tiles").await,
+            plan.tiles
+        );
+        assert_eq!(
+            get_one::<u64>(&mut dst_conn, "SELECT SUM(LENGTH(tile_data)) FROM tiles").await,
+            plan.tile_bytes
+        );
+        dst_conn.close().await.unwrap();
+
+        assert!(matches!(
+            opt.dry_run().await.unwrap_err(),
+            MbtError::DestinationFileExists(..)
+        ));
+    }
+
     #[actix_rt::test]
     async fn copy_with_diff_with_file() {
         let script = include_str!("../../tests/fixtures/mbtiles/geography-class-jpg.sql");
@@ -1133,6 +1395,57 @@ mod tests {
         );
     }
 
+    #[actix_rt::test]
+    async fn copy_skip_blank() {
+        let script = "
+            CREATE TABLE metadata (name text, value text);
+            CREATE TABLE tiles (zoom_level integer, tile_column integer, tile_row integer, tile_data blob);
+            CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+            INSERT INTO tiles VALUES
+                (0, 0, 0, X'1A050A01617802'),
+                (1, 0, 0, X'1A090A0162120012007802'),
+                (1, 1, 0, X''),
+                (1, 1, 1, X'0102');";
+        let (_mbt, _conn, src_file) = temp_named_mbtiles("src_copy_skip_blank_mem", script).await;
+
+        for (name, dst_type_cli, policy, expected) in [
+            ("flat_heuristic", FLAT, BlankPolicy::Heuristic, 2),
+            ("norm_heuristic", NORM_CLI, BlankPolicy::Heuristic, 2),
+            ("flat_sample", FLAT, BlankPolicy::Sample(vec![1, 2]), 3),
+        ] {
+            let dst_file = PathBuf::from(format!(
+                "file:copy_skip_blank_{name}_mem_db?mode=memory&cache=shared"
+            ));
+            let mut dst_conn = MbtilesCopier {
+                src_file: src_file.clone(),
+                dst_file,
+                dst_type_cli,
+                skip_blank: Some(policy),
+                ..Default::default()
+            }
+            .run()
+            .await
+            .unwrap();
+            assert_eq!(
+                get_one::<u32>(&mut dst_conn, "SELECT COUNT(*) FROM tiles").await,
+                expected,
+                "{name}"
+            );
+        }
+
+        let opt = MbtilesCopier {
+            src_file: src_file.clone(),
+            dst_file: PathBuf::from("file:copy_skip_blank_diff_mem_db?mode=memory&cache=shared"),
+            diff_with_file: Some((src_file, None)),
+            skip_blank: Some(BlankPolicy::Heuristic),
+            ..Default::default()
+        };
+        assert!(matches!(
+            opt.run().await,
+            Err(MbtError::CannotSkipBlankWithDiffOrPatch)
+        ));
+    }
+
     #[actix_rt::test]
     async fn copy_to_existing_abort_mode() {
         let script = include_str!("../../tests/fixtures/mbtiles/world_cities_modified.sql");
@@ -1202,6 +1515,77 @@ mod tests {
         );
     }
 
+    #[actix_rt::test]
+    async fn copy_to_existing_override_if_changed_mode() {
+        let script = include_str!("../../tests/fixtures/mbtiles/world_cities_modified.sql");
+        let (_mbt, _conn, src_file) =
+            temp_named_mbtiles("src_copy_to_existing_override_if_changed_mode", script).await;
+
+        let script = include_str!("../../tests/fixtures/mbtiles/world_cities.sql");
+        let (_mbt, _conn, dst_file) =
+            temp_named_mbtiles("dst_copy_to_existing_override_if_changed_mode", script).await;
+
+        let dst = PathBuf::from(
+            "file:copy_to_existing_override_if_changed_mode_mem_db?mode=memory&cache=shared",
+        );
+
+        let mut dst_conn = MbtilesCopier {
+            src_file: dst_file.clone(),
+            dst_file: dst.clone(),
+            dst_type_cli: FLAT_WITH_HASH,
+            ..Default::default()
+        }
+        .run()
+        .await
+        .unwrap();
+
+        // Count every tile write to verify that identical tiles are skipped
+        dst_conn
+            .execute(
+                "CREATE TABLE writes (n INTEGER);
+                 CREATE TRIGGER count_writes AFTER INSERT ON tiles_with_hash
+                 BEGIN INSERT INTO writes VALUES (1); END;",
+            )
+            .await
+            .unwrap();
+        Mbtiles::new(&src_file)
+            .unwrap()
+            .attach_to(&mut dst_conn, "testSrcDb")
+            .await
+            .unwrap();
+        let changed: i64 = get_one(
+            &mut dst_conn,
+            "SELECT COUNT(*) FROM (SELECT * FROM testSrcDb.tiles EXCEPT SELECT zoom_level, tile_column, tile_row, tile_data FROM tiles_with_hash)",
+        )
+        .await;
+        assert!(changed > 0);
+
+        let opt = MbtilesCopier {
+            src_file: src_file.clone(),
+            dst_file: dst.clone(),
+            on_duplicate: Some(CopyDuplicateMode::OverrideIfChanged),
+            ..Default::default()
+        };
+        let mut dst_conn = opt.run().await.unwrap();
+
+        let writes: i64 = get_one(&mut dst_conn, "SELECT COUNT(*) FROM writes").await;
+        assert_eq!(writes, changed);
+
+        // The result must be the same as with the override mode
+        Mbtiles::new(src_file)
+            .unwrap()
+            .attach_to(&mut dst_conn, "testOtherDb")
+            .await
+            .unwrap();
+        assert!(
+            dst_conn
+                .fetch_optional("SELECT * FROM testOtherDb.tiles EXCEPT SELECT * FROM tiles;")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
     #[actix_rt::test]
     async fn copy_to_existing_ignore_mode() {
         let script = include_str!("../../tests/fixtures/mbtiles/world_cities_modified.sql");
@@ -0,0 +1,151 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use serde::Serialize;
+use size_format::SizeFormatterSI;
+use sqlx::{Row as _, SqliteConnection, SqliteExecutor, query};
+
+use crate::{MbtResult, MbtType, Mbtiles, MbtilesCopier, NormalizedSchema};
+
+/// How much space duplicate tiles take up, as computed by [`Mbtiles::dedup_report`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DedupReport {
+    /// Number of tiles
+    pub tiles: u64,
+    /// Number of distinct tile contents
+    pub unique_tiles: u64,
+    /// Total size of the tile data of all tiles
+    pub tile_bytes: u64,
+    /// Size of the tile data that a normalized file would not have to store,
+    /// i.e. the total size of all tiles minus the size of the distinct tile contents.
+    /// This does not account for `SQLite` overhead or the size of the hashes.
+    pub duplicate_bytes: u64,
+}
+
+impl Display for DedupReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:15} {}", "Tiles:", self.tiles)?;
+        writeln!(f, "{:15} {}", "Unique tiles:", self.unique_tiles)?;
+        let tile_bytes = SizeFormatterSI::new(self.tile_bytes);
+        writeln!(f, "{:15} {tile_bytes:.2}B", "Tile data:")?;
+        let duplicate_bytes = SizeFormatterSI::new(self.duplicate_bytes);
+        writeln!(f, "{:15} {duplicate_bytes:.2}B", "Duplicates:")
+    }
+}
+
+impl Mbtiles {
+    /// Hash all tiles and report how much space is taken up by tiles with identical content.
+    ///
+    /// Tiles are counted as if stored in a flat file. Normalized files already store each
+    /// distinct tile once, so this tells whether [`convert_to_normalized`](Self::convert_to_normalized)
+    /// would be worth it for a flat one.
+    #[hotpath::measure]
+    pub async fn dedup_report<T>(&self, conn: &mut T) -> MbtResult<DedupReport>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        let row = query(
+            "SELECT COUNT(*), COALESCE(SUM(cnt), 0), COALESCE(SUM(size * cnt), 0), COALESCE(SUM(size), 0)
+             FROM (SELECT COUNT(*) AS cnt, MAX(LENGTH(tile_data)) AS size
+                   FROM tiles
+                   GROUP BY md5_hex(tile_data))",
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        let unique_tiles: i64 = row.get(0);
+        let tiles: i64 = row.get(1);
+        let tile_bytes: i64 = row.get(2);
+        let unique_bytes: i64 = row.get(3);
+        Ok(DedupReport {
+            tiles: tiles.unsigned_abs(),
+            unique_tiles: unique_tiles.unsigned_abs(),
+            tile_bytes: tile_bytes.unsigned_abs(),
+            duplicate_bytes: tile_bytes.abs_diff(unique_bytes),
+        })
+    }
+
+    /// Copy this file to a new normalized `dst_file`, storing each distinct tile only once.
+    ///
+    /// The destination uses the standard `map` and `images` tables with a `tiles_with_hash` view.
+    #[hotpath::measure]
+    pub async fn convert_to_normalized<P: AsRef<Path>>(
+        &self,
+        dst_file: P,
+    ) -> MbtResult<SqliteConnection> {
+        let copier = MbtilesCopier {
+            src_file: self.filepath().into(),
+            dst_file: dst_file.as_ref().to_path_buf(),
+            dst_type: Some(MbtType::Normalized {
+                hash_view: true,
+                schema: NormalizedSchema::Hash,
+            }),
+            ..Default::default()
+        };
+        // The copier future is large, keep it on the heap to not overflow the caller's stack
+        Box::pin(copier.run()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Connection as _;
+
+    use crate::{CopyDuplicateMode, DedupReport, MbtType, Mbtiles, NormalizedSchema};
+
+    #[actix_rt::test]
+    async fn dedup_report_and_convert() {
+        let dir = tempfile::tempdir().unwrap();
+        let mbt = Mbtiles::new(dir.path().join("flat.mbtiles")).unwrap();
+        let mut conn = mbt.open_or_new().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, MbtType::Flat)
+            .await
+            .unwrap();
+        assert_eq!(
+            mbt.dedup_report(&mut conn).await.unwrap(),
+            DedupReport::default()
+        );
+
+        let ocean = vec![1, 2, 3, 4];
+        let tiles = [
+            (1, 0, 0, ocean.clone()),
+            (1, 1, 0, ocean.clone()),
+            (1, 0, 1, ocean),
+            (1, 1, 1, vec![5, 6]),
+        ];
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &tiles,
+        )
+        .await
+        .unwrap();
+        let report = mbt.dedup_report(&mut conn).await.unwrap();
+        assert_eq!(
+            report,
+            DedupReport {
+                tiles: 4,
+                unique_tiles: 2,
+                tile_bytes: 14,
+                duplicate_bytes: 8,
+            }
+        );
+        conn.close().await.unwrap();
+
+        let dst = Mbtiles::new(dir.path().join("normalized.mbtiles")).unwrap();
+        let mut dst_conn = mbt.convert_to_normalized(dst.filepath()).await.unwrap();
+        assert_eq!(
+            dst.detect_type(&mut dst_conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: true,
+                schema: NormalizedSchema::Hash,
+            }
+        );
+        let images: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM images")
+            .fetch_one(&mut dst_conn)
+            .await
+            .unwrap();
+        assert_eq!(images, 2);
+        assert_eq!(dst.count_tiles(&mut dst_conn).await.unwrap(), 4);
+    }
+}
@@ -0,0 +1,224 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use futures::TryStreamExt as _;
+use serde::Serialize;
+use size_format::SizeFormatterSI;
+use sqlx::{Row as _, SqliteConnection, query};
+
+use crate::{MbtResult, MbtType, Mbtiles, NormalizedSchema};
+
+/// Counts of tiles that differ between two `MBTiles` files, as computed by [`Mbtiles::diff_summary`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DiffSummary {
+    /// Tiles present only in the new file
+    pub added: u64,
+    /// Tiles present only in the old file
+    pub removed: u64,
+    /// Tiles present in both files with different content
+    pub changed: u64,
+    /// Tiles present in both files with identical content
+    pub unchanged: u64,
+    /// Approximate size of the tile data a patch would have to store,
+    /// i.e. the total size of all added and changed tiles in the new file.
+    /// This does not account for `SQLite` overhead or bin-diff encoding.
+    pub patch_bytes: u64,
+}
+
+impl DiffSummary {
+    /// Returns `true` if both files contain the same tiles.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.changed == 0
+    }
+}
+
+impl Display for DiffSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:15} {}", "Added:", self.added)?;
+        writeln!(f, "{:15} {}", "Removed:", self.removed)?;
+        writeln!(f, "{:15} {}", "Changed:", self.changed)?;
+        writeln!(f, "{:15} {}", "Unchanged:", self.unchanged)?;
+        let patch_bytes = SizeFormatterSI::new(self.patch_bytes);
+        writeln!(f, "{:15} {patch_bytes:.2}B", "Patch size:")
+    }
+}
+
+/// SQL returning `zoom_level, tile_column, tile_row, tile_hash, tile_size` for every tile,
+/// ordered by tile index. Stored hashes are used whenever the schema has them.
+fn ordered_hashes_sql(mbt_type: MbtType) -> &'static str {
+    match mbt_type {
+        MbtType::FlatWithHash
+        | MbtType::Normalized {
+            hash_view: true, ..
+        } => {
+            "SELECT zoom_level, tile_column, tile_row, upper(tile_hash), length(tile_data)
+             FROM tiles_with_hash
+             ORDER BY zoom_level, tile_column, tile_row"
+        }
+        MbtType::Normalized {
+            hash_view: false,
+            schema: NormalizedSchema::Hash,
+        } => {
+            "SELECT map.zoom_level, map.tile_column, map.tile_row, upper(map.tile_id), length(images.tile_data)
+             FROM map JOIN images ON map.tile_id = images.tile_id
+             ORDER BY map.zoom_level, map.tile_column, map.tile_row"
+        }
+        MbtType::Flat
+        | MbtType::Normalized {
+            hash_view: false,
+            schema: NormalizedSchema::DedupId,
+        } => {
+            "SELECT zoom_level, tile_column, tile_row, md5_hex(tile_data), length(tile_data)
+             FROM tiles
+             ORDER BY zoom_level, tile_column, tile_row"
+        }
+    }
+}
+
+type HashedTile = ((i64, i64, i64), Option<String>, i64);
+
+impl Mbtiles {
+    /// Compare this (old) file with a `new` one, counting tiles that were added, removed, or changed,
+    /// and estimating how large a patch between the two would be.
+    ///
+    /// Both tile sets are streamed in tile index order and compared by hash,
+    /// so no patch database is created and memory usage stays constant.
+    /// Hashes stored in the file are used when available, otherwise they are computed on the fly.
+    #[hotpath::measure]
+    pub async fn diff_summary(
+        &self,
+        old_conn: &mut SqliteConnection,
+        new: &Self,
+        new_conn: &mut SqliteConnection,
+    ) -> MbtResult<DiffSummary> {
+        let old_type = self.detect_type(&mut *old_conn).await?;
+        let new_type = new.detect_type(&mut *new_conn).await?;
+
+        let map_row = |row: sqlx::sqlite::SqliteRow| -> HashedTile {
+            let size: Option<i64> = row.get(4);
+            (
+                (row.get(0), row.get(1), row.get(2)),
+                row.get(3),
+                size.unwrap_or(0),
+            )
+        };
+        let mut old_tiles = query(ordered_hashes_sql(old_type))
+            .fetch(&mut *old_conn)
+            .map_ok(map_row);
+        let mut new_tiles = query(ordered_hashes_sql(new_type))
+            .fetch(&mut *new_conn)
+            .map_ok(map_row);
+
+        let mut summary = DiffSummary::default();
+        let mut old = old_tiles.try_next().await?;
+        let mut new = new_tiles.try_next().await?;
+        loop {
+            // Once one side is exhausted, the remaining tiles of the other side are matched by their presence alone
+            let ord = match (&old, &new) {
+                (Some((old_idx, ..)), Some((new_idx, ..))) => old_idx.cmp(new_idx),
+                _ => Ordering::Equal,
+            };
+            match (ord, &old, &new) {
+                (_, None, None) => break,
+                (Ordering::Less, ..) | (_, Some(_), None) => {
+                    summary.removed += 1;
+                    old = old_tiles.try_next().await?;
+                }
+                (Ordering::Greater, _, Some((_, _, size))) | (_, None, Some((_, _, size))) => {
+                    summary.added += 1;
+                    summary.patch_bytes += size.unsigned_abs();
+                    new = new_tiles.try_next().await?;
+                }
+                (_, Some((_, old_hash, _)), Some((_, new_hash, size))) => {
+                    if old_hash == new_hash {
+                        summary.unchanged += 1;
+                    } else {
+                        summary.changed += 1;
+                        summary.patch_bytes += size.unsigned_abs();
+                    }
+                    old = old_tiles.try_next().await?;
+                    new = new_tiles.try_next().await?;
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use sqlx::SqliteConnection;
+
+    use crate::{CopyDuplicateMode, DiffSummary, MbtType, Mbtiles, NormalizedSchema};
+
+    async fn new_mbt(
+        mbt_type: MbtType,
+        tiles: &[(u8, u32, u32, Vec<u8>)],
+    ) -> (Mbtiles, SqliteConnection) {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, mbt_type)
+            .await
+            .unwrap();
+        mbt.insert_tiles(&mut conn, mbt_type, CopyDuplicateMode::Override, tiles)
+            .await
+            .unwrap();
+        (mbt, conn)
+    }
+
+    #[rstest]
+    #[case::flat(MbtType::Flat, MbtType::Flat)]
+    #[case::flat_with_hash(MbtType::FlatWithHash, MbtType::FlatWithHash)]
+    #[case::normalized(
+        MbtType::Normalized { hash_view: false, schema: NormalizedSchema::Hash },
+        MbtType::Normalized { hash_view: false, schema: NormalizedSchema::Hash },
+    )]
+    #[case::mixed(MbtType::Flat, MbtType::FlatWithHash)]
+    #[actix_rt::test]
+    async fn diff_summary(#[case] old_type: MbtType, #[case] new_type: MbtType) {
+        let (old, mut old_conn) = new_mbt(
+            old_type,
+            &[
+                (0, 0, 0, vec![1, 2, 3]),
+                (1, 0, 0, vec![4, 5]),
+                (1, 1, 0, vec![6]),
+            ],
+        )
+        .await;
+        let (new, mut new_conn) = new_mbt(
+            new_type,
+            &[
+                (0, 0, 0, vec![1, 2, 3]),
+                (1, 0, 0, vec![4, 5, 6, 7]),
+                (2, 0, 0, vec![8, 9]),
+            ],
+        )
+        .await;
+
+        let summary = old
+            .diff_summary(&mut old_conn, &new, &mut new_conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            DiffSummary {
+                added: 1,
+                removed: 1,
+                changed: 1,
+                unchanged: 1,
+                patch_bytes: 6,
+            }
+        );
+        assert!(!summary.is_empty());
+
+        let summary = old
+            .diff_summary(&mut old_conn, &old, &mut old.open().await.unwrap())
+            .await
+            .unwrap();
+        assert!(summary.is_empty());
+        assert_eq!(summary.unchanged, 3);
+    }
+}
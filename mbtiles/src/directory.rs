@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use futures::TryStreamExt as _;
+use log::{debug, info};
+use martin_tile_utils::{Format, TileCoord, TileScheme};
+use sqlx::SqliteConnection;
+use tilejson::Bounds;
+
+use crate::{
+    CopyDuplicateMode, MbtError, MbtResult, MbtType, Mbtiles, UpdateZoomType, init_mbtiles_schema,
+};
+
+/// Number of tiles read from disk before they are inserted in one transaction
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+impl Mbtiles {
+    /// Writes every tile into `dir` as a `{z}/{x}/{y}.{ext}` file, creating directories as needed
+    /// and overwriting existing files.
+    ///
+    /// The extension is the detected tile format as stored in the `format` metadata value, e.g. `pbf` or `png`.
+    /// Tiles are written as-is, so compressed vector tiles stay compressed.
+    /// If `with_metadata` is set, the metadata is also written as a `TileJSON` `metadata.json` file,
+    /// with a relative tile URL template matching the directory layout.
+    ///
+    /// Returns the number of written tiles.
+    #[hotpath::measure]
+    pub async fn export_to_directory(
+        &self,
+        conn: &mut SqliteConnection,
+        dir: &Path,
+        scheme: TileScheme,
+        with_metadata: bool,
+    ) -> MbtResult<u64> {
+        let metadata = self.get_metadata(&mut *conn).await?;
+        let info = self
+            .detect_format(&metadata.tilejson, &mut *conn)
+            .await?
+            .ok_or(MbtError::NoTilesFound)?;
+        let ext = info.format.metadata_format_value();
+        info!(
+            "Exporting {self} ({info}) to directory {} using {scheme} scheme",
+            dir.display()
+        );
+
+        fs::create_dir_all(dir)?;
+        if with_metadata {
+            let mut tj = metadata.tilejson;
+            tj.tiles = vec![format!("{{z}}/{{x}}/{{y}}.{ext}")];
+            tj.scheme = Some(scheme.to_string());
+            tj.other
+                .insert("format".to_string(), ext.to_string().into());
+            let file = fs::File::create(dir.join("metadata.json"))?;
+            serde_json::to_writer_pretty(file, &tj)?;
+        }
+
+        let mut count = 0;
+        let mut tiles = self.stream_tiles(&mut *conn);
+        while let Some((coord, data)) = tiles.try_next().await? {
+            let Some(data) = data else {
+                continue;
+            };
+            let path = dir.join(coord.z.to_string()).join(coord.x.to_string());
+            fs::create_dir_all(&path)?;
+            let y = scheme.convert_y(coord.z, coord.y);
+            fs::write(path.join(format!("{y}.{ext}")), data)?;
+            count += 1;
+        }
+        debug!("Exported {count} tiles from {self} to {}", dir.display());
+        Ok(count)
+    }
+
+    /// Imports all `{z}/{x}/{y}.{ext}` tile files from `dir`, e.g. as written by [`Mbtiles::export_to_directory`],
+    /// creating the tables of `mbt_type` if they do not exist yet. Existing tiles are overwritten.
+    ///
+    /// The tile format is taken from the file extensions, which must all refer to the same format.
+    /// Files and directories that do not follow this layout, like a `metadata.json`, are skipped.
+    /// Afterwards, the `format`, `minzoom`, `maxzoom` and `bounds` metadata values are set to match the imported tiles.
+    ///
+    /// Returns the number of imported tiles.
+    #[hotpath::measure]
+    pub async fn import_from_directory(
+        &self,
+        conn: &mut SqliteConnection,
+        dir: &Path,
+        mbt_type: MbtType,
+        scheme: TileScheme,
+    ) -> MbtResult<u64> {
+        let files = find_tile_files(dir, scheme)?;
+        let Some((_, _, format)) = files.first() else {
+            info!("No tiles found in directory {}", dir.display());
+            return Ok(0);
+        };
+        let format = *format;
+        if let Some((_, _, other)) = files.iter().find(|(_, _, f)| *f != format) {
+            return Err(MbtError::MixedTileFormats(
+                dir.to_path_buf(),
+                format,
+                *other,
+            ));
+        }
+        info!(
+            "Importing {} {format} tiles from directory {} into {self}",
+            files.len(),
+            dir.display()
+        );
+
+        init_mbtiles_schema(&mut *conn, mbt_type).await?;
+        self.invalidate_type_cache();
+        for chunk in files.chunks(IMPORT_BATCH_SIZE) {
+            let batch = chunk
+                .iter()
+                .map(|(coord, path, _)| Ok((coord.z, coord.x, coord.y, fs::read(path)?)))
+                .collect::<MbtResult<Vec<_>>>()?;
+            self.insert_tiles(&mut *conn, mbt_type, CopyDuplicateMode::Override, &batch)
+                .await?;
+        }
+
+        self.set_metadata_value(&mut *conn, "format", format.metadata_format_value())
+            .await?;
+        self.update_metadata(&mut *conn, UpdateZoomType::Reset)
+            .await?;
+        if let Some(bounds) = tiles_bounds(files.iter().map(|(coord, _, _)| *coord)) {
+            self.set_metadata_value(&mut *conn, "bounds", bounds)
+                .await?;
+        }
+
+        Ok(files.len() as u64)
+    }
+}
+
+/// Finds all `{z}/{x}/{y}.{ext}` files with a known tile format extension, converting `y` to the xyz scheme.
+fn find_tile_files(dir: &Path, scheme: TileScheme) -> MbtResult<Vec<(TileCoord, PathBuf, Format)>> {
+    let mut files = Vec::new();
+    for (z, z_dir) in numbered_entries(dir)? {
+        let Ok(z) = u8::try_from(z) else {
+            debug!("Skipping {}, not a zoom level", z_dir.display());
+            continue;
+        };
+        for (x, x_dir) in numbered_entries(&z_dir)? {
+            for entry in fs::read_dir(&x_dir)? {
+                let path = entry?.path();
+                let tile = path
+                    .file_stem()
+                    .and_then(|v| v.to_str()?.parse().ok())
+                    .zip(path.extension().and_then(|v| Format::parse(v.to_str()?)))
+                    .filter(|(y, _)| TileCoord::is_possible_on_zoom_level(z, x, *y));
+                if let Some((y, format)) = tile.filter(|_| path.is_file()) {
+                    let coord = TileCoord::new_unchecked(z, x, scheme.convert_y(z, y));
+                    files.push((coord, path, format));
+                } else {
+                    debug!("Skipping {}, not a tile file", path.display());
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Lists the subdirectories of `dir` whose names are numbers.
+fn numbered_entries(dir: &Path) -> MbtResult<Vec<(u32, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let number = path.file_name().and_then(|v| v.to_str()?.parse().ok());
+        match number {
+            Some(number) if path.is_dir() => entries.push((number, path)),
+            _ => debug!("Skipping {}, not a numbered directory", path.display()),
+        }
+    }
+    Ok(entries)
+}
+
+/// WGS84 bounds of the tiles on the highest zoom level, which are the most precise.
+fn tiles_bounds(coords: impl Iterator<Item = TileCoord>) -> Option<Bounds> {
+    let coords: Vec<_> = coords.collect();
+    let max_zoom = coords.iter().map(|c| c.z).max()?;
+    coords
+        .iter()
+        .filter(|c| c.z == max_zoom)
+        .map(|c| Bounds::from(c.lonlat_bounds()))
+        .reduce(|a, b| a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use martin_tile_utils::encode_gzip;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::calc_agg_tiles_hash;
+
+    #[rstest::rstest]
+    #[case::xyz(TileScheme::Xyz, "1/0/0.pbf")]
+    #[case::tms(TileScheme::Tms, "1/0/1.pbf")]
+    #[actix_rt::test]
+    async fn export_to_directory(#[case] scheme: TileScheme, #[case] path: &str) {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, MbtType::Flat).await.unwrap();
+        mbt.set_name(&mut conn, "Test").await.unwrap();
+        let tile = encode_gzip(&[0x1A, 0x03, 0x78, 0x02, 1]).unwrap();
+        let tiles = [(0, 0, 0, tile.clone()), (1, 0, 0, tile.clone())];
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &tiles,
+        )
+        .await
+        .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let count = mbt
+            .export_to_directory(&mut conn, dir.path(), scheme, true)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(fs::read(dir.path().join("0/0/0.pbf")).unwrap(), tile);
+        assert_eq!(fs::read(dir.path().join(path)).unwrap(), tile);
+
+        let meta: Value =
+            serde_json::from_slice(&fs::read(dir.path().join("metadata.json")).unwrap()).unwrap();
+        assert_eq!(meta["name"], "Test");
+        assert_eq!(meta["tiles"][0], "{z}/{x}/{y}.pbf");
+        assert_eq!(meta["scheme"], scheme.to_string());
+    }
+
+    #[actix_rt::test]
+    async fn import_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let tile = encode_gzip(&[0x1A, 0x03, 0x78, 0x02, 1]).unwrap();
+        for path in ["0/0/0.pbf", "1/1/1.pbf", "1/1/0.mvt"] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, &tile).unwrap();
+        }
+        // not tiles
+        fs::write(dir.path().join("metadata.json"), "{}").unwrap();
+        fs::write(dir.path().join("1/1/readme.txt"), "").unwrap();
+        fs::write(dir.path().join("1/1/2.pbf"), "").unwrap();
+        fs::create_dir_all(dir.path().join("1/foo")).unwrap();
+
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        let count = mbt
+            .import_from_directory(&mut conn, dir.path(), MbtType::Flat, TileScheme::Tms)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(mbt.get_tile(&mut conn, 1, 1, 0).await.unwrap(), Some(tile));
+        assert!(
+            mbt.contains(&mut conn, MbtType::Flat, 1, 1, 1)
+                .await
+                .unwrap()
+        );
+
+        let meta = mbt.get_metadata(&mut conn).await.unwrap();
+        assert_eq!(meta.tilejson.other["format"], "pbf");
+        assert_eq!(
+            (meta.tilejson.minzoom, meta.tilejson.maxzoom),
+            (Some(0), Some(1))
+        );
+        let bounds = meta.tilejson.bounds.unwrap();
+        assert_eq!((bounds.left, bounds.right), (0.0, 180.0));
+
+        fs::write(dir.path().join("1/1/1.png"), "").unwrap();
+        let err = mbt
+            .import_from_directory(&mut conn, dir.path(), MbtType::Flat, TileScheme::Tms)
+            .await;
+        assert!(matches!(err, Err(MbtError::MixedTileFormats(..))));
+    }
+
+    #[actix_rt::test]
+    async fn directory_round_trip() {
+        let script = include_str!("../../tests/fixtures/mbtiles/world_cities.sql");
+        let (src, mut src_conn) = crate::anonymous_mbtiles(script).await;
+        let dir = tempfile::tempdir().unwrap();
+        let exported = src
+            .export_to_directory(&mut src_conn, dir.path(), TileScheme::Xyz, true)
+            .await
+            .unwrap();
+
+        let dst = Mbtiles::new_in_memory();
+        let mut dst_conn = dst.open().await.unwrap();
+        let imported = dst
+            .import_from_directory(&mut dst_conn, dir.path(), MbtType::Flat, TileScheme::Xyz)
+            .await
+            .unwrap();
+        assert_eq!(imported, exported);
+        assert_eq!(
+            calc_agg_tiles_hash(&mut src_conn).await.unwrap(),
+            calc_agg_tiles_hash(&mut dst_conn).await.unwrap()
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use martin_tile_utils::{MAX_ZOOM, TileInfo};
+use martin_tile_utils::{Format, MAX_ZOOM, TileInfo};
 use sqlite_hashes::rusqlite;
 
 use crate::{AGG_TILES_HASH, AGG_TILES_HASH_AFTER_APPLY, AGG_TILES_HASH_BEFORE_APPLY, MbtType};
@@ -32,15 +32,32 @@ pub enum MbtError {
     #[error(transparent)]
     JsonSerdeError(#[from] serde_json::Error),
 
+    #[error("The directory {0} contains tiles of different formats: {1} and {2}")]
+    MixedTileFormats(PathBuf, Format, Format),
+
     #[error("MBTile filepath contains unsupported characters: {0}")]
     UnsupportedCharsInFilepath(PathBuf),
 
     #[error("Inconsistent tile formats detected: {0} vs {1}")]
     InconsistentMetadata(TileInfo, TileInfo),
 
+    #[deprecated(note = "`Mbtiles::detect_type` returns `MbtError::UnsupportedSchema` instead")]
     #[error("Invalid data format for MBTile file {0}")]
     InvalidDataFormat(String),
 
+    /// The file does not contain the tables of any supported [`MbtType`]:
+    /// a `tiles` table ([`MbtType::Flat`]), a `tiles_with_hash` table ([`MbtType::FlatWithHash`]),
+    /// `map` and `images` tables, or `tiles_shallow` and `tiles_data` tables ([`MbtType::Normalized`]).
+    /// `found_tables` lists the tables and views that exist in the file.
+    #[error(
+        "Unsupported schema of MBTile file {filepath}, expected a flat, flat-with-hash or normalized schema, but found tables: {}",
+        found_tables.join(", ")
+    )]
+    UnsupportedSchema {
+        filepath: String,
+        found_tables: Vec<String>,
+    },
+
     #[error("Integrity check failed for MBTile file {0} for the following reasons:\n    {1:?}")]
     FailedIntegrityCheck(String, Vec<String>),
 
@@ -53,9 +70,17 @@ pub enum MbtError {
     MissingTileReference(String, String, &'static str),
 
     #[error(
-        "At least one tile in the tiles table/view has an invalid value: zoom_level={1}, tile_column={2}, tile_row={3} in MBTile file {0}"
+        "At least one tile in the tiles table/view has an invalid value: zoom_level={z}, tile_column={x}, tile_row={y} in MBTile file {0}",
+        z = fmt_index(.1.as_deref()), x = fmt_index(.2.as_deref()), y = fmt_index(.3.as_deref())
     )]
-    InvalidTileIndex(String, String, String, String),
+    /// The zoom level, column and row as stored in the file, `None` for an SQL `NULL`.
+    /// Non-integer values keep their original value and type, e.g. `"1" (TEXT)` or `1.5 (REAL)`.
+    InvalidTileIndex(String, Option<String>, Option<String>, Option<String>),
+
+    #[error(
+        "Tile {1}/{2}/{3} does not match the '{4}' compression advertised by the metadata of MBTile file {0}"
+    )]
+    TileEncodingMismatch(String, u8, u32, u32, String),
 
     #[error(
         "Computed aggregate tiles hash {0} does not match tile data in metadata {1} for MBTile file {2}"
@@ -70,6 +95,9 @@ pub enum MbtError {
     #[error(r#"Filename "{0}" passed to SQLite must be valid UTF-8"#)]
     InvalidFilenameType(PathBuf),
 
+    #[error("Cannot VACUUM MBTiles file {0} while a transaction is open")]
+    VacuumInTransaction(String),
+
     #[error("No tiles found")]
     NoTilesFound,
 
@@ -81,6 +109,11 @@ pub enum MbtError {
     #[error("The file {0} does not have the required uniqueness constraint")]
     NoUniquenessConstraint(String),
 
+    #[error(
+        "The file {0} does not have the normalized map and images tables needed for the tiles_with_hash view"
+    )]
+    NoNormalizedTables(String),
+
     #[error("Could not copy MBTiles file: {reason}")]
     UnsupportedCopyOperation { reason: String },
 
@@ -90,6 +123,12 @@ pub enum MbtError {
     #[error("Applying a patch while diffing is not supported")]
     CannotApplyPatchAndDiff,
 
+    #[error("Skipping blank tiles is not supported when diffing or applying a patch")]
+    CannotSkipBlankWithDiffOrPatch,
+
+    #[error("A dry run is not supported when diffing or applying a patch")]
+    CannotDryRunDiffOrPatch,
+
     #[error("The MBTiles file {0} has data of type {1}, but the desired type was set to {2}")]
     MismatchedTargetType(PathBuf, MbtType, MbtType),
 
@@ -98,9 +137,45 @@ pub enum MbtError {
     )]
     DestinationFileExists(PathBuf),
 
+    #[cfg(feature = "raster")]
+    #[error(
+        "Cannot transcode tiles from {0} to {1}, only PNG, JPEG and WebP raster tiles are supported"
+    )]
+    UnsupportedTranscode(Format, Format),
+
+    #[cfg(feature = "raster")]
+    #[error(transparent)]
+    ImageError(#[from] image::ImageError),
+
+    #[cfg(feature = "raster")]
+    #[error("Tile transcoding task failed: {0}")]
+    TranscodeTaskError(#[source] tokio::task::JoinError),
+
+    #[cfg(feature = "pmtiles")]
+    #[error(transparent)]
+    PmtilesError(#[from] pmtiles::PmtError),
+
+    #[cfg(feature = "pmtiles")]
+    #[error("Tiles with {0} cannot be stored in a PMTiles archive")]
+    UnsupportedPmtilesTiles(TileInfo),
+
     #[error("Invalid zoom value {0}={1}, expecting an integer between 0..{MAX_ZOOM}")]
     InvalidZoomValue(&'static str, String),
 
+    #[error(
+        "Invalid zoom range {0}..={1}, the minimum zoom must not be greater than the maximum zoom"
+    )]
+    InvalidZoomRange(u8, u8),
+
+    #[error("Invalid gzip compression level {0}, expecting an integer between 1..=9")]
+    InvalidCompressionLevel(u8),
+
+    #[error("Metadata value {0} must not be empty")]
+    EmptyMetadataValue(&'static str),
+
+    #[error("Inconsistent TileJSON metadata for MBTiles file {0}: {1}")]
+    InconsistentTileJson(String, String),
+
     #[error(
         "A file {0} does not have an {AGG_TILES_HASH} metadata entry, probably because it was not created by this tool. Use `--force` to ignore this warning, or run this to update hash value: `mbtiles validate --agg-hash update {0}`"
     )]
@@ -155,4 +230,21 @@ pub enum MbtError {
     TranscodeError(String),
 }
 
+impl MbtError {
+    /// Creates an [`MbtError::InvalidTileIndex`] from integer (or `NULL`) index values.
+    pub(crate) fn invalid_tile_index(
+        filepath: impl Into<String>,
+        z: Option<i64>,
+        x: Option<i64>,
+        y: Option<i64>,
+    ) -> Self {
+        let fmt = |v: Option<i64>| v.map(|v| v.to_string());
+        Self::InvalidTileIndex(filepath.into(), fmt(z), fmt(x), fmt(y))
+    }
+}
+
+fn fmt_index(value: Option<&str>) -> &str {
+    value.unwrap_or("NULL")
+}
+
 pub type MbtResult<T> = Result<T, MbtError>;
@@ -4,31 +4,61 @@
 pub use bindiff::{PatchType, PatchTypeCli};
 pub use sqlx;
 
+mod batch;
+pub use batch::{TileBatch, batched_tiles};
+
+mod blank;
+pub use blank::BlankPolicy;
+
 mod copier;
-pub use copier::{CopyDuplicateMode, MbtilesCopier};
+pub use copier::{CopyDuplicateMode, CopyPlan, MbtilesCopier};
+
+mod dedup;
+pub use dedup::DedupReport;
+
+mod diff;
+pub use diff::DiffSummary;
+
+mod directory;
+pub use martin_tile_utils::TileScheme;
 
 mod errors;
 pub use errors::{MbtError, MbtResult};
 
 mod mbtiles;
-pub use mbtiles::{CopyType, MbtTypeCli, Mbtiles};
+pub use mbtiles::{
+    CopyType, DEFAULT_BUSY_TIMEOUT, DEFAULT_WAL_AUTOCHECKPOINT, EncodingCheck,
+    MBTILES_APPLICATION_ID, MbtTypeCli, Mbtiles, TileWithHash,
+};
 
 mod metadata;
 pub use metadata::{Metadata, anonymous_mbtiles, temp_named_mbtiles};
 
+mod owned_stream;
+pub use owned_stream::OwnedTileStream;
+
 mod patcher;
 pub use patcher::apply_patch;
 
+#[cfg(feature = "pmtiles")]
+mod pmtiles_export;
+
 mod pool;
 pub use pool::MbtilesPool;
 
+mod progress;
+pub use progress::CopyProgress;
+
 mod queries;
 pub use queries::*;
 
+#[cfg(feature = "raster")]
+mod raster;
+
 mod summary;
 
 mod update;
-pub use update::UpdateZoomType;
+pub use update::{UpdateZoomType, ZoomRangeDeletion};
 
 mod bindiff;
 
@@ -55,8 +85,30 @@ pub use validation::{
 /// assert_eq!(invert_y_value(2, 0), 3);
 /// assert_eq!(invert_y_value(2, 1), 2);
 /// ```
+///
+/// # Panics
+/// Panics if `y` is greater than `(1 << zoom) - 1`, i.e. if it does not exist at this zoom level,
+/// or if `zoom` is 32 or more. Use [`checked_invert_y`] for values that are not known to be valid.
 #[inline]
 #[must_use]
 pub fn invert_y_value(zoom: u8, y: u32) -> u32 {
     (1u32 << zoom) - 1 - y
 }
+
+/// Same as [`invert_y_value`], but returns `None` instead of panicking
+/// if `y` does not exist at this zoom level.
+/// ```
+/// use mbtiles::checked_invert_y;
+/// assert_eq!(checked_invert_y(2, 0), Some(3));
+/// assert_eq!(checked_invert_y(2, 3), Some(0));
+/// assert_eq!(checked_invert_y(2, 4), None);
+/// assert_eq!(checked_invert_y(32, 0), None);
+/// ```
+#[inline]
+#[must_use]
+pub fn checked_invert_y(zoom: u8, y: u32) -> Option<u32> {
+    1_u32
+        .checked_shl(u32::from(zoom))?
+        .wrapping_sub(1)
+        .checked_sub(y)
+}
@@ -2,15 +2,18 @@ use std::ffi::OsStr;
 use std::fmt::{Display, Formatter};
 use std::path::Path;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use enum_display::EnumDisplay;
 use futures::Stream;
-use log::debug;
-use martin_tile_utils::{Tile, TileCoord};
+use log::{debug, warn};
+use martin_tile_utils::{Encoding, Tile, TileCoord, encode_gzip_with_level};
 use serde::{Deserialize, Serialize};
 use sqlite_compressions::{register_bsdiffraw_functions, register_gzip_functions};
 use sqlite_hashes::register_md5_functions;
-use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
 use sqlx::{
     Connection as _, Executor as _, Row as _, SqliteConnection, SqliteExecutor, Statement as _,
     query,
@@ -18,7 +21,8 @@ use sqlx::{
 
 use crate::bindiff::PatchType;
 use crate::errors::{MbtError, MbtResult};
-use crate::{CopyDuplicateMode, MbtType, NormalizedSchema, invert_y_value};
+use crate::metadata::{compression_metadata_value, is_encoding_mismatch};
+use crate::{CopyDuplicateMode, MbtType, NormalizedSchema, action_with_rusqlite, checked_invert_y};
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize, EnumDisplay)]
 #[enum_display(case = "Kebab")]
@@ -50,6 +54,21 @@ impl CopyType {
     }
 }
 
+/// What [`Mbtiles::insert_tiles`] does with tiles whose compression does not match
+/// the `compression` metadata value, see [`Mbtiles::set_tile_info`].
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize, EnumDisplay)]
+#[enum_display(case = "Kebab")]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum EncodingCheck {
+    /// Do not inspect the inserted tiles
+    #[default]
+    Off,
+    /// Insert the tiles, but log a warning
+    Warn,
+    /// Fail with [`MbtError::TileEncodingMismatch`] without inserting any tile of the batch
+    Strict,
+}
+
 pub struct PatchFileInfo {
     pub mbt_type: MbtType,
     pub agg_tiles_hash: Option<String>,
@@ -88,7 +107,7 @@ pub struct PatchFileInfo {
 /// > **The default in mapbox and maplibre is xyz.***
 /// > **The default in mbtiles generation like plantitler is tms.***
 /// >
-/// > You can use [`invert_y_value`] to convert them.
+/// > You can use [`invert_y_value`](crate::invert_y_value) to convert them.
 ///
 /// ```
 /// use mbtiles::Mbtiles;
@@ -149,8 +168,27 @@ pub struct PatchFileInfo {
 pub struct Mbtiles {
     filepath: String,
     filename: String,
+    busy_timeout: Duration,
+    /// WAL auto-checkpoint interval in pages, if read-write connections use WAL mode
+    wal_autocheckpoint: Option<u32>,
+    encoding_check: EncodingCheck,
+    /// Result of the last successful [`Mbtiles::detect_type`] for file-backed databases, shared by all clones
+    detected_type: Arc<Mutex<Option<MbtType>>>,
 }
 
+/// A tile coordinate with the tile data and its hash, see [`Mbtiles::stream_tiles_with_hash`].
+pub type TileWithHash = (TileCoord, Option<Vec<u8>>, Option<String>);
+
+/// Default time a connection waits for a lock held by another connection
+/// before failing with `database is locked`, see [`Mbtiles::with_busy_timeout`].
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default number of WAL pages after which `SQLite` checkpoints them into the database, see [`Mbtiles::with_wal`].
+pub const DEFAULT_WAL_AUTOCHECKPOINT: u32 = 1000;
+
+/// `PRAGMA application_id` registered for `MBTiles` files (`MPBX`), see [`Mbtiles::application_id`].
+pub const MBTILES_APPLICATION_ID: i32 = 0x4d50_4258;
+
 impl Display for Mbtiles {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.filepath)
@@ -184,9 +222,159 @@ impl Mbtiles {
                 .unwrap_or_else(|| OsStr::new("unknown"))
                 .to_string_lossy()
                 .to_string(),
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            wal_autocheckpoint: None,
+            encoding_check: EncodingCheck::default(),
+            detected_type: Arc::default(),
         })
     }
 
+    /// Creates a reference to a new, empty `MBTiles` database which only exists in memory.
+    ///
+    /// Every call creates a distinct database. It uses a shared cache, so all connections opened
+    /// from this instance (or its clones), including an [`MbtilesPool`](crate::MbtilesPool), see the same data.
+    ///
+    /// <div class="warning">
+    ///
+    /// **Note:** The database only lives as long as at least one connection to it is open.
+    /// Once the last connection is closed, all data is lost, and the next connection
+    /// will start with an empty database again.
+    ///
+    /// </div>
+    ///
+    /// # Examples
+    /// ```
+    /// use mbtiles::{MbtType, Mbtiles, init_mbtiles_schema};
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mbtiles = Mbtiles::new_in_memory();
+    /// // keep this connection open for as long as the data is needed
+    /// let mut conn = mbtiles.open().await?;
+    /// init_mbtiles_schema(&mut conn, MbtType::Flat).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn new_in_memory() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let name = format!(
+            "mbtiles-memory-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        Self {
+            filepath: format!("file:{name}?mode=memory&cache=shared"),
+            filename: name,
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            wal_autocheckpoint: None,
+            encoding_check: EncodingCheck::default(),
+            detected_type: Arc::default(),
+        }
+    }
+
+    /// Sets how long connections opened from this instance wait for a lock held
+    /// by another connection (e.g. a concurrent writer) before failing with `database is locked`.
+    ///
+    /// Defaults to [`DEFAULT_BUSY_TIMEOUT`].
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use mbtiles::Mbtiles;
+    ///
+    /// let mbtiles = Mbtiles::new("example.mbtiles")
+    ///     .unwrap()
+    ///     .with_busy_timeout(Duration::from_secs(30));
+    /// ```
+    #[must_use]
+    pub fn with_busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = busy_timeout;
+        self
+    }
+
+    /// Opens read-write connections in [write-ahead log](https://sqlite.org/wal.html) mode,
+    /// so that readers do not block a writer and a writer does not block readers.
+    /// This allows serving a file while another process keeps updating it.
+    ///
+    /// `autocheckpoint` is the number of pages after which the log is written back into the database file,
+    /// [`DEFAULT_WAL_AUTOCHECKPOINT`] is `SQLite`'s default, and `0` disables automatic checkpoints.
+    ///
+    /// WAL mode is stored in the file, so read-only connections, e.g. from [`Mbtiles::open_readonly`],
+    /// use it automatically once any writer enabled it. They need write access to the directory
+    /// to create the `-shm` file if no other connection has created it yet.
+    /// WAL mode does not work over network filesystems.
+    ///
+    /// # Examples
+    /// ```
+    /// use mbtiles::{DEFAULT_WAL_AUTOCHECKPOINT, Mbtiles};
+    ///
+    /// let mbtiles = Mbtiles::new("example.mbtiles")
+    ///     .unwrap()
+    ///     .with_wal(DEFAULT_WAL_AUTOCHECKPOINT);
+    /// ```
+    #[must_use]
+    pub fn with_wal(mut self, autocheckpoint: u32) -> Self {
+        self.wal_autocheckpoint = Some(autocheckpoint);
+        self
+    }
+
+    /// Sets how [`Mbtiles::insert_tiles`] handles tiles that are not compressed
+    /// as advertised by the `compression` metadata value.
+    ///
+    /// Defaults to [`EncodingCheck::Off`], because checking reads the `compression` metadata value for every batch.
+    /// Files without a `compression` metadata value are never checked.
+    ///
+    /// # Examples
+    /// ```
+    /// use mbtiles::{EncodingCheck, Mbtiles};
+    ///
+    /// let mbtiles = Mbtiles::new("example.mbtiles")
+    ///     .unwrap()
+    ///     .with_encoding_check(EncodingCheck::Strict);
+    /// ```
+    #[must_use]
+    pub fn with_encoding_check(mut self, encoding_check: EncodingCheck) -> Self {
+        self.encoding_check = encoding_check;
+        self
+    }
+
+    /// In-memory databases are usually built up by the caller, so their schema is not stable enough to cache
+    pub(crate) fn is_in_memory(&self) -> bool {
+        self.filepath == ":memory:" || self.filepath.contains("mode=memory")
+    }
+
+    pub(crate) fn detected_type_cache(&self) -> std::sync::MutexGuard<'_, Option<MbtType>> {
+        // The cached value is a plain Copy value, so a poisoned lock cannot leave it inconsistent
+        self.detected_type
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// The time connections wait for a lock held by another connection
+    #[must_use]
+    pub fn busy_timeout(&self) -> Duration {
+        self.busy_timeout
+    }
+
+    /// Connection options shared by all ways of opening this file
+    pub(crate) fn connect_options(&self) -> SqliteConnectOptions {
+        SqliteConnectOptions::new()
+            .filename(self.filepath())
+            .busy_timeout(self.busy_timeout)
+    }
+
+    /// Connection options for read-write connections, enabling WAL mode if configured
+    fn read_write_options(&self) -> SqliteConnectOptions {
+        let opt = self.connect_options();
+        match self.wal_autocheckpoint {
+            Some(pages) => opt
+                .journal_mode(SqliteJournalMode::Wal)
+                .pragma("wal_autocheckpoint", pages.to_string()),
+            None => opt,
+        }
+    }
+
     /// Opens an existing `MBTiles` file in read-write mode.
     ///
     /// Opens a connection to the file for both reading and writing operations.
@@ -215,7 +403,7 @@ impl Mbtiles {
     #[hotpath::measure]
     pub async fn open(&self) -> MbtResult<SqliteConnection> {
         debug!("Opening w/ defaults {self}");
-        let opt = SqliteConnectOptions::new().filename(self.filepath());
+        let opt = self.read_write_options();
         Self::open_int(&opt).await
     }
 
@@ -247,9 +435,7 @@ impl Mbtiles {
     #[hotpath::measure]
     pub async fn open_or_new(&self) -> MbtResult<SqliteConnection> {
         debug!("Opening or creating {self}");
-        let opt = SqliteConnectOptions::new()
-            .filename(self.filepath())
-            .create_if_missing(true);
+        let opt = self.read_write_options().create_if_missing(true);
         Self::open_int(&opt).await
     }
 
@@ -285,9 +471,7 @@ impl Mbtiles {
     #[hotpath::measure]
     pub async fn open_readonly(&self) -> MbtResult<SqliteConnection> {
         debug!("Opening as readonly {self}");
-        let opt = SqliteConnectOptions::new()
-            .filename(self.filepath())
-            .read_only(true);
+        let opt = self.connect_options().read_only(true);
         Self::open_int(&opt).await
     }
 
@@ -323,6 +507,34 @@ impl Mbtiles {
         Ok(())
     }
 
+    /// Optimizes the database after large changes, e.g. deletes or conversions.
+    ///
+    /// Runs `ANALYZE` and `PRAGMA optimize` so that the query planner has up-to-date statistics.
+    /// If `vacuum` is set, `VACUUM` is run first to rebuild the file and reclaim unused space.
+    /// Note that `VACUUM` temporarily needs as much free disk space as the size of the file.
+    ///
+    /// # Errors
+    /// Returns [`MbtError::VacuumInTransaction`] if `vacuum` is requested while a transaction is open on `conn`.
+    #[hotpath::measure]
+    pub async fn optimize(&self, conn: &mut SqliteConnection, vacuum: bool) -> MbtResult<()> {
+        if vacuum {
+            let mut in_transaction = false;
+            action_with_rusqlite(conn, |c| {
+                in_transaction = !c.is_autocommit();
+                Ok(())
+            })
+            .await?;
+            if in_transaction {
+                return Err(MbtError::VacuumInTransaction(self.filepath.clone()));
+            }
+            debug!("Vacuuming {self}");
+            conn.execute("VACUUM").await?;
+        }
+        debug!("Optimizing {self}");
+        conn.execute("ANALYZE; PRAGMA optimize;").await?;
+        Ok(())
+    }
+
     /// Stream over coordinates of all tiles in the database.
     ///
     /// No particular order is guaranteed.
@@ -353,20 +565,51 @@ impl Mbtiles {
         let filepath = self.filepath.clone();
 
         Box::pin(stream.map(move |result| {
-            result.map_err(MbtError::from).and_then(|row| {
-                let z = row.zoom_level;
-                let x = row.tile_column;
-                let y = row.tile_row;
-                let coord = parse_tile_index(z, x, y).ok_or_else(|| {
-                    MbtError::InvalidTileIndex(
-                        filepath.clone(),
-                        format!("{z:?}"),
-                        format!("{x:?}"),
-                        format!("{y:?}"),
-                    )
-                })?;
-                Ok(coord)
-            })
+            let row = result?;
+            coord_from_index(&filepath, row.zoom_level, row.tile_column, row.tile_row)
+        }))
+    }
+
+    /// Stream over coordinates of the tiles at zoom level `zoom`.
+    ///
+    /// Same as [`Mbtiles::stream_coords`], but only reads the given zoom level from the database.
+    pub fn stream_coords_for_zoom<'e, T>(
+        &self,
+        conn: &'e mut T,
+        zoom: u8,
+    ) -> Pin<Box<dyn Stream<Item = MbtResult<TileCoord>> + Send + 'e>>
+    where
+        &'e mut T: SqliteExecutor<'e>,
+    {
+        self.stream_coords_for_zoom_range(conn, zoom, zoom)
+    }
+
+    /// Stream over coordinates of the tiles with zoom levels within `min_zoom..=max_zoom`.
+    ///
+    /// Same as [`Mbtiles::stream_coords`], but only reads the given zoom levels from the database.
+    /// The stream is empty if `min_zoom` is greater than `max_zoom`.
+    pub fn stream_coords_for_zoom_range<'e, T>(
+        &self,
+        conn: &'e mut T,
+        min_zoom: u8,
+        max_zoom: u8,
+    ) -> Pin<Box<dyn Stream<Item = MbtResult<TileCoord>> + Send + 'e>>
+    where
+        &'e mut T: SqliteExecutor<'e>,
+    {
+        use futures::StreamExt as _;
+
+        let stream = query(
+            "SELECT zoom_level, tile_column, tile_row FROM tiles WHERE zoom_level BETWEEN ? AND ?",
+        )
+        .bind(min_zoom)
+        .bind(max_zoom)
+        .fetch(conn);
+        let filepath = self.filepath.clone();
+
+        Box::pin(stream.map(move |result| {
+            let row = result?;
+            coord_from_index(&filepath, row.get(0), row.get(1), row.get(2))
         }))
     }
 
@@ -379,6 +622,7 @@ impl Mbtiles {
     /// **Note:** The returned [`Stream`] holds a mutable reference to the given
     /// connection, making it unusable for anything else until the stream
     /// is dropped.
+    /// Use [`Mbtiles::stream_tiles_owned`] for a stream that owns the connection instead.
     ///
     /// </div>
     pub fn stream_tiles<'e, T>(
@@ -396,22 +640,122 @@ impl Mbtiles {
 
         Box::pin(stream.map(move |result| {
             result.map_err(MbtError::from).and_then(|row| {
-                let z = row.zoom_level;
-                let x = row.tile_column;
-                let y = row.tile_row;
-                let coord = parse_tile_index(z, x, y).ok_or_else(|| {
-                    MbtError::InvalidTileIndex(
-                        filepath.clone(),
-                        format!("{z:?}"),
-                        format!("{x:?}"),
-                        format!("{y:?}"),
-                    )
-                })?;
+                let coord =
+                    coord_from_index(&filepath, row.zoom_level, row.tile_column, row.tile_row)?;
                 Ok((coord, row.tile_data))
             })
         }))
     }
 
+    /// Returns a stream over all tiles in the database together with their hash.
+    ///
+    /// Hashed types yield the stored hash, so the data is not hashed again.
+    /// For [`MbtType::Flat`] and the dedup-id normalized schema, the MD5 hash is computed
+    /// while reading. Tiles without data have no hash. No particular order is guaranteed.
+    ///
+    /// <div class="warning">
+    ///
+    /// **Note:** The returned [`Stream`] holds a mutable reference to the given
+    /// connection, making it unusable for anything else until the stream
+    /// is dropped.
+    ///
+    /// </div>
+    pub fn stream_tiles_with_hash<'e, T>(
+        &self,
+        conn: &'e mut T,
+        mbt_type: MbtType,
+    ) -> Pin<Box<dyn Stream<Item = MbtResult<TileWithHash>> + Send + 'e>>
+    where
+        &'e mut T: SqliteExecutor<'e>,
+    {
+        use futures::StreamExt as _;
+
+        let stream = query(Self::stream_tiles_with_hash_sql(mbt_type)).fetch(conn);
+        let filepath = self.filepath.clone();
+
+        Box::pin(stream.map(move |result| {
+            let row = result?;
+            let coord = coord_from_index(&filepath, row.get(0), row.get(1), row.get(2))?;
+            Ok((coord, row.get(3), row.get(4)))
+        }))
+    }
+
+    fn stream_tiles_with_hash_sql(mbt_type: MbtType) -> &'static str {
+        match mbt_type {
+            MbtType::Flat => {
+                "SELECT zoom_level, tile_column, tile_row, tile_data, md5_hex(tile_data) FROM tiles"
+            }
+            MbtType::FlatWithHash
+            | MbtType::Normalized {
+                hash_view: true, ..
+            } => {
+                "SELECT zoom_level, tile_column, tile_row, tile_data, tile_hash FROM tiles_with_hash"
+            }
+            MbtType::Normalized {
+                hash_view: false,
+                schema: NormalizedSchema::Hash,
+            } => {
+                "SELECT map.zoom_level, map.tile_column, map.tile_row, images.tile_data, images.tile_id FROM map JOIN images ON map.tile_id = images.tile_id"
+            }
+            MbtType::Normalized {
+                hash_view: false,
+                schema: NormalizedSchema::DedupId,
+            } => {
+                "SELECT tiles_shallow.zoom_level, tiles_shallow.tile_column, tiles_shallow.tile_row, tiles_data.tile_data, md5_hex(tiles_data.tile_data) FROM tiles_shallow JOIN tiles_data ON tiles_shallow.tile_data_id = tiles_data.tile_data_id"
+            }
+        }
+    }
+
+    /// Returns a stream over all tiles in the database, skipping rows with an invalid tile index.
+    ///
+    /// Unlike [`stream_tiles`](Self::stream_tiles), a malformed row is logged and skipped
+    /// instead of being yielded as an error, which allows salvaging partially corrupted files.
+    /// The number of skipped rows is logged once the stream is exhausted.
+    /// Other errors, e.g. from the database itself, are still yielded.
+    ///
+    /// <div class="warning">
+    ///
+    /// **Note:** The returned [`Stream`] holds a mutable reference to the given
+    /// connection, making it unusable for anything else until the stream
+    /// is dropped.
+    ///
+    /// </div>
+    pub fn stream_tiles_lossy<'e, T>(
+        &self,
+        conn: &'e mut T,
+    ) -> Pin<Box<dyn Stream<Item = MbtResult<Tile>> + Send + 'e>>
+    where
+        &'e mut T: SqliteExecutor<'e>,
+    {
+        use futures::StreamExt as _;
+        use futures::future::ready;
+
+        let skipped = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&skipped);
+        let tiles = self.stream_tiles(conn).filter_map(move |result| {
+            ready(match result {
+                Err(e @ MbtError::InvalidTileIndex(..)) => {
+                    warn!("Skipping tile: {e}");
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+                result => Some(result),
+            })
+        });
+
+        let filepath = self.filepath.clone();
+        let summary = futures::stream::once(async move {
+            let skipped = skipped.load(Ordering::Relaxed);
+            if skipped > 0 {
+                warn!("Skipped {skipped} tiles with an invalid tile index in {filepath}");
+            }
+            None
+        })
+        .filter_map(ready);
+
+        Box::pin(tiles.chain(summary))
+    }
+
     /// Retrieves a single tile from the database by its coordinates.
     ///
     /// Returns the raw tile data as a byte vector if the tile exists at the given
@@ -473,7 +817,9 @@ impl Mbtiles {
     where
         for<'e> &'e mut T: SqliteExecutor<'e>,
     {
-        let y = invert_y_value(z, y);
+        let Some(y) = checked_invert_y(z, y) else {
+            return Ok(None);
+        };
         let query = query! {"SELECT tile_data from tiles where zoom_level = ? AND tile_column = ? AND tile_row = ?", z, x, y};
         let row = query.fetch_optional(conn).await?;
         if let Some(row) = row
@@ -484,6 +830,40 @@ impl Mbtiles {
         Ok(None)
     }
 
+    /// Retrieves a single tile from the database, decompressing it if it is gzip-compressed.
+    ///
+    /// Vector tiles are commonly stored gzip-compressed. If the stored data starts with the gzip
+    /// magic header, it is decompressed using the `gzip_decode` function registered on connections
+    /// opened by [`Mbtiles`], e.g. to return the raw PBF bytes. Any other data is returned unchanged.
+    ///
+    /// Coordinates are in the same XYZ scheme as in [`get_tile`](Self::get_tile).
+    #[hotpath::measure]
+    pub async fn get_tile_decompressed<T>(
+        &self,
+        conn: &mut T,
+        z: u8,
+        x: u32,
+        y: u32,
+    ) -> MbtResult<Option<Vec<u8>>>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        let Some(y) = checked_invert_y(z, y) else {
+            return Ok(None);
+        };
+        let row = query(
+            "SELECT CASE WHEN substr(tile_data, 1, 2) = x'1f8b' THEN gzip_decode(tile_data) ELSE tile_data END
+             FROM tiles
+             WHERE zoom_level = ? AND tile_column = ? AND tile_row = ?",
+        )
+        .bind(z)
+        .bind(x)
+        .bind(y)
+        .fetch_optional(conn)
+        .await?;
+        Ok(row.and_then(|row| row.get(0)))
+    }
+
     /// Retrieves a tile and its hash from the database.
     ///
     /// Returns both the tile data and its hash value (if available) for the tile
@@ -547,7 +927,9 @@ impl Mbtiles {
         y: u32,
     ) -> MbtResult<Option<(Vec<u8>, Option<String>)>> {
         let sql = Self::get_tile_and_hash_sql(mbt_type);
-        let y = invert_y_value(z, y);
+        let Some(y) = checked_invert_y(z, y) else {
+            return Ok(None);
+        };
         let Some(row) = query(sql)
             .bind(z)
             .bind(x)
@@ -623,6 +1005,11 @@ impl Mbtiles {
             batch.len()
         );
         let mut tx = conn.begin().await?;
+        if self.encoding_check != EncodingCheck::Off
+            && let Some(advertised) = self.get_metadata_encoding(&mut *tx).await?
+        {
+            self.check_batch_encoding(advertised, batch)?;
+        }
         let (sql1, sql2) = Self::get_insert_sql(mbt_type, on_duplicate);
         if let Some(sql2) = sql2 {
             let sql2 = tx.prepare(&sql2).await?;
@@ -634,17 +1021,93 @@ impl Mbtiles {
             }
         }
         let sql1 = tx.prepare(&sql1).await?;
+        let mut skipped = 0_usize;
         for (z, x, y, tile_data) in batch {
-            let y = invert_y_value(*z, *y);
-            sql1.query()
+            let y = checked_invert_y(*z, *y).ok_or_else(|| {
+                MbtError::invalid_tile_index(
+                    self.filepath(),
+                    Some(i64::from(*z)),
+                    Some(i64::from(*x)),
+                    Some(i64::from(*y)),
+                )
+            })?;
+            let res = sql1
+                .query()
                 .bind(z)
                 .bind(x)
                 .bind(y)
                 .bind(tile_data.as_ref())
                 .execute(&mut *tx)
                 .await?;
+            if res.rows_affected() == 0 {
+                skipped += 1;
+            }
         }
         tx.commit().await?;
+        if on_duplicate == CopyDuplicateMode::OverrideIfChanged {
+            debug!("Skipped writing {skipped} unchanged tiles into {self}");
+        }
+        Ok(())
+    }
+
+    /// Gzip-compresses the batch of tiles with the given `compression_level` and inserts it,
+    /// see [`Mbtiles::insert_tiles`].
+    ///
+    /// The level must be between 1 (fastest) and 9 (smallest). Level 9 roughly doubles the CPU time
+    /// of the default level 6 for marginal size gains on already compact PBF tiles, so lower levels
+    /// are often a better fit for large bulk imports. Tiles that are already gzip-compressed are
+    /// inserted as-is.
+    #[hotpath::measure]
+    pub async fn insert_tiles_gzip<D: AsRef<[u8]>>(
+        &self,
+        conn: &mut SqliteConnection,
+        mbt_type: MbtType,
+        on_duplicate: CopyDuplicateMode,
+        batch: &[(u8, u32, u32, D)],
+        compression_level: u8,
+    ) -> MbtResult<()> {
+        if !(1..=9).contains(&compression_level) {
+            return Err(MbtError::InvalidCompressionLevel(compression_level));
+        }
+        let mut compressed = Vec::with_capacity(batch.len());
+        for (z, x, y, tile_data) in batch {
+            let tile_data = tile_data.as_ref();
+            let tile_data = if tile_data.starts_with(b"\x1f\x8b") {
+                tile_data.to_vec()
+            } else {
+                encode_gzip_with_level(tile_data, u32::from(compression_level))?
+            };
+            compressed.push((*z, *x, *y, tile_data));
+        }
+        self.insert_tiles(conn, mbt_type, on_duplicate, &compressed)
+            .await
+    }
+
+    fn check_batch_encoding<D: AsRef<[u8]>>(
+        &self,
+        advertised: Encoding,
+        batch: &[(u8, u32, u32, D)],
+    ) -> MbtResult<()> {
+        let mut mismatched = batch
+            .iter()
+            .filter(|(_, _, _, data)| is_encoding_mismatch(advertised, data.as_ref()));
+        let Some((z, x, y, _)) = mismatched.next() else {
+            return Ok(());
+        };
+        let compression = compression_metadata_value(advertised).unwrap_or_default();
+        if self.encoding_check == EncodingCheck::Strict {
+            return Err(MbtError::TileEncodingMismatch(
+                self.filepath.clone(),
+                *z,
+                *x,
+                *y,
+                compression.to_string(),
+            ));
+        }
+        warn!(
+            "{} tile(s), e.g. {z}/{x}/{y}, do not match the '{compression}' compression advertised by the metadata of {self}",
+            mismatched.count() + 1
+        );
         Ok(())
     }
 
@@ -667,22 +1130,94 @@ impl Mbtiles {
             MbtType::FlatWithHash => "tiles_with_hash",
             MbtType::Normalized { schema, .. } => schema.map_table(),
         };
+        let Some(y) = checked_invert_y(z, y) else {
+            return Ok(false);
+        };
         let sql = format!(
             "SELECT 1 from {table} where zoom_level = ? AND tile_column = ? AND tile_row = ?"
         );
         let row = query(&sql)
             .bind(z)
             .bind(x)
-            .bind(invert_y_value(z, y))
+            .bind(y)
             .fetch_optional(conn)
             .await?;
         Ok(row.is_some())
     }
 
+    /// Returns the number of tiles in the database.
+    #[hotpath::measure]
+    pub async fn count_tiles<T>(&self, conn: &mut T) -> MbtResult<u64>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        let count: i64 = query("SELECT COUNT(*) FROM tiles")
+            .fetch_one(&mut *conn)
+            .await?
+            .get(0);
+        Ok(count.unsigned_abs())
+    }
+
+    /// Returns the `PRAGMA application_id` of the file, or `0` if it was never set.
+    ///
+    /// Files following the `MBTiles` 1.3 spec use [`MBTILES_APPLICATION_ID`].
+    #[hotpath::measure]
+    pub async fn application_id<T>(&self, conn: &mut T) -> MbtResult<i32>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        Ok(query("PRAGMA application_id")
+            .fetch_one(&mut *conn)
+            .await?
+            .get(0))
+    }
+
+    /// Sets the `PRAGMA application_id` of the file.
+    #[hotpath::measure]
+    pub async fn set_application_id<T>(&self, conn: &mut T, application_id: i32) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        // PRAGMA values cannot be bound as parameters
+        query(&format!("PRAGMA application_id = {application_id}"))
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the `PRAGMA user_version` of the file, or `0` if it was never set.
+    ///
+    /// Tools may use it to stamp the schema version of the files they produce.
+    #[hotpath::measure]
+    pub async fn user_version<T>(&self, conn: &mut T) -> MbtResult<i32>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        Ok(query("PRAGMA user_version")
+            .fetch_one(&mut *conn)
+            .await?
+            .get(0))
+    }
+
+    /// Sets the `PRAGMA user_version` of the file.
+    #[hotpath::measure]
+    pub async fn set_user_version<T>(&self, conn: &mut T, user_version: i32) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        query(&format!("PRAGMA user_version = {user_version}"))
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
     fn get_insert_sql(
         src_type: MbtType,
         on_duplicate: CopyDuplicateMode,
     ) -> (String, Option<String>) {
+        if on_duplicate == CopyDuplicateMode::OverrideIfChanged {
+            return Self::get_insert_if_changed_sql(src_type);
+        }
         let on_duplicate = on_duplicate.to_sql();
         match src_type {
             MbtType::Flat => (
@@ -715,6 +1250,56 @@ impl Mbtiles {
             ),
         }
     }
+
+    /// Same as [`Self::get_insert_sql`] with [`CopyDuplicateMode::OverrideIfChanged`]:
+    /// a tile is only written if it does not exist yet or if its content differs.
+    fn get_insert_if_changed_sql(src_type: MbtType) -> (String, Option<String>) {
+        let unchanged = |table: &str, id_column: &str, id_value: &str| {
+            format!(
+                "WHERE NOT EXISTS (
+        SELECT 1 FROM {table}
+        WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3 AND {id_column} = {id_value});"
+            )
+        };
+        match src_type {
+            MbtType::Flat => (
+                format!(
+                    "
+    INSERT OR REPLACE INTO tiles (zoom_level, tile_column, tile_row, tile_data)
+    SELECT ?1, ?2, ?3, ?4
+    {}",
+                    unchanged("tiles", "tile_data", "?4")
+                ),
+                None,
+            ),
+            MbtType::FlatWithHash => (
+                format!(
+                    "
+    INSERT OR REPLACE INTO tiles_with_hash (zoom_level, tile_column, tile_row, tile_data, tile_hash)
+    SELECT ?1, ?2, ?3, ?4, md5_hex(?4)
+    {}",
+                    unchanged("tiles_with_hash", "tile_hash", "md5_hex(?4)")
+                ),
+                None,
+            ),
+            MbtType::Normalized { .. } => (
+                format!(
+                    "
+    INSERT OR REPLACE INTO map (zoom_level, tile_column, tile_row, tile_id)
+    SELECT ?1, ?2, ?3, md5_hex(?4)
+    {}",
+                    unchanged("map", "tile_id", "md5_hex(?4)")
+                ),
+                // identical content has an identical id, so there is nothing to replace
+                Some(
+                    "
+    INSERT OR IGNORE INTO images (tile_id, tile_data)
+    VALUES (md5_hex(?1), ?1);"
+                        .to_string(),
+                ),
+            ),
+        }
+    }
 }
 
 pub async fn attach_sqlite_fn(conn: &mut SqliteConnection) -> MbtResult<()> {
@@ -738,18 +1323,441 @@ pub(crate) fn parse_tile_index(
     let x: u32 = x?.try_into().ok()?;
     let y: u32 = y?.try_into().ok()?;
 
-    // Inverting `y` value can panic if it is greater than `(1 << z) - 1`,
-    // so we must ensure that it is vald first.
-    TileCoord::is_possible_on_zoom_level(z, x, y)
-        .then(|| TileCoord::new_unchecked(z, x, invert_y_value(z, y)))
+    let y = checked_invert_y(z, y)?;
+    TileCoord::is_possible_on_zoom_level(z, x, y).then(|| TileCoord::new_unchecked(z, x, y))
+}
+
+/// Same as [`parse_tile_index`], but returns an [`MbtError::InvalidTileIndex`] for invalid values.
+fn coord_from_index(
+    filepath: &str,
+    z: Option<i64>,
+    x: Option<i64>,
+    y: Option<i64>,
+) -> MbtResult<TileCoord> {
+    parse_tile_index(z, x, y).ok_or_else(|| MbtError::invalid_tile_index(filepath, z, x, y))
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
+    use martin_tile_utils::{Format, TileInfo};
+
     use super::*;
 
     pub async fn open(filepath: &str) -> MbtResult<(SqliteConnection, Mbtiles)> {
         let mbt = Mbtiles::new(filepath)?;
         mbt.open().await.map(|conn| (conn, mbt))
     }
+
+    #[rstest::rstest]
+    #[case::flat(MbtType::Flat)]
+    #[case::flat_with_hash(MbtType::FlatWithHash)]
+    #[case::normalized(MbtType::Normalized { hash_view: false, schema: NormalizedSchema::Hash })]
+    #[actix_rt::test]
+    async fn insert_tiles_override_if_changed(#[case] mbt_type: MbtType) {
+        let mbt = Mbtiles::new(":memory:").unwrap();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, mbt_type)
+            .await
+            .unwrap();
+
+        let mode = CopyDuplicateMode::OverrideIfChanged;
+        mbt.insert_tiles(
+            &mut conn,
+            mbt_type,
+            mode,
+            &[(1, 0, 0, b"a"), (1, 1, 0, b"b")],
+        )
+        .await
+        .unwrap();
+        mbt.insert_tiles(
+            &mut conn,
+            mbt_type,
+            mode,
+            &[(1, 0, 0, b"a"), (1, 1, 0, b"c")],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            mbt.get_tile(&mut conn, 1, 0, 0).await.unwrap(),
+            Some(b"a".to_vec())
+        );
+        assert_eq!(
+            mbt.get_tile(&mut conn, 1, 1, 0).await.unwrap(),
+            Some(b"c".to_vec())
+        );
+    }
+
+    #[rstest::rstest]
+    #[case::flat(MbtType::Flat)]
+    #[case::flat_with_hash(MbtType::FlatWithHash)]
+    #[case::normalized(MbtType::Normalized { hash_view: false, schema: NormalizedSchema::Hash })]
+    #[actix_rt::test]
+    async fn stream_tiles_with_hash(#[case] mbt_type: MbtType) {
+        use futures::TryStreamExt as _;
+
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, mbt_type)
+            .await
+            .unwrap();
+        mbt.insert_tiles(
+            &mut conn,
+            mbt_type,
+            CopyDuplicateMode::Override,
+            &[(1, 0, 0, b"a"), (1, 1, 0, b"b"), (2, 0, 0, b"a")],
+        )
+        .await
+        .unwrap();
+
+        let mut tiles: Vec<_> = mbt
+            .stream_tiles_with_hash(&mut conn, mbt_type)
+            .try_collect()
+            .await
+            .unwrap();
+        tiles.sort_by_key(|(coord, _, _)| (coord.z, coord.x, coord.y));
+
+        let hash_a: String = query("SELECT md5_hex('a')")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+            .get(0);
+        let hash_b: String = query("SELECT md5_hex('b')")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(
+            tiles,
+            [
+                (
+                    TileCoord { z: 1, x: 0, y: 0 },
+                    Some(b"a".to_vec()),
+                    Some(hash_a.clone())
+                ),
+                (
+                    TileCoord { z: 1, x: 1, y: 0 },
+                    Some(b"b".to_vec()),
+                    Some(hash_b)
+                ),
+                (
+                    TileCoord { z: 2, x: 0, y: 0 },
+                    Some(b"a".to_vec()),
+                    Some(hash_a)
+                ),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn out_of_range_tile_row() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, MbtType::Flat)
+            .await
+            .unwrap();
+
+        assert_eq!(mbt.get_tile(&mut conn, 1, 0, 2).await.unwrap(), None);
+        assert!(
+            !mbt.contains(&mut conn, MbtType::Flat, 1, 0, 2)
+                .await
+                .unwrap()
+        );
+        let err = mbt
+            .insert_tiles(
+                &mut conn,
+                MbtType::Flat,
+                CopyDuplicateMode::Override,
+                &[(1, 0, 2, b"a")],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "At least one tile in the tiles table/view has an invalid value: zoom_level=1, tile_column=0, tile_row=2 in MBTile file {mbt}"
+            )
+        );
+    }
+
+    #[actix_rt::test]
+    async fn in_memory_is_shared_between_connections() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, MbtType::Flat)
+            .await
+            .unwrap();
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &[(0, 0, 0, b"a")],
+        )
+        .await
+        .unwrap();
+
+        let mut other = mbt.open_readonly().await.unwrap();
+        assert_eq!(
+            mbt.get_tile(&mut other, 0, 0, 0).await.unwrap(),
+            Some(b"a".to_vec())
+        );
+
+        let separate = Mbtiles::new_in_memory();
+        assert_ne!(separate.filepath(), mbt.filepath());
+        let mut conn = separate.open().await.unwrap();
+        assert!(crate::is_empty_database(&mut conn).await.unwrap());
+    }
+
+    #[rstest::rstest]
+    #[case::analyze(false)]
+    #[case::vacuum(true)]
+    #[actix_rt::test]
+    async fn optimize(#[case] vacuum: bool) {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, MbtType::FlatWithHash)
+            .await
+            .unwrap();
+        mbt.optimize(&mut conn, vacuum).await.unwrap();
+
+        conn.execute("BEGIN").await.unwrap();
+        let res = mbt.optimize(&mut conn, vacuum).await;
+        if vacuum {
+            assert!(matches!(res, Err(MbtError::VacuumInTransaction(_))));
+        } else {
+            res.unwrap();
+        }
+    }
+
+    #[actix_rt::test]
+    async fn get_tile_decompressed() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, MbtType::Flat)
+            .await
+            .unwrap();
+        let gzipped = martin_tile_utils::encode_gzip(b"pbf").unwrap();
+        let tiles = [(1, 0, 0, gzipped.as_slice()), (1, 1, 0, b"raw")];
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &tiles,
+        )
+        .await
+        .unwrap();
+
+        let tile = mbt.get_tile_decompressed(&mut conn, 1, 0, 0).await.unwrap();
+        assert_eq!(tile, Some(b"pbf".to_vec()));
+        let tile = mbt.get_tile_decompressed(&mut conn, 1, 1, 0).await.unwrap();
+        assert_eq!(tile, Some(b"raw".to_vec()));
+        let tile = mbt.get_tile_decompressed(&mut conn, 1, 1, 1).await.unwrap();
+        assert_eq!(tile, None);
+    }
+
+    #[actix_rt::test]
+    async fn insert_tiles_gzip() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, MbtType::Flat)
+            .await
+            .unwrap();
+
+        let tile = b"pbf".repeat(100);
+        let gzipped = martin_tile_utils::encode_gzip(b"raw").unwrap();
+        let mode = CopyDuplicateMode::Override;
+        for level in [0, 10] {
+            let err = mbt
+                .insert_tiles_gzip(&mut conn, MbtType::Flat, mode, &[(0, 0, 0, b"")], level)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, MbtError::InvalidCompressionLevel(v) if v == level));
+        }
+
+        let batch = [(1, 0, 0, tile.as_slice()), (1, 1, 0, gzipped.as_slice())];
+        mbt.insert_tiles_gzip(&mut conn, MbtType::Flat, mode, &batch, 1)
+            .await
+            .unwrap();
+        let fast = mbt.get_tile(&mut conn, 1, 0, 0).await.unwrap().unwrap();
+        assert_eq!(martin_tile_utils::decode_gzip(&fast).unwrap(), tile);
+        // already compressed tiles are not compressed twice
+        assert_eq!(
+            mbt.get_tile(&mut conn, 1, 1, 0).await.unwrap(),
+            Some(gzipped.clone())
+        );
+
+        mbt.insert_tiles_gzip(&mut conn, MbtType::Flat, mode, &batch, 9)
+            .await
+            .unwrap();
+        let best = mbt.get_tile(&mut conn, 1, 0, 0).await.unwrap().unwrap();
+        assert_eq!(martin_tile_utils::decode_gzip(&best).unwrap(), tile);
+        // the gzip header's XFL byte records the level: 4 for the fastest and 2 for the best compression
+        assert_eq!(fast[8], 4);
+        assert_eq!(best[8], 2);
+    }
+
+    #[actix_rt::test]
+    async fn insert_tiles_encoding_check() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        crate::init_mbtiles_schema(&mut conn, MbtType::Flat)
+            .await
+            .unwrap();
+        let info = TileInfo::new(Format::Mvt, Encoding::Uncompressed);
+        mbt.set_tile_info(&mut conn, info).await.unwrap();
+        assert_eq!(
+            mbt.get_metadata_value(&mut conn, "format").await.unwrap(),
+            Some("pbf".to_string())
+        );
+        assert_eq!(
+            mbt.get_metadata_encoding(&mut conn).await.unwrap(),
+            Some(Encoding::Uncompressed)
+        );
+
+        let gzipped = martin_tile_utils::encode_gzip(b"pbf").unwrap();
+        let tiles = [(1, 0, 0, b"raw".as_slice()), (1, 1, 0, gzipped.as_slice())];
+        let dup = CopyDuplicateMode::Override;
+
+        let strict = mbt.clone().with_encoding_check(EncodingCheck::Strict);
+        let res = strict
+            .insert_tiles(&mut conn, MbtType::Flat, dup, &tiles)
+            .await;
+        assert!(matches!(
+            res,
+            Err(MbtError::TileEncodingMismatch(_, 1, 1, 0, v)) if v == "none"
+        ));
+        assert_eq!(mbt.count_tiles(&mut conn).await.unwrap(), 0);
+        strict
+            .insert_tiles(&mut conn, MbtType::Flat, dup, &tiles[..1])
+            .await
+            .unwrap();
+
+        // by default, tiles are not checked
+        mbt.insert_tiles(&mut conn, MbtType::Flat, dup, &tiles)
+            .await
+            .unwrap();
+        assert_eq!(mbt.count_tiles(&mut conn).await.unwrap(), 2);
+        // warnings do not prevent inserting
+        let warn = mbt.clone().with_encoding_check(EncodingCheck::Warn);
+        warn.insert_tiles(&mut conn, MbtType::Flat, dup, &tiles)
+            .await
+            .unwrap();
+        assert_eq!(mbt.count_tiles(&mut conn).await.unwrap(), 2);
+
+        let info = info.encoding(Encoding::Gzip);
+        mbt.set_tile_info(&mut conn, info).await.unwrap();
+        let res = strict
+            .insert_tiles(&mut conn, MbtType::Flat, dup, &tiles[..1])
+            .await;
+        assert!(matches!(
+            res,
+            Err(MbtError::TileEncodingMismatch(_, 1, 0, 0, v)) if v == "gzip"
+        ));
+
+        // internally compressed formats are not checked
+        let info = TileInfo::new(Format::Png, Encoding::Internal);
+        mbt.set_tile_info(&mut conn, info).await.unwrap();
+        assert_eq!(mbt.get_metadata_encoding(&mut conn).await.unwrap(), None);
+        strict
+            .insert_tiles(&mut conn, MbtType::Flat, dup, &tiles)
+            .await
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn application_id_and_user_version() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        assert_eq!(mbt.application_id(&mut conn).await.unwrap(), 0);
+        assert_eq!(mbt.user_version(&mut conn).await.unwrap(), 0);
+
+        mbt.set_application_id(&mut conn, MBTILES_APPLICATION_ID)
+            .await
+            .unwrap();
+        mbt.set_user_version(&mut conn, -3).await.unwrap();
+        assert_eq!(
+            mbt.application_id(&mut conn).await.unwrap(),
+            MBTILES_APPLICATION_ID
+        );
+        assert_eq!(mbt.user_version(&mut conn).await.unwrap(), -3);
+    }
+
+    #[actix_rt::test]
+    async fn busy_timeout_is_applied() {
+        let mbt = Mbtiles::new(":memory:").unwrap();
+        assert_eq!(mbt.busy_timeout(), DEFAULT_BUSY_TIMEOUT);
+        let mut conn = mbt.open().await.unwrap();
+        let ms: i64 = query("PRAGMA busy_timeout")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(ms, 5000);
+
+        let mbt = mbt.with_busy_timeout(Duration::from_millis(1234));
+        let mut conn = mbt.open_or_new().await.unwrap();
+        let ms: i64 = query("PRAGMA busy_timeout")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(ms, 1234);
+    }
+
+    #[actix_rt::test]
+    async fn wal_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.mbtiles");
+        let writer = Mbtiles::new(&path).unwrap().with_wal(100);
+        let mut wconn = writer.open_or_new().await.unwrap();
+        crate::init_mbtiles_schema(&mut wconn, MbtType::Flat)
+            .await
+            .unwrap();
+        writer
+            .insert_tiles(
+                &mut wconn,
+                MbtType::Flat,
+                CopyDuplicateMode::Override,
+                &[(0, 0, 0, b"old")],
+            )
+            .await
+            .unwrap();
+        let mode: String = query("PRAGMA journal_mode")
+            .fetch_one(&mut wconn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(mode, "wal");
+        let pages: i64 = query("PRAGMA wal_autocheckpoint")
+            .fetch_one(&mut wconn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(pages, 100);
+
+        // A reader that must not wait for the lock still reads the last committed tile during a write
+        let reader = Mbtiles::new(&path)
+            .unwrap()
+            .with_busy_timeout(Duration::ZERO);
+        let mut rconn = reader.open_readonly().await.unwrap();
+        wconn.execute("BEGIN").await.unwrap();
+        query("UPDATE tiles SET tile_data = 'new'")
+            .execute(&mut wconn)
+            .await
+            .unwrap();
+        assert_eq!(
+            reader.get_tile(&mut rconn, 0, 0, 0).await.unwrap(),
+            Some(b"old".to_vec())
+        );
+        // Custom functions are still available on both connections
+        let sql = "SELECT md5_hex(tile_data) FROM tiles";
+        let old_hash: String = query(sql).fetch_one(&mut rconn).await.unwrap().get(0);
+        let new_hash: String = query(sql).fetch_one(&mut wconn).await.unwrap().get(0);
+        assert_ne!(old_hash, new_hash);
+        wconn.execute("COMMIT").await.unwrap();
+        assert_eq!(
+            reader.get_tile(&mut rconn, 0, 0, 0).await.unwrap(),
+            Some(b"new".to_vec())
+        );
+    }
 }
@@ -3,13 +3,14 @@ use std::path::PathBuf;
 use std::str::FromStr as _;
 
 use futures::TryStreamExt as _;
-use log::{info, warn};
+use log::{debug, info, warn};
+use martin_tile_utils::{Encoding, MAX_ZOOM, TileInfo};
 use serde::Serialize;
 use serde_json::{Value as JSONValue, Value, json};
 use sqlx::{SqliteConnection, SqliteExecutor, query};
-use tilejson::{Bounds, Center, TileJSON, tilejson};
+use tilejson::{Bounds, Center, TileJSON, VectorLayer, tilejson};
 
-use crate::MbtError::InvalidZoomValue;
+use crate::MbtError::{EmptyMetadataValue, InconsistentTileJson, InvalidZoomValue};
 use crate::Mbtiles;
 use crate::errors::MbtResult;
 
@@ -129,6 +130,115 @@ impl Mbtiles {
         Ok(())
     }
 
+    /// Sets the human-readable `name` of the tileset, as shown by `TileJSON` clients.
+    ///
+    /// Returns [`MbtError::EmptyMetadataValue`](crate::MbtError::EmptyMetadataValue) if the name is blank.
+    pub async fn set_name<T>(&self, conn: &mut T, name: &str) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        self.set_non_empty_metadata_value(conn, "name", name).await
+    }
+
+    /// Sets the `description` of the tileset.
+    ///
+    /// Returns [`MbtError::EmptyMetadataValue`](crate::MbtError::EmptyMetadataValue) if the description is blank,
+    /// use [`Mbtiles::delete_metadata_value`] to remove it instead.
+    pub async fn set_description<T>(&self, conn: &mut T, description: &str) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        self.set_non_empty_metadata_value(conn, "description", description)
+            .await
+    }
+
+    /// Sets the `attribution` of the tileset, which may contain HTML.
+    ///
+    /// Returns [`MbtError::EmptyMetadataValue`](crate::MbtError::EmptyMetadataValue) if the attribution is blank,
+    /// use [`Mbtiles::delete_metadata_value`] to remove it instead.
+    pub async fn set_attribution<T>(&self, conn: &mut T, attribution: &str) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        self.set_non_empty_metadata_value(conn, "attribution", attribution)
+            .await
+    }
+
+    /// Advertises how the tiles are stored by setting the `format` and `compression` metadata values.
+    ///
+    /// Use [`Encoding::Uncompressed`] to store tiles without `gzip`. Formats that are compressed
+    /// by themselves, i.e. [`Encoding::Internal`], remove the `compression` value.
+    /// Once set, [`Mbtiles::insert_tiles`] can check inserted tiles against it, see [`EncodingCheck`](crate::EncodingCheck).
+    pub async fn set_tile_info<T>(&self, conn: &mut T, info: TileInfo) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        self.set_metadata_value(&mut *conn, "format", info.format.metadata_format_value())
+            .await?;
+        if let Some(compression) = compression_metadata_value(info.encoding) {
+            self.set_metadata_value(&mut *conn, "compression", compression)
+                .await
+        } else {
+            self.delete_metadata_value(&mut *conn, "compression").await
+        }
+    }
+
+    /// Returns the tile compression advertised by the `compression` metadata value, if any.
+    ///
+    /// Unknown values are logged and ignored.
+    pub async fn get_metadata_encoding<T>(&self, conn: &mut T) -> MbtResult<Option<Encoding>>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        let Some(value) = self.get_metadata_value(conn, "compression").await? else {
+            return Ok(None);
+        };
+        let encoding = Encoding::parse(&value);
+        if encoding.is_none() {
+            warn!(
+                "Unknown metadata compression value '{value}' in {}, ignoring it",
+                self.filename()
+            );
+        }
+        Ok(encoding)
+    }
+
+    /// Returns the vector layers stored in the `json` metadata value, if any.
+    ///
+    /// Vector tile writers store `vector_layers` (and often `tilestats`) as a JSON-encoded
+    /// string under the `json` key. Some tools encode that string once more, so a value that
+    /// decodes to a JSON string is decoded again.
+    pub async fn get_vector_layers<T>(&self, conn: &mut T) -> MbtResult<Option<Vec<VectorLayer>>>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        let Some(value) = self.get_metadata_value(conn, "json").await? else {
+            return Ok(None);
+        };
+        let JSONValue::Object(mut obj) = parse_json_metadata(&value)? else {
+            return Ok(None);
+        };
+        Ok(obj
+            .remove("vector_layers")
+            .map(serde_json::from_value)
+            .transpose()?)
+    }
+
+    async fn set_non_empty_metadata_value<T>(
+        &self,
+        conn: &mut T,
+        key: &'static str,
+        value: &str,
+    ) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        if value.trim().is_empty() {
+            return Err(EmptyMetadataValue(key));
+        }
+        self.set_metadata_value(conn, key, value).await
+    }
+
     /// Retrieves all metadata from the `MBTiles` file.
     ///
     /// Reads the entire metadata table and constructs a [`Metadata`] struct
@@ -201,8 +311,8 @@ impl Mbtiles {
                     "type" => layer_type = Some(value),
                     "legend" => tj.legend = Some(value),
                     "template" => tj.template = Some(value),
-                    "json" => json = self.to_val(serde_json::from_str(&value), &name),
-                    "format" | "generator" => {
+                    "json" => json = self.to_val(parse_json_metadata(&value), &name),
+                    "format" | "compression" | "generator" => {
                         tj.other.insert(name, Value::String(value));
                     }
                     "agg_tiles_hash" => agg_tiles_hash = Some(value),
@@ -332,6 +442,160 @@ impl Mbtiles {
 
         Ok(())
     }
+
+    /// Replaces the tileset-describing metadata with the values of a [`TileJSON`],
+    /// e.g. to regenerate the metadata after the tiles were edited. This is the inverse of [`Mbtiles::get_metadata`].
+    ///
+    /// Writes `name`, `bounds`, `center`, `minzoom`, `maxzoom` and `attribution`,
+    /// deleting the keys whose `TileJSON` value is `None`.
+    /// The `vector_layers` are stored in the `json` metadata key, keeping any other values in it, such as `tilestats`.
+    /// All other metadata keys are left untouched.
+    ///
+    /// Nothing is written if the bounds are outside of WGS84 coordinates, the zoom levels are above [`MAX_ZOOM`]
+    /// or `minzoom` is above `maxzoom`, or the center is outside of the bounds or zoom range.
+    #[hotpath::measure]
+    pub async fn apply_tilejson<T>(&self, conn: &mut T, tj: &TileJSON) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        self.validate_tilejson(tj)?;
+
+        for (key, value) in [
+            ("name", tj.name.clone()),
+            ("bounds", tj.bounds.map(|v| v.to_string())),
+            ("center", tj.center.map(|v| v.to_string())),
+            ("minzoom", tj.minzoom.map(|v| v.to_string())),
+            ("maxzoom", tj.maxzoom.map(|v| v.to_string())),
+            ("attribution", tj.attribution.clone()),
+        ] {
+            match value {
+                Some(value) => self.set_metadata_value(&mut *conn, key, value).await?,
+                None => self.delete_metadata_value(&mut *conn, key).await?,
+            }
+        }
+
+        let mut json = match self.get_metadata_value(&mut *conn, "json").await? {
+            Some(value) => {
+                if let Ok(JSONValue::Object(obj)) = serde_json::from_str(&value) {
+                    obj
+                } else {
+                    warn!(
+                        "Replacing unparsable metadata json value in {}",
+                        self.filename()
+                    );
+                    serde_json::Map::new()
+                }
+            }
+            None => serde_json::Map::new(),
+        };
+        if let Some(vector_layers) = &tj.vector_layers {
+            json.insert(
+                "vector_layers".to_string(),
+                serde_json::to_value(vector_layers)?,
+            );
+        } else {
+            json.remove("vector_layers");
+        }
+        if json.is_empty() {
+            self.delete_metadata_value(&mut *conn, "json").await?;
+        } else {
+            self.set_metadata_value(&mut *conn, "json", JSONValue::Object(json))
+                .await?;
+        }
+
+        debug!("Applied TileJSON metadata to {self}");
+        Ok(())
+    }
+
+    fn validate_tilejson(&self, tj: &TileJSON) -> MbtResult<()> {
+        let err = |reason: String| InconsistentTileJson(self.filepath().to_string(), reason);
+
+        for (name, zoom) in [("minzoom", tj.minzoom), ("maxzoom", tj.maxzoom)] {
+            if let Some(zoom) = zoom
+                && zoom > MAX_ZOOM
+            {
+                return Err(InvalidZoomValue(name, zoom.to_string()));
+            }
+        }
+        let minzoom = tj.minzoom.unwrap_or(0);
+        let maxzoom = tj.maxzoom.unwrap_or(MAX_ZOOM);
+        if minzoom > maxzoom {
+            return Err(err(format!(
+                "minzoom {minzoom} is greater than maxzoom {maxzoom}"
+            )));
+        }
+
+        if let Some(b) = tj.bounds {
+            let lon = -180.0..=180.0;
+            let lat = -90.0..=90.0;
+            if !lon.contains(&b.left)
+                || !lon.contains(&b.right)
+                || !lat.contains(&b.bottom)
+                || !lat.contains(&b.top)
+                || b.bottom > b.top
+            {
+                return Err(err(format!("bounds {b} are not valid WGS84 bounds")));
+            }
+        }
+
+        if let Some(c) = tj.center {
+            if !(minzoom..=maxzoom).contains(&c.zoom) {
+                return Err(err(format!(
+                    "center zoom {} is outside of the zoom range {minzoom}..={maxzoom}",
+                    c.zoom
+                )));
+            }
+            if let Some(b) = tj.bounds {
+                // bounds crossing the antimeridian have left > right
+                let in_lon = if b.left <= b.right {
+                    (b.left..=b.right).contains(&c.longitude)
+                } else {
+                    c.longitude >= b.left || c.longitude <= b.right
+                };
+                if !in_lon || !(b.bottom..=b.top).contains(&c.latitude) {
+                    return Err(err(format!("center {c} is outside of the bounds {b}")));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the `json` metadata value, decoding it once more if it holds a JSON-encoded string.
+fn parse_json_metadata(value: &str) -> serde_json::Result<JSONValue> {
+    match serde_json::from_str(value)? {
+        JSONValue::String(inner) => serde_json::from_str(&inner),
+        json => Ok(json),
+    }
+}
+
+/// Value of the `compression` metadata key for the given tile encoding,
+/// or `None` if the tile format is compressed by itself.
+pub(crate) fn compression_metadata_value(encoding: Encoding) -> Option<&'static str> {
+    match encoding {
+        Encoding::Internal => None,
+        Encoding::Uncompressed => Some("none"),
+        Encoding::Gzip => Some("gzip"),
+        Encoding::Zlib => Some("zlib"),
+        Encoding::Brotli => Some("brotli"),
+        Encoding::Zstd => Some("zstd"),
+    }
+}
+
+/// Checks the magic bytes of the tile data against the advertised compression.
+///
+/// Only gzip and zlib can be recognized this way, so brotli and zstd data is never reported
+/// as a mismatch unless it starts with another recognized magic. Empty tiles always match.
+pub(crate) fn is_encoding_mismatch(advertised: Encoding, data: &[u8]) -> bool {
+    let found = if data.starts_with(b"\x1f\x8b") {
+        Encoding::Gzip
+    } else if data.starts_with(b"\x78\x9c") {
+        Encoding::Zlib
+    } else {
+        return !data.is_empty() && matches!(advertised, Encoding::Gzip | Encoding::Zlib);
+    };
+    found != advertised
 }
 
 /// Create an in memory, temporary mbtile connection with the given `script`
@@ -372,13 +636,12 @@ pub async fn temp_named_mbtiles(
 mod tests {
     use std::collections::BTreeMap;
 
-    use martin_tile_utils::{Encoding, Format, TileInfo};
+    use martin_tile_utils::Format;
     use sqlx::Executor as _;
-    use tilejson::VectorLayer;
 
     use super::*;
     use crate::mbtiles::tests::open;
-    use crate::{MbtType, init_mbtiles_schema};
+    use crate::{MbtError, MbtType, init_mbtiles_schema};
 
     #[actix_rt::test]
     async fn mbtiles_meta() {
@@ -443,6 +706,44 @@ mod tests {
         assert_eq!(meta.layer_type, Some("overlay".to_string()));
     }
 
+    #[actix_rt::test]
+    async fn get_vector_layers() {
+        let script = include_str!("../../tests/fixtures/mbtiles/world_cities.sql");
+        let (mbt, mut conn) = anonymous_mbtiles(script).await;
+        let layers = mbt.get_vector_layers(&mut conn).await.unwrap().unwrap();
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].id, "cities");
+        assert_eq!(layers[0].fields["name"], "String");
+        assert_eq!((layers[0].minzoom, layers[0].maxzoom), (Some(0), Some(6)));
+
+        // Some tools store the `json` value encoded twice
+        let json = r#"{"vector_layers":[{"id":"roads","fields":{"kind":"String"}}]}"#;
+        let double_encoded = serde_json::to_string(json).unwrap();
+        mbt.set_metadata_value(&mut conn, "json", double_encoded)
+            .await
+            .unwrap();
+        let layers = mbt.get_vector_layers(&mut conn).await.unwrap().unwrap();
+        assert_eq!(layers[0].id, "roads");
+        assert_eq!(layers[0].fields["kind"], "String");
+        assert_eq!(layers[0].minzoom, None);
+
+        mbt.set_metadata_value(&mut conn, "json", r#"{"tilestats":{}}"#)
+            .await
+            .unwrap();
+        assert_eq!(mbt.get_vector_layers(&mut conn).await.unwrap(), None);
+
+        mbt.set_metadata_value(&mut conn, "json", "{")
+            .await
+            .unwrap();
+        assert!(matches!(
+            mbt.get_vector_layers(&mut conn).await,
+            Err(MbtError::JsonSerdeError(_))
+        ));
+
+        mbt.delete_metadata_value(&mut conn, "json").await.unwrap();
+        assert_eq!(mbt.get_vector_layers(&mut conn).await.unwrap(), None);
+    }
+
     #[actix_rt::test]
     async fn metadata_get_key() {
         let script = include_str!("../../tests/fixtures/mbtiles/world_cities.sql");
@@ -531,4 +832,91 @@ mod tests {
         let tile_info = mbt.detect_format(&meta.tilejson, &mut conn).await.unwrap();
         assert_eq!(tile_info, None);
     }
+
+    #[actix_rt::test]
+    async fn metadata_well_known_setters() {
+        let mbt = Mbtiles::new(":memory:").unwrap();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, MbtType::Flat).await.unwrap();
+
+        mbt.set_name(&mut conn, "World").await.unwrap();
+        mbt.set_description(&mut conn, "A map of the world")
+            .await
+            .unwrap();
+        mbt.set_attribution(&mut conn, "<a href=\"https://example.com\">Example</a>")
+            .await
+            .unwrap();
+        let tj = mbt.get_metadata(&mut conn).await.unwrap().tilejson;
+        assert_eq!(tj.name.as_deref(), Some("World"));
+        assert_eq!(tj.description.as_deref(), Some("A map of the world"));
+        assert_eq!(
+            tj.attribution.as_deref(),
+            Some("<a href=\"https://example.com\">Example</a>")
+        );
+
+        let err = mbt.set_name(&mut conn, "  ").await.unwrap_err();
+        assert!(matches!(err, EmptyMetadataValue("name")));
+        assert_eq!(
+            mbt.get_metadata_value(&mut conn, "name").await.unwrap(),
+            Some("World".to_string())
+        );
+    }
+
+    #[actix_rt::test]
+    async fn metadata_apply_tilejson() {
+        let mbt = Mbtiles::new(":memory:").unwrap();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, MbtType::Flat).await.unwrap();
+        mbt.set_metadata_value(&mut conn, "format", "pbf")
+            .await
+            .unwrap();
+        mbt.set_attribution(&mut conn, "Old attribution")
+            .await
+            .unwrap();
+        mbt.set_metadata_value(&mut conn, "json", r#"{"tilestats":{"layerCount":1}}"#)
+            .await
+            .unwrap();
+
+        let mut tj = tilejson! {
+            tiles: vec![],
+            name: "Edited".to_string(),
+            bounds: Bounds::new(-10.0, -20.0, 30.0, 40.0),
+            center: Center::new(0.0, 0.0, 3),
+            minzoom: 1,
+            maxzoom: 5,
+            vector_layers: vec![VectorLayer::new("roads".to_string(), BTreeMap::new())],
+        };
+        mbt.apply_tilejson(&mut conn, &tj).await.unwrap();
+
+        let meta = mbt.get_metadata(&mut conn).await.unwrap();
+        assert_eq!(meta.tilejson.name.as_deref(), Some("Edited"));
+        assert_eq!(meta.tilejson.bounds, tj.bounds);
+        assert_eq!(meta.tilejson.center, tj.center);
+        assert_eq!(
+            (meta.tilejson.minzoom, meta.tilejson.maxzoom),
+            (Some(1), Some(5))
+        );
+        assert_eq!(meta.tilejson.attribution, None);
+        assert_eq!(meta.tilejson.vector_layers, tj.vector_layers);
+        assert_eq!(meta.tilejson.other["format"], "pbf");
+        assert_eq!(meta.json, Some(json!({"tilestats": {"layerCount": 1}})));
+
+        // invalid values are rejected without writing anything
+        tj.name = Some("Rejected".to_string());
+        tj.minzoom = Some(6);
+        let err = mbt.apply_tilejson(&mut conn, &tj).await.unwrap_err();
+        assert!(matches!(err, InconsistentTileJson(..)), "{err}");
+        tj.minzoom = Some(1);
+        tj.center = Some(Center::new(50.0, 0.0, 3));
+        let err = mbt.apply_tilejson(&mut conn, &tj).await.unwrap_err();
+        assert!(matches!(err, InconsistentTileJson(..)), "{err}");
+        tj.center = None;
+        tj.maxzoom = Some(31);
+        let err = mbt.apply_tilejson(&mut conn, &tj).await.unwrap_err();
+        assert!(matches!(err, InvalidZoomValue("maxzoom", _)), "{err}");
+        assert_eq!(
+            mbt.get_metadata_value(&mut conn, "name").await.unwrap(),
+            Some("Edited".to_string())
+        );
+    }
 }
@@ -0,0 +1,123 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use flume::r#async::RecvStream;
+use futures::{Stream, StreamExt as _};
+use martin_tile_utils::Tile;
+use sqlx::SqliteConnection;
+use tokio::task::JoinHandle;
+
+use crate::{MbtResult, Mbtiles};
+
+/// Number of tiles read ahead of the consumer of an [`OwnedTileStream`]
+const READ_AHEAD: usize = 256;
+
+/// A stream over all tiles that owns its connection, see [`Mbtiles::stream_tiles_owned`].
+///
+/// Use [`OwnedTileStream::into_connection`] to get the connection back,
+/// either once the stream is exhausted or to stop reading early.
+pub struct OwnedTileStream {
+    tiles: RecvStream<'static, MbtResult<Tile>>,
+    reader: JoinHandle<SqliteConnection>,
+}
+
+impl Stream for OwnedTileStream {
+    type Item = MbtResult<Tile>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.tiles.poll_next_unpin(cx)
+    }
+}
+
+impl OwnedTileStream {
+    /// Stops reading tiles and returns the connection the stream was created with.
+    ///
+    /// Tiles that were not consumed yet are discarded.
+    pub async fn into_connection(self) -> SqliteConnection {
+        // Dropping the receiver makes the reader stop at the next tile
+        drop(self.tiles);
+        match self.reader.await {
+            Ok(conn) => conn,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+impl Mbtiles {
+    /// Returns a stream over all tiles in the database that takes ownership of the connection,
+    /// so the stream is not tied to a borrow and can be freely moved or stored.
+    ///
+    /// Tiles are read by a background task, so this must be called within a Tokio runtime.
+    /// No particular order is guaranteed, just like with [`Mbtiles::stream_tiles`].
+    /// Call [`OwnedTileStream::into_connection`] to reuse the connection afterwards.
+    #[must_use]
+    pub fn stream_tiles_owned(&self, mut conn: SqliteConnection) -> OwnedTileStream {
+        let (tx, rx) = flume::bounded(READ_AHEAD);
+        let mbt = self.clone();
+        let reader = tokio::spawn(async move {
+            let mut tiles = mbt.stream_tiles(&mut conn);
+            while let Some(tile) = tiles.next().await {
+                if tx.send_async(tile).await.is_err() {
+                    break;
+                }
+            }
+            drop(tiles);
+            conn
+        });
+
+        OwnedTileStream {
+            tiles: rx.into_stream(),
+            reader,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt as _;
+
+    use super::*;
+    use crate::{CopyDuplicateMode, MbtType, init_mbtiles_schema};
+
+    #[actix_rt::test]
+    async fn owned_stream() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, MbtType::Flat).await.unwrap();
+        let tiles: Vec<_> = (0..4_u8).map(|x| (2, x.into(), 1, vec![x])).collect();
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &tiles,
+        )
+        .await
+        .unwrap();
+
+        let mut stream = mbt.stream_tiles_owned(conn);
+        let mut count = 0;
+        while let Some((coord, data)) = stream.try_next().await.unwrap() {
+            assert_eq!(data, Some(vec![u8::try_from(coord.x).unwrap()]));
+            count += 1;
+        }
+        assert_eq!(count, 4);
+
+        // stop early and keep using the connection
+        let mut conn = stream.into_connection().await;
+        let mut stream = mbt.stream_tiles_owned(conn);
+        stream.try_next().await.unwrap().unwrap();
+        conn = stream.into_connection().await;
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &[(2, 0, 0, vec![9])],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            mbt.get_tile(&mut conn, 2, 0, 0).await.unwrap(),
+            Some(vec![9])
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::path::Path;
+
+use futures::TryStreamExt as _;
+use log::{debug, info};
+use martin_tile_utils::{Encoding, Format, TileInfo};
+use pmtiles::{Compression, PmTilesWriter, TileCoord, TileId, TileType};
+use serde_json::Value;
+use sqlx::{Row as _, SqliteConnection, query};
+
+use crate::mbtiles::parse_tile_index;
+use crate::{MbtError, MbtResult, Mbtiles};
+
+/// Maps the detected tile format and encoding to the `PMTiles` header values.
+fn pmtiles_type(info: TileInfo) -> Option<(TileType, Compression)> {
+    let tile_type = match info.format {
+        Format::Mvt => TileType::Mvt,
+        Format::Mlt => TileType::Mlt,
+        Format::Png => TileType::Png,
+        Format::Jpeg => TileType::Jpeg,
+        Format::Webp => TileType::Webp,
+        Format::Avif => TileType::Avif,
+        Format::Gif | Format::Json => TileType::Unknown,
+    };
+    let compression = match info.encoding {
+        Encoding::Uncompressed | Encoding::Internal => Compression::None,
+        Encoding::Gzip => Compression::Gzip,
+        Encoding::Brotli => Compression::Brotli,
+        Encoding::Zstd => Compression::Zstd,
+        Encoding::Zlib => None?,
+    };
+    Some((tile_type, compression))
+}
+
+impl Mbtiles {
+    /// Convert this file into a `PMTiles` archive at `out`, overwriting it if it exists.
+    ///
+    /// Tiles are written as-is in Hilbert order, one zoom level at a time,
+    /// so the detected tile format and compression are preserved and the archive is clustered.
+    /// Identical tiles are only stored once.
+    /// The metadata is stored as `PMTiles` JSON metadata, and also used to fill in the header's bounds and center.
+    ///
+    /// Returns the number of written tiles.
+    #[hotpath::measure]
+    pub async fn to_pmtiles(&self, conn: &mut SqliteConnection, out: &Path) -> MbtResult<u64> {
+        let metadata = self.get_metadata(&mut *conn).await?;
+        let info = self
+            .detect_format(&metadata.tilejson, &mut *conn)
+            .await?
+            .ok_or(MbtError::NoTilesFound)?;
+        let (tile_type, compression) =
+            pmtiles_type(info).ok_or(MbtError::UnsupportedPmtilesTiles(info))?;
+
+        let zooms: Vec<u8> = query("SELECT DISTINCT zoom_level FROM tiles ORDER BY zoom_level")
+            .fetch(&mut *conn)
+            .map_ok(|row| row.get::<u8, _>(0))
+            .try_collect()
+            .await?;
+        let (Some(&min_zoom), Some(&max_zoom)) = (zooms.first(), zooms.last()) else {
+            return Err(MbtError::NoTilesFound);
+        };
+
+        let mut json = serde_json::to_value(&metadata.tilejson)?;
+        if let Value::Object(obj) = &mut json {
+            obj.remove("tilejson");
+            obj.remove("tiles");
+            if let Some(Value::Object(extra)) = metadata.json {
+                obj.extend(extra);
+            }
+        }
+
+        let mut writer = PmTilesWriter::new(tile_type)
+            .tile_compression(compression)
+            .min_zoom(min_zoom)
+            .max_zoom(max_zoom)
+            .metadata(&json.to_string());
+        if let Some(b) = metadata.tilejson.bounds {
+            writer = writer.bounds(b.left, b.bottom, b.right, b.top);
+        }
+        if let Some(c) = metadata.tilejson.center {
+            writer = writer.center(c.longitude, c.latitude).center_zoom(c.zoom);
+        }
+        info!(
+            "Converting {self} ({info}) to PMTiles archive {}",
+            out.display()
+        );
+        let mut writer = writer.create(File::create(out)?)?;
+
+        let mut count = 0;
+        for z in zooms {
+            let mut coords: Vec<(TileId, TileCoord)> =
+                query("SELECT tile_column, tile_row FROM tiles WHERE zoom_level = ?")
+                    .bind(z)
+                    .fetch(&mut *conn)
+                    .map_err(MbtError::from)
+                    .and_then(|row| async move {
+                        let (x, y) = (row.get(0), row.get(1));
+                        let xyz = parse_tile_index(Some(z.into()), x, y).ok_or_else(|| {
+                            MbtError::invalid_tile_index(self.filepath(), Some(z.into()), x, y)
+                        })?;
+                        let coord = TileCoord::new(xyz.z, xyz.x, xyz.y)?;
+                        Ok((TileId::from(coord), coord))
+                    })
+                    .try_collect()
+                    .await?;
+            coords.sort_unstable_by_key(|(id, _)| *id);
+            debug!("Writing {} tiles of zoom {z}", coords.len());
+
+            for (_, coord) in coords {
+                if let Some(data) = self
+                    .get_tile(&mut *conn, coord.z(), coord.x(), coord.y())
+                    .await?
+                {
+                    writer.add_raw_tile(coord, &data)?;
+                    count += 1;
+                }
+            }
+        }
+        writer.finalize()?;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use martin_tile_utils::encode_gzip;
+    use pmtiles::AsyncPmTilesReader;
+
+    use super::*;
+    use crate::{CopyDuplicateMode, MbtType, init_mbtiles_schema};
+
+    #[actix_rt::test]
+    async fn to_pmtiles() {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, MbtType::Flat).await.unwrap();
+        mbt.set_name(&mut conn, "Test").await.unwrap();
+        mbt.set_metadata_value(&mut conn, "format", "pbf")
+            .await
+            .unwrap();
+        let tile = |v: u8| encode_gzip(&[0x1A, 0x03, 0x78, 0x02, v]).unwrap();
+        let tiles = [
+            (0, 0, 0, tile(1)),
+            (1, 1, 0, tile(2)),
+            (1, 0, 1, tile(3)),
+            (2, 3, 3, tile(2)),
+        ];
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &tiles,
+        )
+        .await
+        .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("test.pmtiles");
+        assert_eq!(mbt.to_pmtiles(&mut conn, &out).await.unwrap(), 4);
+
+        let reader = AsyncPmTilesReader::new_with_path(&out).await.unwrap();
+        let header = reader.get_header();
+        assert_eq!(header.tile_type, TileType::Mvt);
+        assert_eq!(header.tile_compression, Compression::Gzip);
+        assert_eq!((header.min_zoom, header.max_zoom), (0, 2));
+        for (z, x, y, data) in tiles {
+            let coord = TileCoord::new(z, x, y).unwrap();
+            let actual = reader.get_tile(coord).await.unwrap().unwrap();
+            assert_eq!(actual.as_ref(), data.as_slice(), "{z}/{x}/{y}");
+        }
+        let meta: Value = serde_json::from_str(&reader.get_metadata().await.unwrap()).unwrap();
+        assert_eq!(meta["name"], "Test");
+    }
+}
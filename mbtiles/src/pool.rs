@@ -1,7 +1,6 @@
 use std::path::Path;
 
 use martin_tile_utils::TileInfo;
-use sqlx::sqlite::SqliteConnectOptions;
 use sqlx::{Pool, Sqlite, SqlitePool};
 use tilejson::TileJSON;
 
@@ -115,10 +114,23 @@ impl MbtilesPool {
     /// ```
     #[hotpath::measure]
     pub async fn open_readonly<P: AsRef<Path>>(filepath: P) -> MbtResult<Self> {
-        let mbtiles = Mbtiles::new(filepath)?;
-        let opt = SqliteConnectOptions::new()
-            .filename(mbtiles.filepath())
-            .read_only(true);
+        Self::open_readonly_with(Mbtiles::new(filepath)?).await
+    }
+
+    /// Opens the given `MBTiles` file in read-only mode with connection pooling.
+    ///
+    /// Unlike [`open_readonly`](Self::open_readonly), this respects the settings of
+    /// the given [`Mbtiles`] instance, such as its [busy timeout](Mbtiles::with_busy_timeout).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The file does not exist
+    /// - The file is not a valid `SQLite` database
+    /// - The connection pool cannot be created
+    #[hotpath::measure]
+    pub async fn open_readonly_with(mbtiles: Mbtiles) -> MbtResult<Self> {
+        let opt = mbtiles.connect_options().read_only(true);
         let pool = SqlitePool::connect_with(opt).await?;
         Ok(Self { mbtiles, pool })
     }
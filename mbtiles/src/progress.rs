@@ -0,0 +1,134 @@
+use futures::TryStreamExt as _;
+use log::debug;
+use sqlx::SqliteConnection;
+
+use crate::{CopyDuplicateMode, MbtResult, Mbtiles};
+
+/// Progress of a copy started with [`Mbtiles::copy_tiles_with_progress`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CopyProgress {
+    /// Number of tiles read from the source so far
+    pub tiles_done: u64,
+    /// Total number of tiles in the source, if known
+    pub tiles_total: Option<u64>,
+}
+
+impl Mbtiles {
+    /// Copy all tiles of this (source) file into an existing `dst` file, one batch at a time,
+    /// calling `progress` after every batch of `batch_size` tiles and once more when done.
+    ///
+    /// Unlike [`MbtilesCopier`](crate::MbtilesCopier), which copies everything in a single SQL statement,
+    /// this streams tiles through [`Mbtiles::insert_tiles`], so the copy can report how far it got.
+    /// The destination must already have an `MBTiles` schema, see [`init_mbtiles_schema`](crate::init_mbtiles_schema).
+    /// Metadata is not copied. The callback runs on the calling task.
+    ///
+    /// Returns the number of copied tiles.
+    ///
+    /// # Panics
+    /// Panics if `batch_size` is zero.
+    #[hotpath::measure]
+    pub async fn copy_tiles_with_progress(
+        &self,
+        src_conn: &mut SqliteConnection,
+        dst: &Self,
+        dst_conn: &mut SqliteConnection,
+        on_duplicate: CopyDuplicateMode,
+        batch_size: usize,
+        mut progress: impl FnMut(CopyProgress),
+    ) -> MbtResult<u64> {
+        assert!(batch_size > 0, "batch_size must be greater than zero");
+        let dst_type = dst.detect_type(&mut *dst_conn).await?;
+        let mut state = CopyProgress {
+            tiles_done: 0,
+            tiles_total: Some(self.count_tiles(&mut *src_conn).await?),
+        };
+        debug!("Copying {:?} tiles from {self} to {dst}", state.tiles_total);
+
+        let mut tiles = self.stream_tiles(&mut *src_conn);
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut copied = 0_u64;
+        while let Some((coord, data)) = tiles.try_next().await? {
+            state.tiles_done += 1;
+            if let Some(data) = data {
+                batch.push((coord.z, coord.x, coord.y, data));
+            }
+            if batch.len() >= batch_size {
+                dst.insert_tiles(dst_conn, dst_type, on_duplicate, &batch)
+                    .await?;
+                copied += batch.len() as u64;
+                batch.clear();
+                progress(state);
+            }
+        }
+        if !batch.is_empty() {
+            dst.insert_tiles(dst_conn, dst_type, on_duplicate, &batch)
+                .await?;
+            copied += batch.len() as u64;
+        }
+        progress(state);
+
+        Ok(copied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CopyDuplicateMode, CopyProgress, MbtType, Mbtiles, init_mbtiles_schema};
+
+    #[actix_rt::test]
+    async fn copy_tiles_with_progress() {
+        let src = Mbtiles::new_in_memory();
+        let mut src_conn = src.open().await.unwrap();
+        init_mbtiles_schema(&mut src_conn, MbtType::Flat)
+            .await
+            .unwrap();
+        let tiles: Vec<_> = (0..4_u8)
+            .flat_map(|x| (0..4_u8).map(move |y| (2, x.into(), y.into(), vec![x, y])))
+            .chain([(3, 0, 0, vec![9])])
+            .collect();
+        src.insert_tiles(
+            &mut src_conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &tiles,
+        )
+        .await
+        .unwrap();
+
+        let dst = Mbtiles::new_in_memory();
+        let mut dst_conn = dst.open().await.unwrap();
+        init_mbtiles_schema(&mut dst_conn, MbtType::FlatWithHash)
+            .await
+            .unwrap();
+
+        let mut reports = Vec::new();
+        let copied = src
+            .copy_tiles_with_progress(
+                &mut src_conn,
+                &dst,
+                &mut dst_conn,
+                CopyDuplicateMode::Override,
+                5,
+                |p| reports.push(p),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(copied, 17);
+        assert_eq!(dst.count_tiles(&mut dst_conn).await.unwrap(), 17);
+        let done: Vec<_> = reports.iter().map(|p| p.tiles_done).collect();
+        assert_eq!(done, [5, 10, 15, 17]);
+        assert!(reports.iter().all(|p| p.tiles_total == Some(17)));
+        assert_eq!(
+            dst.get_tile(&mut dst_conn, 2, 3, 1).await.unwrap(),
+            Some(vec![3, 1])
+        );
+        assert_eq!(
+            reports.last(),
+            Some(&CopyProgress {
+                tiles_done: 17,
+                tiles_total: Some(17)
+            })
+        );
+    }
+}
@@ -386,6 +386,10 @@ where
     Ok(())
 }
 
+/// Creates the tables and views of `mbt_type`.
+///
+/// An [`Mbtiles`](crate::Mbtiles) instance that already detected the type of this file
+/// must call [`Mbtiles::invalidate_type_cache`](crate::Mbtiles::invalidate_type_cache) afterwards.
 pub async fn init_mbtiles_schema<T>(conn: &mut T, mbt_type: MbtType) -> MbtResult<()>
 where
     for<'e> &'e mut T: SqliteExecutor<'e>,
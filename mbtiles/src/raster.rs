@@ -0,0 +1,326 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageFormat};
+use log::{debug, info};
+use martin_tile_utils::{Format, TileInfo};
+use sqlx::{Connection as _, Row as _, SqliteConnection, query};
+use tokio::task::spawn_blocking;
+
+use crate::errors::{MbtError, MbtResult};
+use crate::mbtiles::parse_tile_index;
+use crate::{CopyDuplicateMode, MbtType, Mbtiles, NormalizedSchema, invert_y_value};
+
+/// Number of tiles read, re-encoded and written at once.
+const BATCH_SIZE: i64 = 500;
+
+impl Mbtiles {
+    /// Re-encodes all raster tiles stored as `from` into the `to` format, e.g. to shrink a PNG archive by converting it to WebP.
+    ///
+    /// Tiles in any other format, such as vector tiles, are left untouched.
+    /// The `format` metadata value and the `agg_tiles_hash` (if present) are updated accordingly.
+    /// `quality` (`1..=100`) is only used when encoding JPEG, PNG and WebP are always encoded losslessly.
+    ///
+    /// Returns the number of transcoded tiles.
+    ///
+    /// # Errors
+    /// Returns [`MbtError::UnsupportedTranscode`] unless `from` and `to` are two different formats
+    /// out of PNG, JPEG and WebP, or an error if a tile cannot be decoded or the database cannot be updated.
+    pub async fn transcode_tiles(
+        &self,
+        conn: &mut SqliteConnection,
+        from: Format,
+        to: Format,
+        quality: u8,
+    ) -> MbtResult<u64> {
+        if from == to || image_format(from).is_none() || image_format(to).is_none() {
+            return Err(MbtError::UnsupportedTranscode(from, to));
+        }
+        let mbt_type = self.detect_type(&mut *conn).await?;
+        info!("Transcoding {from} tiles to {to} in {self} ({mbt_type})");
+
+        let mut transcoded = 0;
+        let mut last = (-1_i64, -1_i64, -1_i64);
+        loop {
+            let rows = query(
+                "SELECT zoom_level, tile_column, tile_row, tile_data
+                 FROM tiles
+                 WHERE (zoom_level, tile_column, tile_row) > (?, ?, ?)
+                 ORDER BY zoom_level, tile_column, tile_row
+                 LIMIT ?",
+            )
+            .bind(last.0)
+            .bind(last.1)
+            .bind(last.2)
+            .bind(BATCH_SIZE)
+            .fetch_all(&mut *conn)
+            .await?;
+            let Some(row) = rows.last() else { break };
+            last = (row.try_get(0)?, row.try_get(1)?, row.try_get(2)?);
+
+            let mut tiles = Vec::with_capacity(rows.len());
+            for row in rows {
+                let coord = parse_tile_index(row.try_get(0)?, row.try_get(1)?, row.try_get(2)?);
+                let data: Option<Vec<u8>> = row.try_get(3)?;
+                if let (Some(coord), Some(data)) = (coord, data) {
+                    tiles.push((coord, data));
+                }
+            }
+
+            let batch = spawn_blocking(move || {
+                let mut batch = Vec::new();
+                for (coord, data) in tiles {
+                    if let Some(data) = transcode_tile(&data, from, to, quality)? {
+                        batch.push((coord.z, coord.x, coord.y, data));
+                    }
+                }
+                Ok::<_, MbtError>(batch)
+            })
+            .await
+            .map_err(MbtError::TranscodeTaskError)??;
+
+            debug!("Writing {} transcoded tiles to {self}", batch.len());
+            if let MbtType::Normalized {
+                schema: NormalizedSchema::DedupId,
+                ..
+            } = mbt_type
+            {
+                insert_dedup_id_tiles(&mut *conn, &batch).await?;
+            } else {
+                self.insert_tiles(&mut *conn, mbt_type, CopyDuplicateMode::Override, &batch)
+                    .await?;
+            }
+            transcoded += batch.len() as u64;
+        }
+
+        if let MbtType::Normalized { schema, .. } = mbt_type {
+            // Overridden tiles reference new images, the old ones are no longer used
+            let images = schema.content_table();
+            let map = schema.map_table();
+            let id = schema.tile_id_column();
+            query(&format!(
+                "DELETE FROM {images} WHERE {id} NOT IN (SELECT {id} FROM {map})"
+            ))
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        let format = self.get_metadata_value(&mut *conn, "format").await?;
+        if transcoded > 0 || format.as_deref().and_then(Format::parse) == Some(from) {
+            self.set_metadata_value(&mut *conn, "format", to.metadata_format_value())
+                .await?;
+        }
+        if self.get_agg_tiles_hash(&mut *conn).await?.is_some() {
+            self.update_agg_tiles_hash(&mut *conn).await?;
+        }
+
+        info!("Transcoded {transcoded} tiles from {from} to {to} in {self}");
+        Ok(transcoded)
+    }
+}
+
+/// Stores the transcoded tiles of a normalized file with integer `tile_data_id`s as new `tiles_data` rows,
+/// pointing the existing `tiles_shallow` rows at them.
+async fn insert_dedup_id_tiles(
+    conn: &mut SqliteConnection,
+    batch: &[(u8, u32, u32, Vec<u8>)],
+) -> MbtResult<()> {
+    let mut tx = conn.begin().await?;
+    for (z, x, y, data) in batch {
+        let tile_data_id = query("INSERT INTO tiles_data (tile_data) VALUES (?)")
+            .bind(data)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+        query(
+            "UPDATE tiles_shallow SET tile_data_id = ?
+             WHERE zoom_level = ? AND tile_column = ? AND tile_row = ?",
+        )
+        .bind(tile_data_id)
+        .bind(z)
+        .bind(x)
+        .bind(invert_y_value(*z, *y))
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn image_format(format: Format) -> Option<ImageFormat> {
+    match format {
+        Format::Png => Some(ImageFormat::Png),
+        Format::Jpeg => Some(ImageFormat::Jpeg),
+        Format::Webp => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+/// Re-encodes a single tile, returning `None` if it is not stored in the `from` format.
+fn transcode_tile(
+    data: &[u8],
+    from: Format,
+    to: Format,
+    quality: u8,
+) -> MbtResult<Option<Vec<u8>>> {
+    let (Some(src_format), Some(_)) = (image_format(from), image_format(to)) else {
+        return Err(MbtError::UnsupportedTranscode(from, to));
+    };
+    if TileInfo::detect(data).format != from {
+        return Ok(None);
+    }
+
+    let img = image::load_from_memory_with_format(data, src_format)?;
+    let mut out = Cursor::new(Vec::with_capacity(data.len()));
+    match to {
+        Format::Jpeg => {
+            DynamicImage::from(img.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality))?;
+        }
+        Format::Png => img.write_with_encoder(PngEncoder::new(&mut out))?,
+        _ => {
+            // the WebP encoder only supports 8-bit images
+            let img = if img.color().has_alpha() {
+                DynamicImage::from(img.to_rgba8())
+            } else {
+                DynamicImage::from(img.to_rgb8())
+            };
+            img.write_with_encoder(WebPEncoder::new_lossless(&mut out))?;
+        }
+    }
+    Ok(Some(out.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use martin_tile_utils::Encoding;
+
+    use super::*;
+    use crate::init_mbtiles_schema;
+
+    fn png_tile() -> Vec<u8> {
+        let img = RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([
+                u8::try_from(x * 16).unwrap(),
+                u8::try_from(y * 16).unwrap(),
+                0,
+                255,
+            ])
+        });
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, ImageFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    async fn new(mbt_type: MbtType) -> (Mbtiles, SqliteConnection) {
+        let mbt = Mbtiles::new(":memory:").unwrap();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, mbt_type).await.unwrap();
+        mbt.set_metadata_value(&mut conn, "format", "png")
+            .await
+            .unwrap();
+        let tiles = [(0, 0, 0, png_tile()), (1, 0, 0, b"not an image".to_vec())];
+        mbt.insert_tiles(&mut conn, mbt_type, CopyDuplicateMode::Override, &tiles)
+            .await
+            .unwrap();
+        (mbt, conn)
+    }
+
+    #[rstest::rstest]
+    #[case::flat(MbtType::Flat)]
+    #[case::flat_with_hash(MbtType::FlatWithHash)]
+    #[case::normalized(MbtType::Normalized { hash_view: false, schema: NormalizedSchema::Hash })]
+    #[actix_rt::test]
+    async fn png_to_webp(#[case] mbt_type: MbtType) {
+        let (mbt, mut conn) = new(mbt_type).await;
+
+        let count = mbt
+            .transcode_tiles(&mut conn, Format::Png, Format::Webp, 90)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let tile = mbt.get_tile(&mut conn, 0, 0, 0).await.unwrap().unwrap();
+        assert_eq!(
+            TileInfo::detect(&tile),
+            TileInfo::new(Format::Webp, Encoding::Internal)
+        );
+        let tile = mbt.get_tile(&mut conn, 1, 0, 0).await.unwrap().unwrap();
+        assert_eq!(tile, b"not an image");
+        assert_eq!(
+            mbt.get_metadata_value(&mut conn, "format").await.unwrap(),
+            Some("webp".to_string())
+        );
+    }
+
+    #[actix_rt::test]
+    async fn png_to_webp_dedup_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mbt = Mbtiles::new(dir.path().join("dedup-id.mbtiles")).unwrap();
+        let mut conn = mbt.open_or_new().await.unwrap();
+        sqlx::raw_sql(
+            "CREATE TABLE metadata (name text, value text);
+             CREATE UNIQUE INDEX name ON metadata (name);
+             CREATE TABLE tiles_shallow (
+                 zoom_level integer, tile_column integer, tile_row integer, tile_data_id integer,
+                 PRIMARY KEY(zoom_level, tile_column, tile_row)
+             ) WITHOUT ROWID;
+             CREATE TABLE tiles_data (tile_data_id integer PRIMARY KEY, tile_data blob);
+             CREATE VIEW tiles AS
+                 SELECT zoom_level, tile_column, tile_row, tile_data
+                 FROM tiles_shallow JOIN tiles_data USING (tile_data_id);
+             INSERT INTO tiles_shallow VALUES (1, 0, 0, 1), (1, 0, 1, 1), (1, 1, 0, 2);
+             INSERT INTO tiles_data VALUES (2, CAST('not an image' AS BLOB));",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        query("INSERT INTO tiles_data VALUES (1, ?)")
+            .bind(png_tile())
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let count = mbt
+            .transcode_tiles(&mut conn, Format::Png, Format::Webp, 90)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        for (x, y) in [(0, 0), (0, 1)] {
+            let tile = mbt.get_tile(&mut conn, 1, x, y).await.unwrap().unwrap();
+            assert_eq!(
+                TileInfo::detect(&tile),
+                TileInfo::new(Format::Webp, Encoding::Internal)
+            );
+        }
+        let tile = mbt.get_tile(&mut conn, 1, 1, 1).await.unwrap().unwrap();
+        assert_eq!(tile, b"not an image");
+
+        // the original PNG is no longer referenced and must be removed
+        let orphans: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM tiles_data
+             WHERE tile_data_id NOT IN (SELECT tile_data_id FROM tiles_shallow)",
+        )
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(orphans, 0);
+    }
+
+    #[rstest::rstest]
+    #[case(Format::Png, Format::Png)]
+    #[case(Format::Mvt, Format::Webp)]
+    #[case(Format::Png, Format::Mlt)]
+    #[actix_rt::test]
+    async fn unsupported(#[case] from: Format, #[case] to: Format) {
+        let (mbt, mut conn) = new(MbtType::Flat).await;
+        assert!(matches!(
+            mbt.transcode_tiles(&mut conn, from, to, 90).await,
+            Err(MbtError::UnsupportedTranscode(..))
+        ));
+    }
+}
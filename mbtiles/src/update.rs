@@ -1,11 +1,11 @@
 use enum_display::EnumDisplay;
-use log::{info, warn};
-use sqlx::SqliteExecutor;
+use log::{debug, info, warn};
+use sqlx::{Connection as _, SqliteConnection, SqliteExecutor, query};
 
 use self::UpdateZoomType::{GrowOnly, Reset, Skip};
-use crate::MbtError::InvalidZoomValue;
+use crate::MbtError::{InvalidZoomRange, InvalidZoomValue};
 use crate::errors::MbtResult;
-use crate::{Mbtiles, compute_min_max_zoom};
+use crate::{MbtType, Mbtiles, compute_min_max_zoom};
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, EnumDisplay)]
 #[enum_display(case = "Kebab")]
@@ -20,6 +20,16 @@ pub enum UpdateZoomType {
     Skip,
 }
 
+/// Which tiles [`Mbtiles::delete_zoom_range`] deletes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumDisplay)]
+#[enum_display(case = "Kebab")]
+pub enum ZoomRangeDeletion {
+    /// Delete the tiles with a zoom level within the range
+    Inside,
+    /// Delete the tiles with a zoom level outside of the range, keeping only the range
+    Outside,
+}
+
 impl Mbtiles {
     async fn set_zoom_value<T>(
         &self,
@@ -103,4 +113,119 @@ impl Mbtiles {
 
         Ok(())
     }
+
+    /// Delete the tiles inside or outside of the `min_zoom..=max_zoom` range, e.g. to trim high zoom levels to save space.
+    ///
+    /// For normalized files, tile data no longer referenced by any tile is deleted as well.
+    /// The `minzoom` and `maxzoom` metadata values are reset to the remaining tiles,
+    /// and the `agg_tiles_hash` is updated if the file has one.
+    /// Everything is done in a single transaction.
+    ///
+    /// Returns the number of deleted tiles.
+    #[hotpath::measure]
+    pub async fn delete_zoom_range(
+        &self,
+        conn: &mut SqliteConnection,
+        min_zoom: u8,
+        max_zoom: u8,
+        deletion: ZoomRangeDeletion,
+    ) -> MbtResult<u64> {
+        if min_zoom > max_zoom {
+            return Err(InvalidZoomRange(min_zoom, max_zoom));
+        }
+        let mut tx = conn.begin().await?;
+        let mbt_type = self.detect_type(&mut *tx).await?;
+        let table = match mbt_type {
+            MbtType::Flat => "tiles",
+            MbtType::FlatWithHash => "tiles_with_hash",
+            MbtType::Normalized { schema, .. } => schema.map_table(),
+        };
+        let not = match deletion {
+            ZoomRangeDeletion::Inside => "",
+            ZoomRangeDeletion::Outside => "NOT",
+        };
+        let sql = format!("DELETE FROM {table} WHERE zoom_level {not} BETWEEN ? AND ?");
+        let deleted = query(&sql)
+            .bind(min_zoom)
+            .bind(max_zoom)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        if let Some(schema) = mbt_type.normalized_schema() {
+            debug!("Removing unused tiles from the images table (normalized schema)");
+            let (map, img, id) = (
+                schema.map_table(),
+                schema.content_table(),
+                schema.tile_id_column(),
+            );
+            let sql = format!(
+                "DELETE FROM {img} WHERE NOT EXISTS (SELECT 1 FROM {map} WHERE {map}.{id} = {img}.{id})"
+            );
+            query(&sql).execute(&mut *tx).await?;
+        }
+
+        self.update_metadata(&mut *tx, Reset).await?;
+        if self.get_agg_tiles_hash(&mut *tx).await?.is_some() {
+            self.update_agg_tiles_hash(&mut *tx).await?;
+        }
+        tx.commit().await?;
+
+        info!(
+            "Deleted {deleted} tiles {deletion} of zoom levels {min_zoom}..={max_zoom} from {self}"
+        );
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Row as _;
+
+    use super::*;
+    use crate::{CopyDuplicateMode, NormalizedSchema, init_mbtiles_schema};
+
+    #[rstest::rstest]
+    #[case::flat(MbtType::Flat)]
+    #[case::flat_with_hash(MbtType::FlatWithHash)]
+    #[case::normalized(MbtType::Normalized { hash_view: false, schema: NormalizedSchema::Hash })]
+    #[actix_rt::test]
+    async fn delete_zoom_range(#[case] mbt_type: MbtType) {
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, mbt_type).await.unwrap();
+        let tiles: Vec<_> = (0..=4_u8).map(|z| (z, 0, 0, vec![z])).collect();
+        mbt.insert_tiles(&mut conn, mbt_type, CopyDuplicateMode::Override, &tiles)
+            .await
+            .unwrap();
+        mbt.update_metadata(&mut conn, Reset).await.unwrap();
+
+        let deleted = mbt
+            .delete_zoom_range(&mut conn, 1, 3, ZoomRangeDeletion::Outside)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 2);
+        let deleted = mbt
+            .delete_zoom_range(&mut conn, 3, 3, ZoomRangeDeletion::Inside)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+
+        assert_eq!(compute_min_max_zoom(&mut conn).await.unwrap(), Some((1, 2)));
+        let meta = mbt.get_metadata(&mut conn).await.unwrap().tilejson;
+        assert_eq!((meta.minzoom, meta.maxzoom), (Some(1), Some(2)));
+        if mbt_type.is_normalized() {
+            let images = query("SELECT COUNT(*) FROM images")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap();
+            assert_eq!(images.get::<i64, _>(0), 2);
+        }
+
+        let err = mbt
+            .delete_zoom_range(&mut conn, 3, 1, ZoomRangeDeletion::Inside)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, InvalidZoomRange(3, 1)));
+    }
 }
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::str::from_utf8;
 
 use enum_display::EnumDisplay;
@@ -17,10 +17,10 @@ use crate::MbtError::{
 use crate::errors::{MbtError, MbtResult};
 use crate::mbtiles::PatchFileInfo;
 use crate::queries::{
-    has_tiles_with_hash, is_dedup_id_normalized_tables_type, is_flat_tables_type,
-    is_flat_with_hash_tables_type, is_normalized_tables_type,
+    create_tiles_with_hash_view, has_tiles_with_hash, is_dedup_id_normalized_tables_type,
+    is_flat_tables_type, is_flat_with_hash_tables_type, is_normalized_tables_type,
 };
-use crate::{Mbtiles, get_patch_type, invert_y_value};
+use crate::{Mbtiles, checked_invert_y, get_patch_type};
 
 /// Metadata key for the aggregate tiles hash value
 pub const AGG_TILES_HASH: &str = "agg_tiles_hash";
@@ -189,6 +189,22 @@ impl Mbtiles {
         self.validate(&mut conn, check_type, agg_hash).await
     }
 
+    /// Open the mbtiles file in read-only mode, verifying its [`AGG_TILES_HASH`] metadata value if it has one.
+    ///
+    /// Recomputing the aggregate hash reads every tile, so this is much slower than [`Mbtiles::open_readonly`].
+    /// Returns [`MbtError::AggHashMismatch`] if the stored value does not match the tiles.
+    /// Files without the metadata value are opened without any verification.
+    #[hotpath::measure]
+    pub async fn open_verified(&self) -> MbtResult<SqliteConnection> {
+        let mut conn = self.open_readonly().await?;
+        if self.get_agg_tiles_hash(&mut conn).await?.is_some() {
+            self.check_agg_tiles_hashes(&mut conn).await?;
+        } else {
+            debug!("{self} has no {AGG_TILES_HASH} metadata value to verify");
+        }
+        Ok(conn)
+    }
+
     /// Validate the integrity of the mbtiles file by:
     /// - sqlite internal integrity check
     /// - tiles' table has the expected column, row, zoom, and data values
@@ -310,6 +326,48 @@ impl Mbtiles {
         }
     }
 
+    /// Count how many of up to `sample_limit` randomly picked tiles have each format and encoding.
+    ///
+    /// Unlike [`Mbtiles::detect_format`], which fails on the first inconsistency, this returns a histogram,
+    /// e.g. to find out how many tiles of a bad import are PNG and how many are WebP.
+    /// A consistent file has exactly one entry. Tiles without data are not counted.
+    #[hotpath::measure]
+    pub async fn distinct_formats<T>(
+        &self,
+        conn: &mut T,
+        sample_limit: usize,
+    ) -> MbtResult<HashMap<TileInfo, usize>>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        let limit = i64::try_from(sample_limit).unwrap_or(i64::MAX);
+        let rows = query(
+            "SELECT tile_data FROM tiles
+             WHERE (zoom_level, tile_column, tile_row) IN (
+                 SELECT zoom_level, tile_column, tile_row FROM tiles
+                 WHERE tile_data IS NOT NULL
+                 ORDER BY random()
+                 LIMIT ?)",
+        )
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut formats = HashMap::new();
+        for row in rows {
+            let tile: Vec<u8> = row.get(0);
+            *formats.entry(TileInfo::detect(&tile)).or_default() += 1;
+        }
+        if formats.len() > 1 {
+            warn!(
+                "Found {} different tile formats in {}",
+                formats.len(),
+                self.filename()
+            );
+        }
+        Ok(formats)
+    }
+
     /// Detects the format of a tile and returns its information if none of the values are `None`
     fn parse_tile(
         &self,
@@ -323,8 +381,10 @@ impl Mbtiles {
             debug!(
                 "Tile {z}/{x}/{} is detected as {info} in file {}",
                 {
-                    if let (Ok(z), Ok(y)) = (u8::try_from(z), u32::try_from(y)) {
-                        invert_y_value(z, y).to_string()
+                    if let (Ok(z), Ok(y)) = (u8::try_from(z), u32::try_from(y))
+                        && let Some(y) = checked_invert_y(z, y)
+                    {
+                        y.to_string()
                     } else {
                         format!("{y} (invalid values, cannot invert Y)")
                     }
@@ -337,18 +397,63 @@ impl Mbtiles {
         }
     }
 
+    /// Check if the `tiles_with_hash` view exists and has the expected columns.
+    ///
+    /// This is the `hash_view` flag of [`MbtType::Normalized`], but can be checked without a full [`Mbtiles::detect_type`].
+    /// If the view exists, tiles and their hashes can be read with a single lookup.
+    #[hotpath::measure]
+    pub async fn has_hash_view<T>(&self, conn: &mut T) -> MbtResult<bool>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        let is_view =
+            query("SELECT 1 FROM sqlite_schema WHERE type = 'view' AND name = 'tiles_with_hash'")
+                .fetch_optional(&mut *conn)
+                .await?
+                .is_some();
+        Ok(is_view && has_tiles_with_hash(&mut *conn).await?)
+    }
+
+    /// Create the `tiles_with_hash` view for a normalized file that does not have it yet, e.g. one created by an older tool.
+    ///
+    /// Once the view exists, tiles and their hashes are read with a single lookup,
+    /// and [`Mbtiles::detect_type`] reports [`MbtType::Normalized`] with `hash_view: true`.
+    /// Does nothing if the view already exists.
+    /// Returns [`MbtError::NoNormalizedTables`] if the file does not have the `map` and `images` tables.
+    #[hotpath::measure]
+    pub async fn create_hash_view<T>(&self, conn: &mut T) -> MbtResult<()>
+    where
+        for<'e> &'e mut T: SqliteExecutor<'e>,
+    {
+        if !is_normalized_tables_type(&mut *conn).await? {
+            return Err(MbtError::NoNormalizedTables(self.filepath().to_string()));
+        }
+        create_tiles_with_hash_view(&mut *conn).await?;
+        self.invalidate_type_cache();
+        Ok(())
+    }
+
     /// Detect the type of the `MBTiles` file.
     ///
     /// See [`MbtType`] for more information.
+    ///
+    /// The type of a file does not change while it is in use, so the first successfully detected type
+    /// is cached and returned by later calls on this instance and its clones without querying the schema again.
+    /// Helpers of this crate that change the schema of an instance clear the cache,
+    /// other callers must call [`Mbtiles::invalidate_type_cache`] after changing the schema.
+    /// In-memory databases are never cached.
     #[hotpath::measure]
     pub async fn detect_type<T>(&self, conn: &mut T) -> MbtResult<MbtType>
     where
         for<'e> &'e mut T: SqliteExecutor<'e>,
     {
+        if let Some(typ) = *self.detected_type_cache() {
+            return Ok(typ);
+        }
         debug!("Detecting MBTiles type for {self}");
         let typ = if is_normalized_tables_type(&mut *conn).await? {
             MbtType::Normalized {
-                hash_view: has_tiles_with_hash(&mut *conn).await?,
+                hash_view: self.has_hash_view(&mut *conn).await?,
                 schema: NormalizedSchema::Hash,
             }
         } else if is_dedup_id_normalized_tables_type(&mut *conn).await? {
@@ -361,15 +466,34 @@ impl Mbtiles {
         } else if is_flat_tables_type(&mut *conn).await? {
             MbtType::Flat
         } else {
-            return Err(MbtError::InvalidDataFormat(self.filepath().to_string()));
+            let found_tables = query(
+                "SELECT name FROM sqlite_schema WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY name",
+            )
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+            return Err(MbtError::UnsupportedSchema {
+                filepath: self.filepath().to_string(),
+                found_tables,
+            });
         };
 
         self.check_for_uniqueness_constraint(&mut *conn, typ)
             .await?;
 
+        if !self.is_in_memory() {
+            *self.detected_type_cache() = Some(typ);
+        }
         Ok(typ)
     }
 
+    /// Forget the type cached by [`Mbtiles::detect_type`], e.g. after tables or views were created or dropped.
+    pub fn invalidate_type_cache(&self) {
+        *self.detected_type_cache() = None;
+    }
+
     async fn check_for_uniqueness_constraint<T>(
         &self,
         conn: &mut T,
@@ -483,35 +607,31 @@ LIMIT 1;"
         );
 
         if let Some(row) = query(&sql).fetch_optional(&mut *conn).await? {
-            let mut res: Vec<String> = Vec::with_capacity(3);
-            for idx in (0..3).rev() {
+            let value = |idx| -> MbtResult<Option<String>> {
                 use sqlx::ValueRef as _;
                 let raw = row.try_get_raw(idx)?;
-                if raw.is_null() {
-                    res.push("NULL".to_string());
+                Ok(if raw.is_null() {
+                    None
                 } else if let Ok(v) = row.try_get::<String, _>(idx) {
-                    res.push(format!(r#""{v}" (TEXT)"#));
+                    Some(format!(r#""{v}" (TEXT)"#))
                 } else if let Ok(v) = row.try_get::<Vec<u8>, _>(idx) {
-                    res.push(format!(
+                    Some(format!(
                         r#""{}" (BLOB)"#,
                         from_utf8(&v).unwrap_or("<non-utf8-data>")
-                    ));
-                } else if let Ok(v) = row.try_get::<i32, _>(idx) {
-                    res.push(format!("{v}"));
+                    ))
+                } else if let Ok(v) = row.try_get::<i64, _>(idx) {
+                    Some(v.to_string())
                 } else if let Ok(v) = row.try_get::<f64, _>(idx) {
-                    res.push(format!("{v} (REAL)"));
+                    Some(format!("{v} (REAL)"))
                 } else {
-                    res.push(format!("{:?}", raw.type_info()));
-                }
-            }
-
-            let [tile_row, tile_column, zoom_level]: [String; 3] =
-                res.try_into().expect("res should contain exactly 3 items");
+                    Some(format!("{:?}", raw.type_info()))
+                })
+            };
             return Err(InvalidTileIndex(
                 self.filepath().to_string(),
-                zoom_level,
-                tile_column,
-                tile_row,
+                value(0)?,
+                value(1)?,
+                value(2)?,
             ));
         }
 
@@ -758,6 +878,45 @@ pub(crate) mod tests {
     use crate::mbtiles::tests::open;
     use crate::metadata::anonymous_mbtiles;
 
+    #[actix_rt::test]
+    async fn distinct_formats() {
+        use martin_tile_utils::Encoding;
+
+        use crate::{CopyDuplicateMode, init_mbtiles_schema};
+
+        let mbt = Mbtiles::new_in_memory();
+        let mut conn = mbt.open().await.unwrap();
+        init_mbtiles_schema(&mut conn, MbtType::Flat).await.unwrap();
+        let png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let webp = b"RIFF\0\0\0\0WEBPVP8 ".to_vec();
+        let tiles = [
+            (1, 0, 0, png.clone()),
+            (1, 0, 1, png.clone()),
+            (1, 1, 0, png),
+            (1, 1, 1, webp),
+        ];
+        mbt.insert_tiles(
+            &mut conn,
+            MbtType::Flat,
+            CopyDuplicateMode::Override,
+            &tiles,
+        )
+        .await
+        .unwrap();
+
+        let formats = mbt.distinct_formats(&mut conn, 100).await.unwrap();
+        assert_eq!(
+            formats,
+            HashMap::from([
+                (TileInfo::new(Format::Png, Encoding::Internal), 3),
+                (TileInfo::new(Format::Webp, Encoding::Internal), 1),
+            ])
+        );
+
+        let formats = mbt.distinct_formats(&mut conn, 2).await.unwrap();
+        assert_eq!(formats.values().sum::<usize>(), 2);
+    }
+
     #[actix_rt::test]
     async fn detect_type() {
         let script = include_str!("../../tests/fixtures/mbtiles/world_cities.sql");
@@ -794,7 +953,151 @@ pub(crate) mod tests {
 
         let (mut conn, mbt) = open(":memory:").await.unwrap();
         let res = mbt.detect_type(&mut conn).await;
-        assert!(matches!(res, Err(MbtError::InvalidDataFormat(_))));
+        assert!(matches!(
+            res,
+            Err(MbtError::UnsupportedSchema { found_tables, .. }) if found_tables.is_empty()
+        ));
+
+        let (mbt, mut conn) = anonymous_mbtiles(
+            "CREATE TABLE tiles (z INTEGER, x INTEGER, y INTEGER, data BLOB);
+             CREATE VIEW other AS SELECT * FROM tiles;",
+        )
+        .await;
+        let err = mbt.detect_type(&mut conn).await.unwrap_err();
+        assert!(
+            matches!(&err, MbtError::UnsupportedSchema { found_tables, .. } if found_tables == &["other", "tiles"]),
+            "{err}"
+        );
+        assert!(
+            err.to_string().ends_with("found tables: other, tiles"),
+            "{err}"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn has_hash_view() {
+        // flat-with-hash has a `tiles_with_hash` table, not a view
+        let script = include_str!("../../tests/fixtures/mbtiles/zoomed_world_cities.sql");
+        let (mbt, mut conn) = anonymous_mbtiles(script).await;
+        assert!(!mbt.has_hash_view(&mut conn).await.unwrap());
+
+        let script = include_str!("../../tests/fixtures/mbtiles/geography-class-jpg.sql");
+        let (mbt, mut conn) = anonymous_mbtiles(script).await;
+        assert!(!mbt.has_hash_view(&mut conn).await.unwrap());
+
+        create_tiles_with_hash_view(&mut conn).await.unwrap();
+        assert!(mbt.has_hash_view(&mut conn).await.unwrap());
+        assert_eq!(
+            mbt.detect_type(&mut conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: true,
+                schema: NormalizedSchema::Hash
+            }
+        );
+    }
+
+    #[actix_rt::test]
+    async fn detect_type_cache() {
+        let script = include_str!("../../tests/fixtures/mbtiles/geography-class-jpg.sql");
+        let file = tempfile::NamedTempFile::with_suffix(".mbtiles").unwrap();
+        let mbt = Mbtiles::new(file.path()).unwrap();
+        let mut conn = mbt.open().await.unwrap();
+        sqlx::raw_sql(script).execute(&mut conn).await.unwrap();
+        let without_view = MbtType::Normalized {
+            hash_view: false,
+            schema: NormalizedSchema::Hash,
+        };
+        let with_view = MbtType::Normalized {
+            hash_view: true,
+            schema: NormalizedSchema::Hash,
+        };
+        assert_eq!(mbt.detect_type(&mut conn).await.unwrap(), without_view);
+
+        // the cached type is shared with clones, and kept until invalidated
+        create_tiles_with_hash_view(&mut conn).await.unwrap();
+        let clone = mbt.clone();
+        assert_eq!(clone.detect_type(&mut conn).await.unwrap(), without_view);
+
+        clone.invalidate_type_cache();
+        assert_eq!(mbt.detect_type(&mut conn).await.unwrap(), with_view);
+    }
+
+    #[actix_rt::test]
+    async fn detect_type_cache_cleared_by_schema_change() {
+        let script = include_str!("../../tests/fixtures/mbtiles/geography-class-jpg.sql");
+        let file = tempfile::NamedTempFile::with_suffix(".mbtiles").unwrap();
+        let mbt = Mbtiles::new(file.path()).unwrap();
+        let mut conn = mbt.open().await.unwrap();
+        sqlx::raw_sql(script).execute(&mut conn).await.unwrap();
+        assert_eq!(
+            mbt.detect_type(&mut conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: false,
+                schema: NormalizedSchema::Hash
+            }
+        );
+
+        mbt.create_hash_view(&mut conn).await.unwrap();
+        assert_eq!(
+            mbt.detect_type(&mut conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: true,
+                schema: NormalizedSchema::Hash
+            }
+        );
+    }
+
+    #[actix_rt::test]
+    async fn detect_type_in_memory_not_cached() {
+        let script = include_str!("../../tests/fixtures/mbtiles/geography-class-jpg.sql");
+        let (mbt, mut conn) = anonymous_mbtiles(script).await;
+        assert_eq!(
+            mbt.detect_type(&mut conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: false,
+                schema: NormalizedSchema::Hash
+            }
+        );
+
+        create_tiles_with_hash_view(&mut conn).await.unwrap();
+        assert_eq!(
+            mbt.detect_type(&mut conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: true,
+                schema: NormalizedSchema::Hash
+            }
+        );
+    }
+
+    #[actix_rt::test]
+    async fn create_hash_view() {
+        let script = include_str!("../../tests/fixtures/mbtiles/geography-class-jpg.sql");
+        let (mbt, mut conn) = anonymous_mbtiles(script).await;
+        assert!(matches!(
+            mbt.detect_type(&mut conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: false,
+                ..
+            }
+        ));
+
+        mbt.create_hash_view(&mut conn).await.unwrap();
+        // creating it again is a no-op
+        mbt.create_hash_view(&mut conn).await.unwrap();
+        assert_eq!(
+            mbt.detect_type(&mut conn).await.unwrap(),
+            MbtType::Normalized {
+                hash_view: true,
+                schema: NormalizedSchema::Hash
+            }
+        );
+
+        let script = include_str!("../../tests/fixtures/mbtiles/world_cities.sql");
+        let (mbt, mut conn) = anonymous_mbtiles(script).await;
+        assert!(matches!(
+            mbt.create_hash_view(&mut conn).await,
+            Err(MbtError::NoNormalizedTables(_))
+        ));
     }
 
     #[actix_rt::test]
@@ -814,6 +1117,25 @@ pub(crate) mod tests {
         assert!(matches!(result, Err(AggHashMismatch(..))));
     }
 
+    #[actix_rt::test]
+    async fn open_verified() {
+        use crate::temp_named_mbtiles;
+
+        let script = include_str!("../../tests/fixtures/mbtiles/zoomed_world_cities.sql");
+        let (mbt, _conn, _) = temp_named_mbtiles("open_verified_valid", script).await;
+        mbt.open_verified().await.unwrap();
+
+        let script = include_str!("../../tests/fixtures/files/invalid_zoomed_world_cities.sql");
+        let (mbt, mut conn, _) = temp_named_mbtiles("open_verified_invalid", script).await;
+        let result = mbt.open_verified().await;
+        assert!(matches!(result, Err(AggHashMismatch(..))));
+
+        mbt.delete_metadata_value(&mut conn, AGG_TILES_HASH)
+            .await
+            .unwrap();
+        mbt.open_verified().await.unwrap();
+    }
+
     #[actix_rt::test]
     async fn check_tile_hash_valid_normalized_hash() {
         let script = include_str!("../../tests/fixtures/mbtiles/geography-class-png.sql");
@@ -135,7 +135,8 @@ async fn mbtiles_stream_errors() {
     {
         let mut stream = mbtiles.stream_coords(&mut conn);
         match stream.next().await {
-            Some(Err(MbtError::InvalidTileIndex(..))) => {}
+            Some(Err(MbtError::InvalidTileIndex(_, Some(z), Some(x), Some(y))))
+                if (z.as_str(), x.as_str(), y.as_str()) == ("2", "4", "0") => {}
             _ => panic!("Unexpected value returned from stream!"),
         }
     }
@@ -153,3 +154,84 @@ async fn mbtiles_stream_errors() {
         );
     }
 }
+
+#[tokio::test(flavor = "current_thread")]
+async fn mbtiles_stream_tiles_lossy() {
+    let (mbtiles, mut conn) = new(&[
+        "1, 0, 1, CAST('tl' AS BLOB)",
+        // `4` is an invalid value for `x` at `z = 2`.
+        "2, 4, 0, NULL",
+        "-1, 0, 0, NULL",
+        "1, 1, 0, CAST('br' AS BLOB)",
+    ])
+    .await;
+
+    let mut tiles: Vec<Tile> = mbtiles
+        .stream_tiles_lossy(&mut conn)
+        .try_collect()
+        .await
+        .unwrap();
+    tiles.sort_by_key(tile_key);
+    assert_eq!(
+        tiles,
+        vec![
+            (TileCoord { z: 1, x: 0, y: 0 }, Some(b"tl".to_vec())),
+            (TileCoord { z: 1, x: 1, y: 1 }, Some(b"br".to_vec())),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn mbtiles_stream_coords_for_zoom() {
+    let (mbtiles, mut conn) = new(&[
+        "0, 0, 0, NULL",
+        "1, 0, 1, NULL",
+        "1, 1, 0, NULL",
+        "2, 0, 0, NULL",
+        "3, 0, 0, NULL",
+    ])
+    .await;
+
+    let mut coords: Vec<TileCoord> = mbtiles
+        .stream_coords_for_zoom(&mut conn, 1)
+        .try_collect()
+        .await
+        .unwrap();
+    coords.sort_by_key(coord_key);
+    assert_eq!(
+        coords,
+        [
+            TileCoord { z: 1, x: 0, y: 0 },
+            TileCoord { z: 1, x: 1, y: 1 }
+        ]
+    );
+
+    let mut coords: Vec<TileCoord> = mbtiles
+        .stream_coords_for_zoom_range(&mut conn, 1, 2)
+        .try_collect()
+        .await
+        .unwrap();
+    coords.sort_by_key(coord_key);
+    assert_eq!(
+        coords,
+        [
+            TileCoord { z: 1, x: 0, y: 0 },
+            TileCoord { z: 1, x: 1, y: 1 },
+            TileCoord { z: 2, x: 0, y: 3 },
+        ]
+    );
+
+    let coords: Vec<TileCoord> = mbtiles
+        .stream_coords_for_zoom(&mut conn, 4)
+        .try_collect()
+        .await
+        .unwrap();
+    assert!(coords.is_empty());
+
+    let coords: Vec<TileCoord> = mbtiles
+        .stream_coords_for_zoom_range(&mut conn, 2, 1)
+        .try_collect()
+        .await
+        .unwrap();
+    assert!(coords.is_empty());
+}
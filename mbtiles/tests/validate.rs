@@ -123,6 +123,19 @@ async fn tile_data() {
     err!("0, 0, 0, CAST(123 AS TEXT)");
 }
 
+#[actix_rt::test]
+#[tracing_test::traced_test]
+async fn invalid_index_values() {
+    let (mbt, mut conn) = new("'a', 0.5, NULL, NULL").await;
+    let err = mbt.check_tiles_type_validity(&mut conn).await.unwrap_err();
+    let InvalidTileIndex(_, z, x, y) = &err else {
+        panic!("expected Err(InvalidTileIndex), got {err:?}");
+    };
+    assert_eq!(z.as_deref(), Some(r#""a" (TEXT)"#));
+    assert_eq!(x.as_deref(), Some("0.5 (REAL)"));
+    assert_eq!(y.as_deref(), None);
+}
+
 #[test]
 fn test_box() {
     fn tst(left: f64, bottom: f64, right: f64, top: f64, zoom: u8) -> String {